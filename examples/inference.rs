@@ -8,7 +8,7 @@ extern crate jerome;
 extern crate ndarray;
 
 use jerome as j;
-use j::ConditionalInferenceEngine;
+use j::{ConditionalInferenceEngine, Model};
 use std::collections::HashSet;
 
 fn main() -> j::Result<()> {
@@ -27,22 +27,22 @@ fn main() -> j::Result<()> {
     /////////////////////////////////////////////////////
     // Step 2: Compile some evidence
     let mut evidence = j::Assignment::new();
-    evidence.set(&difficulty, 0);
-    evidence.set(&letter, 1);
-    evidence.set(&sat, 0);
+    evidence.set_label(model.labels(), &difficulty, "easy");
+    evidence.set_label(model.labels(), &letter, "strong");
+    evidence.set_label(model.labels(), &sat, "low");
 
     /////////////////////////////////////////////////////
     // Step 3: Build an inference engine
     
     // uncomment for variable elimination
-    let mut engine = j::VariableEliminationEngine::for_directed(&model, &evidence);
+    let mut engine = j::VariableEliminationEngine::for_directed(&model, &evidence)?;
 
     // uncomment for importance sampling
     // let mut sampler = j::LikelihoodWeightedSampler::new(&model, &evidence);
     // let mut engine = j::ImportanceSamplingEngine::new(&mut sampler, 2000);
     
     // uncomment for MCMC - gibbs sampling
-    // let mut sampler = j::GibbsSampler::for_directed(&model, &evidence);
+    // let mut sampler = j::GibbsSampler::for_directed(&model, &evidence)?;
     // let burnin = 10_000;
     // let samples = 2_000;
     // let mut engine = j::McmcEngine::new(&mut sampler, burnin, samples);
@@ -55,8 +55,12 @@ fn main() -> j::Result<()> {
     let query = scope.iter().cloned().collect();
     let p = engine.infer(&query)?;
 
-    for (i, assignment) in j::all_assignments(&scope).enumerate() {
-        println!("P(I = {} | D = 0, S = 0, L = 1) = {:.4}", i, p.value(&assignment).unwrap());
+    for assignment in j::all_assignments(&scope) {
+        println!(
+            "P(I = {} | D = easy, S = low, L = strong) = {:.4}", 
+            assignment.get_label(model.labels(), &intelligence).unwrap(), 
+            p.value(&assignment).unwrap()
+        );
     }
 
     Ok(())
@@ -111,6 +115,12 @@ fn build_model(vars: StudentVariables) -> j::Result<j::DirectedModel> {
     builder = builder.with_named_variable(
         &l, "L", vec![g].into_iter().collect(), j::Initialization::Table(cpt_l)
     );
+
+    builder = builder.with_labels(&d, &["easy", "hard"])
+                     .with_labels(&i, &["low", "high"])
+                     .with_labels(&g, &["A", "B", "C"])
+                     .with_labels(&s, &["low", "high"])
+                     .with_labels(&l, &["weak", "strong"]);
     
     builder.build()
 }
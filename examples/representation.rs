@@ -68,6 +68,13 @@ fn main() -> j::Result<()> {
     builder = builder.with_named_variable(
         &letter, "L", vec![grade].into_iter().collect(), j::Initialization::Table(cpt_l)
     );
+
+    // label the values of each variable, so assignments can be printed by label
+    builder = builder.with_labels(&difficulty, &["easy", "hard"])
+                     .with_labels(&intelligence, &["low", "high"])
+                     .with_labels(&grade, &["A", "B", "C"])
+                     .with_labels(&sat, &["low", "high"])
+                     .with_labels(&letter, &["weak", "strong"]);
     
     let model = builder.build()?;
 
@@ -75,17 +82,19 @@ fn main() -> j::Result<()> {
     // Step 3: Determine Probability of Assignments
     let scope = vec![intelligence, difficulty, grade, sat, letter];
 
+    let labels = model.labels();
+
     let mut acc = 0.0;
     for assignment in j::all_assignments(&scope) {
         let p = model.probability(&assignment)?;
 
         println!(
-            "P(I = {}, D = {}, G = {}, S = {}, L = {}) = {:.4}", 
-            assignment.get(&intelligence).unwrap(),
-            assignment.get(&difficulty).unwrap(),
-            assignment.get(&grade).unwrap(),
-            assignment.get(&sat).unwrap(),
-            assignment.get(&letter).unwrap(),
+            "P(I = {:4}, D = {:4}, G = {}, S = {:4}, L = {:6}) = {:.4}", 
+            assignment.get_label(labels, &intelligence).unwrap(),
+            assignment.get_label(labels, &difficulty).unwrap(),
+            assignment.get_label(labels, &grade).unwrap(),
+            assignment.get_label(labels, &sat).unwrap(),
+            assignment.get_label(labels, &letter).unwrap(),
             p
         );
 
@@ -0,0 +1,248 @@
+//! Definition of the deterministic CPD
+//!
+//! A `Deterministic` CPD describes a `Variable` that is a function of its parents, such as a
+//! logical gate, a threshold or a lookup table. Described in Koller & Friedman Section 5.2.
+
+use factor::Factor;
+use util::{JeromeError, Result};
+use variable::{all_assignments, relabel, Variable, VariableAssignment};
+
+use itertools::Itertools;
+use ndarray::prelude as nd;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+
+/// The function of a `Deterministic` CPD, from the values of the parents (in order) to the value
+/// of the child
+pub type DeterministicFn = dyn Fn(&[usize]) -> usize + Send + Sync;
+
+
+/// A deterministic CPD, ```P(Y = f(u) | u) = 1```.
+#[derive(Clone)]
+pub struct Deterministic {
+
+    /// The discrete `Variable` described by the CPD
+    variable: Variable,
+
+    /// The discrete parents of `variable`
+    parents: Vec<Variable>,
+
+    /// The value of `variable` as a function of the values of `parents`
+    function: Arc<DeterministicFn>,
+
+    /// The CPD expanded to a table `Factor`, computed the first time it is needed
+    table: OnceLock<Factor>
+
+}
+
+
+impl Deterministic {
+
+    /// Construct a new `Deterministic` CPD.
+    ///
+    /// # Args
+    /// * `variable`: the discrete `Variable` described by the CPD
+    /// * `parents`: the discrete parents of `variable`
+    /// * `function`: the value of `variable`, given the values of `parents` in the same order
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if any `Variable` is continuous, or `parents` contains
+    ///   `variable` or a duplicate
+    /// * `JeromeError::General`, if `function` produces a value out of the range of `variable`
+    ///   for some assignment to the parents
+    pub fn new<F>(variable: Variable, parents: Vec<Variable>, function: F) -> Result<Self>
+        where F: Fn(&[usize]) -> usize + Send + Sync + 'static
+    {
+        if variable.is_continuous()
+            || parents.iter().any(|p| p.is_continuous() || *p == variable)
+            || parents.iter().unique().count() != parents.len() {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        let cpd = Deterministic { variable, parents, function: Arc::new(function), table: OnceLock::new() };
+
+        // verify the function up front, so that it cannot fail during sampling or inference
+        let out_of_range = all_assignments(&cpd.parents).any(|a| {
+            let values: Vec<usize> = cpd.parents.iter().map(|p| *a.get(p).unwrap()).collect();
+            (cpd.function)(&values) >= variable.cardinality()
+        });
+
+        if out_of_range {
+            return Err(JeromeError::General(String::from("Deterministic function is out of range of the variable")));
+        }
+
+        Ok(cpd)
+    }
+
+    /// Get the `Variable` described by the CPD
+    pub fn variable(&self) -> Variable {
+        self.variable
+    }
+
+    /// Get the parents of the `Variable`
+    pub fn parents(&self) -> &[Variable] {
+        &self.parents
+    }
+
+    /// Get the scope of the CPD. As with a table CPD, the `Variable` is last.
+    pub fn scope(&self) -> Vec<Variable> {
+        let mut scope = self.parents.clone();
+        scope.push(self.variable);
+        scope
+    }
+
+    /// Compute the value of the `Variable` given an assignment to its parents
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if a parent is not assigned
+    pub fn evaluate<A: VariableAssignment>(&self, assignment: &A) -> Result<usize> {
+        let values: Option<Vec<usize>> = self.parents.iter().map(|p| assignment.value_of(p)).collect();
+        values.map(|values| (self.function)(&values)).ok_or(JeromeError::IncompleteAssignment)
+    }
+
+    /// Compute the probability of a full assignment to the scope of the CPD, which is either 0 or
+    /// 1
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if the assignment is not complete over the scope
+    pub fn value<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        let y = assignment.value_of(&self.variable).ok_or(JeromeError::IncompleteAssignment)?;
+        self.evaluate(assignment).map(|v| if v == y { 1.0 } else { 0.0 })
+    }
+
+    /// Compute the natural logarithm of the probability of a full assignment to the scope of the
+    /// CPD
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if the assignment is not complete over the scope
+    pub fn log_value<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        self.value(assignment).map(f64::ln)
+    }
+
+    /// Sample the `Variable` given an assignment to its parents. This is exact - the sample is
+    /// always the value of the function.
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if a parent is not assigned
+    pub fn sample_cpd<A: VariableAssignment>(&self, assignment: &A) -> Result<usize> {
+        self.evaluate(assignment)
+    }
+
+    /// Reduce the CPD by the observed parents in `assignment`, fixing their values in the
+    /// function. Any value of the `Variable` itself is ignored.
+    pub fn reduce<A: VariableAssignment>(&self, assignment: &A) -> Self {
+        let observed: Vec<Option<usize>> = self.parents.iter().map(|p| assignment.value_of(p)).collect();
+        let parents: Vec<Variable> = self.parents
+                                         .iter()
+                                         .zip(observed.iter())
+                                         .filter(|&(_, o)| o.is_none())
+                                         .map(|(&p, _)| p)
+                                         .collect();
+
+        let function = self.function.clone();
+        let reduced = move |values: &[usize]| {
+            // interleave the remaining values with the observed ones
+            let mut remaining = values.iter();
+            let full: Vec<usize> = observed.iter().map(|o| o.unwrap_or_else(|| *remaining.next().unwrap())).collect();
+            function(&full)
+        };
+
+        Deterministic { variable: self.variable, parents, function: Arc::new(reduced), table: OnceLock::new() }
+    }
+
+    /// Replace the `Variable`s of the CPD by their images in `map`; see `Factor::relabel`
+    pub fn relabel(&self, map: &HashMap<Variable, Variable>) -> Result<Self> {
+        let mut parents = relabel(&self.scope(), map)?;
+        let variable = parents.pop().unwrap();
+
+        Ok(Deterministic { variable, parents, function: self.function.clone(), table: OnceLock::new() })
+    }
+
+    /// Get the CPD as a table `Factor` over ```parents + variable```, in which every entry is 0 or
+    /// 1. The table is only built the first time it is requested.
+    pub fn as_factor(&self) -> &Factor {
+        self.table.get_or_init(|| {
+            let n = self.parents.len();
+            let shape: Vec<usize> = self.scope().iter().map(|v| v.cardinality()).collect();
+
+            let table = nd::ArrayD::from_shape_fn(shape, |idx| {
+                let values: Vec<usize> = (0..n).map(|i| idx[i]).collect();
+                if (self.function)(&values) == idx[n] { 1.0 } else { 0.0 }
+            });
+
+            Factor::cpd(self.variable, self.parents.clone(), table).expect("expansion encountered unexpected error")
+        })
+    }
+}
+
+
+impl fmt::Debug for Deterministic {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Deterministic")
+         .field("variable", &self.variable)
+         .field("parents", &self.parents)
+         .finish()
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use variable::Assignment;
+
+    #[test]
+    fn deterministic() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+        let y = Variable::binary();
+        let sum = Variable::discrete(3);
+
+        assert!(Deterministic::new(y, vec![a, b], |u: &[usize]| u[0] + u[1]).is_err());
+        assert!(Deterministic::new(y, vec![a, y], |u: &[usize]| u[0]).is_err());
+
+        let xor = Deterministic::new(y, vec![a, b], |u: &[usize]| u[0] ^ u[1]).unwrap();
+        let adder = Deterministic::new(sum, vec![a, b], |u: &[usize]| u[0] + u[1]).unwrap();
+
+        let mut x = Assignment::new();
+        x.set(&a, 1);
+        assert!(xor.evaluate(&x).is_err());
+
+        x.set(&b, 1);
+        assert_eq!(0, xor.evaluate(&x).unwrap());
+        assert_eq!(0, xor.sample_cpd(&x).unwrap());
+        assert_eq!(2, adder.sample_cpd(&x).unwrap());
+
+        x.set(&y, 1);
+        assert_eq!(0.0, xor.value(&x).unwrap());
+        assert_eq!(f64::NEG_INFINITY, xor.log_value(&x).unwrap());
+
+        x.set(&y, 0);
+        assert_eq!(1.0, xor.value(&x).unwrap());
+
+        // the table of a deterministic CPD is a CPD, despite its zeros
+        let f = xor.as_factor();
+        assert!(f.is_cpd());
+        for x in all_assignments(&xor.scope()) {
+            assert_eq!(xor.value(&x).unwrap(), f.value(&x).unwrap());
+        }
+
+        // reducing by B = 1 leaves Y = NOT A
+        let mut e = Assignment::new();
+        e.set(&b, 1);
+        let not = xor.reduce(&e);
+        assert_eq!(vec![a, y], not.scope());
+
+        let mut x = Assignment::new();
+        x.set(&a, 0);
+        assert_eq!(1, not.evaluate(&x).unwrap());
+        x.set(&a, 1);
+        assert_eq!(0, not.evaluate(&x).unwrap());
+    }
+}
@@ -0,0 +1,135 @@
+//! Defines the CPDs of discrete `Variable`s in a `DirectedModel`
+//!
+//! A table `Factor` has an entry for every assignment to a `Variable` and its parents, and so
+//! grows exponentially with the number of parents. The structured CPDs in this module describe
+//! the same distributions with far fewer parameters. They can be expanded to a table `Factor` on
+//! demand, but the samplers and inference engines work with them directly where they can.
+
+mod deterministic;
+mod noisy;
+mod tree;
+
+pub use self::deterministic::{Deterministic, DeterministicFn};
+pub use self::noisy::NoisyMax;
+pub use self::tree::{TreeCpd, TreeNode};
+
+use factor::Factor;
+use variable::{Variable, VariableAssignment};
+use util::Result;
+
+use std::collections::HashMap;
+
+
+/// The CPD of a discrete `Variable` in a `DirectedModel`
+#[derive(Clone, Debug)]
+pub enum DiscreteCpd {
+    /// A conditional probability table
+    Table(Factor),
+
+    /// A noisy-MAX (or noisy-OR) CPD
+    NoisyMax(NoisyMax),
+
+    /// A tree-structured CPD
+    Tree(TreeCpd),
+
+    /// A deterministic function of the parents
+    Deterministic(Deterministic)
+}
+
+
+impl DiscreteCpd {
+
+    /// Get the scope of the CPD. The `Variable` described by the CPD is last.
+    pub fn scope(&self) -> Vec<Variable> {
+        match *self {
+            DiscreteCpd::Table(ref f) => f.scope(),
+            DiscreteCpd::NoisyMax(ref cpd) => cpd.scope(),
+            DiscreteCpd::Tree(ref cpd) => cpd.scope(),
+            DiscreteCpd::Deterministic(ref cpd) => cpd.scope()
+        }
+    }
+
+    /// Compute the probability of a full assignment to the scope of the CPD
+    pub fn value<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        match *self {
+            DiscreteCpd::Table(ref f) => f.value(assignment),
+            DiscreteCpd::NoisyMax(ref cpd) => cpd.value(assignment),
+            DiscreteCpd::Tree(ref cpd) => cpd.value(assignment),
+            DiscreteCpd::Deterministic(ref cpd) => cpd.value(assignment)
+        }
+    }
+
+    /// Compute the natural logarithm of the probability of a full assignment to the scope of the
+    /// CPD
+    pub fn log_value<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        match *self {
+            DiscreteCpd::Table(ref f) => f.log_value(assignment),
+            DiscreteCpd::NoisyMax(ref cpd) => cpd.log_value(assignment),
+            DiscreteCpd::Tree(ref cpd) => cpd.log_value(assignment),
+            DiscreteCpd::Deterministic(ref cpd) => cpd.log_value(assignment)
+        }
+    }
+
+    /// Randomly sample the `Variable` described by the CPD, given a full assignment to its
+    /// parents
+    pub fn sample_cpd<A: VariableAssignment>(&self, assignment: &A) -> Result<usize> {
+        match *self {
+            DiscreteCpd::Table(ref f) => f.sample_cpd(assignment),
+            DiscreteCpd::NoisyMax(ref cpd) => cpd.sample_cpd(assignment),
+            DiscreteCpd::Tree(ref cpd) => cpd.sample_cpd(assignment),
+            DiscreteCpd::Deterministic(ref cpd) => cpd.sample_cpd(assignment)
+        }
+    }
+
+    /// Reduce the CPD by the observed parents in `assignment`
+    pub fn reduce<A: VariableAssignment>(&self, assignment: &A) -> Self {
+        match *self {
+            DiscreteCpd::Table(ref f) => DiscreteCpd::Table(f.reduce(assignment)),
+            DiscreteCpd::NoisyMax(ref cpd) => DiscreteCpd::NoisyMax(cpd.reduce(assignment)),
+            DiscreteCpd::Tree(ref cpd) => DiscreteCpd::Tree(cpd.reduce(assignment)),
+            DiscreteCpd::Deterministic(ref cpd) => DiscreteCpd::Deterministic(cpd.reduce(assignment))
+        }
+    }
+
+    /// Replace the `Variable`s of the CPD by their images in `map`, keeping its parameters
+    ///
+    /// # Errors
+    /// `JeromeError::InvalidScope` if an image has a different cardinality than its `Variable`, or
+    /// the new scope contains a duplicate
+    pub fn relabel(&self, map: &HashMap<Variable, Variable>) -> Result<Self> {
+        match *self {
+            DiscreteCpd::Table(ref f) => f.relabel(map).map(DiscreteCpd::Table),
+            DiscreteCpd::NoisyMax(ref cpd) => cpd.relabel(map).map(DiscreteCpd::NoisyMax),
+            DiscreteCpd::Tree(ref cpd) => cpd.relabel(map).map(DiscreteCpd::Tree),
+            DiscreteCpd::Deterministic(ref cpd) => cpd.relabel(map).map(DiscreteCpd::Deterministic)
+        }
+    }
+
+    /// Get the CPD as a table `Factor`, expanding it if necessary
+    pub fn as_factor(&self) -> &Factor {
+        match *self {
+            DiscreteCpd::Table(ref f) => f,
+            DiscreteCpd::NoisyMax(ref cpd) => cpd.as_factor(),
+            DiscreteCpd::Tree(ref cpd) => cpd.as_factor(),
+            DiscreteCpd::Deterministic(ref cpd) => cpd.as_factor()
+        }
+    }
+
+    /// Decompose the CPD into small `Factor`s whose product, summed over the `auxiliary`
+    /// `Variable`s, is the CPD. A table CPD is its own decomposition, and other CPDs are
+    /// expanded.
+    pub fn decompose(&self) -> Vec<Factor> {
+        match *self {
+            DiscreteCpd::NoisyMax(ref cpd) => cpd.decompose(),
+            ref cpd => vec![cpd.as_factor().clone()]
+        }
+    }
+
+    /// Get the `Variable`s introduced by `DiscreteCpd::decompose`
+    pub fn auxiliary(&self) -> &[Variable] {
+        match *self {
+            DiscreteCpd::NoisyMax(ref cpd) => cpd.auxiliary(),
+            _ => &[]
+        }
+    }
+}
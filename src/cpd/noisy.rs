@@ -0,0 +1,483 @@
+//! Definition of the noisy-MAX CPD
+//!
+//! A `NoisyMax` is a model of causal independence, described in Koller & Friedman Section 5.4.
+//! Each parent independently 'proposes' a value for the child, as does a leak that stands in for
+//! every cause not in the model. The child takes the largest proposed value. Noisy-OR is the
+//! special case in which the child and all of its parents are binary.
+
+use factor::Factor;
+use util::{JeromeError, Result};
+use variable::{relabel, Variable, VariableAssignment};
+
+use itertools::Itertools;
+use ndarray::prelude as nd;
+use rand;
+use rand::distributions::{IndependentSample, Range};
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+
+/// A noisy-MAX CPD, ```Y = max(Z_0, Z_1, ..., Z_k)```, where ```Z_0``` is the leak and ```Z_i```
+/// is the value proposed by the parent ```X_i```.
+///
+/// Defined in Koller & Friedman Section 5.4.1 and 5.4.2. The CPD needs one distribution over the
+/// child per (non-zero) parent value, rather than one per assignment to all of the parents.
+#[derive(Clone, Debug)]
+pub struct NoisyMax {
+
+    /// The discrete `Variable` described by the CPD
+    variable: Variable,
+
+    /// The discrete parents of `variable`
+    parents: Vec<Variable>,
+
+    /// ```effects[i][u - 1]``` is the distribution of the value proposed by ```parents[i]``` when
+    /// it takes the value ```u```. A parent with value 0 is inactive, and always proposes 0.
+    effects: Vec<Vec<Vec<f64>>>,
+
+    /// The distribution of the value proposed by the leak
+    leak: Vec<f64>,
+
+    /// The intermediate `Variable`s of the causal independence decomposition, one fewer than the
+    /// parents. ```auxiliary[i]``` is the max of the leak and the first ```i + 1``` parents.
+    auxiliary: Vec<Variable>,
+
+    /// The CPD expanded to a table `Factor`, computed the first time it is needed
+    table: OnceLock<Factor>
+
+}
+
+
+impl NoisyMax {
+
+    /// Construct a new `NoisyMax` CPD.
+    ///
+    /// # Args
+    /// * `variable`: the discrete `Variable` described by the CPD
+    /// * `parents`: the discrete parents of `variable`
+    /// * `effects`: for each parent, in the same order as `parents`, the distribution over the
+    ///   values of `variable` proposed by each non-zero value of that parent
+    /// * `leak`: the distribution over the values of `variable` proposed by the leak
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if any `Variable` is continuous, `parents` contains
+    ///   `variable` or a duplicate, or `effects` does not match the cardinalities of the
+    ///   `Variable`s
+    /// * `JeromeError::NonPositiveProbability`, if any probability is negative
+    /// * `JeromeError::NotACPD`, if any distribution does not sum to one
+    pub fn new(
+        variable: Variable,
+        parents: Vec<Variable>,
+        effects: Vec<Vec<Vec<f64>>>,
+        leak: Vec<f64>
+    ) -> Result<Self> {
+        let card = variable.cardinality();
+
+        if variable.is_continuous()
+            || parents.iter().any(|p| p.is_continuous() || *p == variable)
+            || parents.iter().unique().count() != parents.len()
+            || effects.len() != parents.len()
+            || parents.iter().zip(effects.iter()).any(|(p, e)| e.len() + 1 != p.cardinality())
+            || effects.iter().flat_map(|e| e.iter()).chain(Some(&leak)).any(|d| d.len() != card) {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        for d in effects.iter().flat_map(|e| e.iter()).chain(Some(&leak)) {
+            if d.iter().any(|&p| p.is_nan() || p < 0.0) {
+                return Err(JeromeError::NonPositiveProbability);
+            }
+
+            // the same tolerance as a table CPD
+            if (d.iter().sum::<f64>() - 1.0).abs() >= 0.0001 {
+                return Err(JeromeError::NotACPD);
+            }
+        }
+
+        let auxiliary = (1..parents.len()).map(|_| Variable::discrete(card)).collect();
+
+        Ok(NoisyMax { variable, parents, effects, leak, auxiliary, table: OnceLock::new() })
+    }
+
+    /// Construct a noisy-OR CPD for a binary `Variable` with binary parents.
+    ///
+    /// The `Variable` is 1 unless every active parent, and the leak, fails to activate it:
+    /// ```P(Y = 0 | x) = (1 - leak) * prod_{i : x_i = 1} inhibitions[i]```
+    ///
+    /// # Args
+    /// * `variable`: the binary `Variable` described by the CPD
+    /// * `parents`: the binary parents of `variable`
+    /// * `inhibitions`: the probability that each parent, when active, fails to activate
+    ///   `variable`
+    /// * `leak`: the probability that `variable` is active when no parent is
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if any `Variable` is not binary, or there is not exactly one
+    ///   inhibition probability per parent
+    /// * `JeromeError::NonPositiveProbability`, if any probability is not between 0 and 1
+    pub fn noisy_or(variable: Variable, parents: Vec<Variable>, inhibitions: Vec<f64>, leak: f64) -> Result<Self> {
+        if variable.cardinality() != 2 || parents.iter().any(|p| p.cardinality() != 2) {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        let effects = inhibitions.iter().map(|&q| vec![vec![q, 1.0 - q]]).collect();
+
+        NoisyMax::new(variable, parents, effects, vec![1.0 - leak, leak])
+    }
+
+    /// Get the `Variable` described by the CPD
+    pub fn variable(&self) -> Variable {
+        self.variable
+    }
+
+    /// Get the parents of the `Variable`
+    pub fn parents(&self) -> &[Variable] {
+        &self.parents
+    }
+
+    /// Get the distribution proposed by each non-zero value of each parent
+    pub fn effects(&self) -> &[Vec<Vec<f64>>] {
+        &self.effects
+    }
+
+    /// Get the distribution proposed by the leak
+    pub fn leak(&self) -> &[f64] {
+        &self.leak
+    }
+
+    /// Get the intermediate `Variable`s introduced by `NoisyMax::decompose`
+    pub fn auxiliary(&self) -> &[Variable] {
+        &self.auxiliary
+    }
+
+    /// Get the scope of the CPD. As with a table CPD, the `Variable` is last.
+    pub fn scope(&self) -> Vec<Variable> {
+        let mut scope = self.parents.clone();
+        scope.push(self.variable);
+        scope
+    }
+
+    /// Compute the distribution over the `Variable` given an assignment to its parents
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if a parent is not assigned
+    pub fn distribution<A: VariableAssignment>(&self, assignment: &A) -> Result<Vec<f64>> {
+        self.cumulative(assignment).map(|cdf| difference(&cdf))
+    }
+
+    /// Compute the probability of a full assignment to the scope of the CPD
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if the assignment is not complete over the scope
+    pub fn value<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        let y = assignment.value_of(&self.variable).ok_or(JeromeError::IncompleteAssignment)?;
+        self.distribution(assignment).map(|d| d[y])
+    }
+
+    /// Compute the natural logarithm of the probability of a full assignment to the scope of the
+    /// CPD
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if the assignment is not complete over the scope
+    pub fn log_value<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        self.value(assignment).map(f64::ln)
+    }
+
+    /// Randomly sample a value of the `Variable` given an assignment to its parents
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if a parent is not assigned
+    pub fn sample_cpd<A: VariableAssignment>(&self, assignment: &A) -> Result<usize> {
+        let cdf = self.cumulative(assignment)?;
+
+        let between = Range::new(0.0, 1.0);
+        let val = between.ind_sample(&mut rand::thread_rng());
+
+        Ok(cdf.iter().position(|&c| val < c).unwrap_or(cdf.len() - 1))
+    }
+
+    /// Reduce the CPD by the observed parents in `assignment`. Each observed parent always
+    /// proposes the same distribution, so it is folded into the leak. Any value of the `Variable`
+    /// itself is ignored.
+    pub fn reduce<A: VariableAssignment>(&self, assignment: &A) -> Self {
+        let mut leak = cumulative(&self.leak);
+        let mut parents = Vec::new();
+        let mut effects = Vec::new();
+
+        for (p, effect) in self.parents.iter().zip(self.effects.iter()) {
+            match assignment.value_of(p) {
+                Some(0) => (),
+                Some(u) => {
+                    for (l, e) in leak.iter_mut().zip(cumulative(&effect[u - 1])) {
+                        *l *= e;
+                    }
+                },
+                None => {
+                    parents.push(*p);
+                    effects.push(effect.clone());
+                }
+            }
+        }
+
+        NoisyMax::new(self.variable, parents, effects, difference(&leak)).expect("reduce encountered unexpected error")
+    }
+
+    /// Replace the `Variable`s of the CPD by their images in `map`; see `Factor::relabel`. The
+    /// auxiliary `Variable`s of the decomposition are created anew.
+    pub fn relabel(&self, map: &HashMap<Variable, Variable>) -> Result<Self> {
+        let mut scope = relabel(&self.scope(), map)?;
+        let variable = scope.pop().unwrap();
+
+        NoisyMax::new(variable, scope, self.effects.clone(), self.leak.clone())
+    }
+
+    /// Get the CPD as a table `Factor` over ```parents + variable```. The table has one entry per
+    /// assignment to the scope, so it is only built the first time it is requested.
+    pub fn as_factor(&self) -> &Factor {
+        self.table.get_or_init(|| {
+            let n = self.parents.len();
+            let shape: Vec<usize> = self.scope().iter().map(|v| v.cardinality()).collect();
+
+            let table = nd::ArrayD::from_shape_fn(shape, |idx| {
+                let values: Vec<usize> = (0..n).map(|i| idx[i]).collect();
+                difference(&self.cumulative_at(&values))[idx[n]]
+            });
+
+            Factor::cpd(self.variable, self.parents.clone(), table).expect("expansion encountered unexpected error")
+        })
+    }
+
+    /// Decompose the CPD into `Factor`s over at most three `Variable`s each, whose product,
+    /// summed over the `auxiliary` `Variable`s, is the CPD.
+    ///
+    /// This is the causal independence decomposition of Koller & Friedman Section 9.6.1: the
+    /// max is computed one parent at a time, ```O_i = max(O_{i - 1}, Z_i)```, with the proposal
+    /// ```Z_i``` of each parent summed out in advance. The size of the `Factor`s grows linearly,
+    /// rather than exponentially, in the number of parents.
+    pub fn decompose(&self) -> Vec<Factor> {
+        if self.parents.is_empty() {
+            return vec![self.as_factor().clone()];
+        }
+
+        let card = self.variable.cardinality();
+        let outputs: Vec<Variable> = self.auxiliary.iter().cloned().chain(Some(self.variable)).collect();
+
+        let leak = cumulative(&self.leak);
+        let mut factors = Vec::new();
+
+        for (i, (&p, &out)) in self.parents.iter().zip(outputs.iter()).enumerate() {
+            let factor = if i == 0 {
+                // O_0 = max(Z_leak, Z_0)
+                let table = nd::Array2::from_shape_fn((p.cardinality(), card), |(u, o)| {
+                    let cdf: Vec<f64> = leak.iter().zip(self.proposal(i, u)).map(|(l, z)| l * z).collect();
+                    difference(&cdf)[o]
+                });
+
+                Factor::new(vec![p, out], table.into_dyn())
+            } else {
+                // O_i = max(O_{i - 1}, Z_i)
+                let table = nd::Array3::from_shape_fn((card, p.cardinality(), card), |(prev, u, o)| {
+                    let cdf = self.proposal(i, u);
+                    if o < prev {
+                        0.0
+                    } else if o == prev {
+                        cdf[o]
+                    } else {
+                        cdf[o] - cdf[o - 1]
+                    }
+                });
+
+                Factor::new(vec![outputs[i - 1], p, out], table.into_dyn())
+            };
+
+            factors.push(factor.expect("decompose encountered unexpected error"));
+        }
+
+        factors
+    }
+
+    /// The cumulative distribution of the value proposed by ```parents[i]``` at value ```u```
+    fn proposal(&self, i: usize, u: usize) -> Vec<f64> {
+        if u == 0 {
+            vec![1.0; self.variable.cardinality()]
+        } else {
+            cumulative(&self.effects[i][u - 1])
+        }
+    }
+
+    /// The cumulative distribution of the `Variable` given an assignment to its parents
+    fn cumulative<A: VariableAssignment>(&self, assignment: &A) -> Result<Vec<f64>> {
+        let values: Option<Vec<usize>> = self.parents.iter().map(|p| assignment.value_of(p)).collect();
+        values.map(|values| self.cumulative_at(&values)).ok_or(JeromeError::IncompleteAssignment)
+    }
+
+    /// The cumulative distribution of the `Variable` given the value of each parent. The child
+    /// is at most ```y``` only if every proposal is at most ```y```, and the proposals are
+    /// independent.
+    fn cumulative_at(&self, values: &[usize]) -> Vec<f64> {
+        values.iter()
+              .enumerate()
+              .fold(cumulative(&self.leak), |cdf, (i, &u)| {
+                  cdf.iter().zip(self.proposal(i, u)).map(|(c, z)| c * z).collect()
+              })
+    }
+}
+
+
+/// The cumulative distribution of a distribution
+fn cumulative(pmf: &[f64]) -> Vec<f64> {
+    pmf.iter()
+       .scan(0.0, |sum, &p| { *sum += p; Some(*sum) })
+       .collect()
+}
+
+
+/// The distribution of a cumulative distribution
+fn difference(cdf: &[f64]) -> Vec<f64> {
+    cdf.iter()
+       .enumerate()
+       .map(|(i, &c)| if i == 0 { c } else { c - cdf[i - 1] })
+       .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use variable::{all_assignments, Assignment};
+
+    fn assert_close(expected: f64, actual: f64) {
+        assert!((expected - actual).abs() < 1e-9, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn noisy_or() {
+        let x1 = Variable::binary();
+        let x2 = Variable::binary();
+        let y = Variable::binary();
+        let z = Variable::discrete(3);
+
+        assert!(NoisyMax::noisy_or(y, vec![x1, x2], vec![0.2], 0.1).is_err());
+        assert!(NoisyMax::noisy_or(y, vec![x1, z], vec![0.2, 0.5], 0.1).is_err());
+        assert!(NoisyMax::noisy_or(y, vec![x1, x2], vec![-0.1, 0.5], 0.1).is_err());
+        assert!(NoisyMax::noisy_or(y, vec![x1, x2], vec![0.2, 0.5], 1.5).is_err());
+
+        // without a leak, Y is 0 when no parent is active
+        let strict = NoisyMax::noisy_or(y, vec![x1, x2], vec![0.2, 0.5], 0.0).unwrap();
+        let mut off = Assignment::new();
+        off.set(&x1, 0);
+        off.set(&x2, 0);
+        off.set(&y, 1);
+        assert_eq!(0.0, strict.value(&off).unwrap());
+        assert_eq!(0.0, strict.as_factor().value(&off).unwrap());
+        assert!(NoisyMax::noisy_or(y, vec![x1, y], vec![0.2, 0.5], 0.1).is_err());
+
+        let cpd = NoisyMax::noisy_or(y, vec![x1, x2], vec![0.2, 0.5], 0.1).unwrap();
+        assert_eq!(vec![x1, x2, y], cpd.scope());
+        assert!(cpd.auxiliary().len() == 1);
+
+        let mut a = Assignment::new();
+        a.set(&x1, 1);
+        assert!(cpd.value(&a).is_err());
+
+        a.set(&x2, 0);
+        a.set(&y, 0);
+        assert_close(0.9 * 0.2, cpd.value(&a).unwrap());
+
+        a.set(&x2, 1);
+        assert_close(0.9 * 0.2 * 0.5, cpd.value(&a).unwrap());
+
+        a.set(&y, 1);
+        assert_close(1.0 - 0.9 * 0.2 * 0.5, cpd.value(&a).unwrap());
+
+        let f = cpd.as_factor();
+        assert!(f.is_cpd());
+        for a in all_assignments(&cpd.scope()) {
+            assert_close(cpd.value(&a).unwrap(), f.value(&a).unwrap());
+        }
+    }
+
+    #[test]
+    fn noisy_max() {
+        let x1 = Variable::discrete(3);
+        let x2 = Variable::binary();
+        let x3 = Variable::discrete(3);
+        let y = Variable::discrete(3);
+
+        let effects = vec![
+            vec![vec![0.5, 0.3, 0.2], vec![0.2, 0.3, 0.5]],
+            vec![vec![0.6, 0.3, 0.1]],
+            vec![vec![0.7, 0.2, 0.1], vec![0.1, 0.1, 0.8]]
+        ];
+        let leak = vec![0.8, 0.15, 0.05];
+
+        assert!(NoisyMax::new(y, vec![x1, x2], effects.clone(), leak.clone()).is_err());
+        assert!(NoisyMax::new(y, vec![x1, x2, x3], effects.clone(), vec![0.8, 0.2]).is_err());
+        assert!(NoisyMax::new(y, vec![x1, x2, x3], effects.clone(), vec![0.8, 0.15, 0.15]).is_err());
+
+        let cpd = NoisyMax::new(y, vec![x1, x2, x3], effects, leak).unwrap();
+
+        // P(Y <= 1 | x1 = 2, x2 = 0, x3 = 1) = (0.8 + 0.15) * (0.2 + 0.3) * (0.7 + 0.2)
+        let mut a = Assignment::new();
+        a.set(&x1, 2);
+        a.set(&x2, 0);
+        a.set(&x3, 1);
+        let d = cpd.distribution(&a).unwrap();
+        assert_close(0.8 * 0.2 * 0.7, d[0]);
+        assert_close(0.95 * 0.5 * 0.9, d[0] + d[1]);
+        assert_close(1.0, d.iter().sum());
+
+        // the decomposition, summed over the auxiliary variables, is the CPD
+        let product = cpd.decompose()
+                         .iter()
+                         .fold(Factor::identity(), |acc, f| acc.product(f).unwrap());
+        assert!(cpd.decompose().iter().all(|f| f.scope().len() <= 3));
+
+        let joint = cpd.auxiliary().iter().fold(product, |acc, &v| acc.marginalize(v));
+        for a in all_assignments(&cpd.scope()) {
+            assert_close(cpd.as_factor().value(&a).unwrap(), joint.value(&a).unwrap());
+        }
+    }
+
+    #[test]
+    fn reduce() {
+        let x1 = Variable::discrete(3);
+        let x2 = Variable::binary();
+        let y = Variable::discrete(3);
+
+        let effects = vec![
+            vec![vec![0.5, 0.3, 0.2], vec![0.2, 0.3, 0.5]],
+            vec![vec![0.6, 0.3, 0.1]]
+        ];
+        let cpd = NoisyMax::new(y, vec![x1, x2], effects, vec![0.8, 0.15, 0.05]).unwrap();
+
+        for u in 0..3 {
+            let mut e = Assignment::new();
+            e.set(&x1, u);
+
+            let reduced = cpd.reduce(&e);
+            assert_eq!(vec![x2, y], reduced.scope());
+
+            let expected = cpd.as_factor().reduce(&e);
+            for a in all_assignments(&reduced.scope()) {
+                assert_close(expected.value(&a).unwrap(), reduced.value(&a).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn sample() {
+        let x = Variable::binary();
+        let y = Variable::binary();
+        let cpd = NoisyMax::noisy_or(y, vec![x], vec![0.25], 0.2).unwrap();
+
+        let mut a = Assignment::new();
+        a.set(&x, 1);
+
+        let n = 10000;
+        let ones = (0..n).filter(|_| cpd.sample_cpd(&a).unwrap() == 1).count();
+        // P(Y = 1 | X = 1) = 1 - 0.8 * 0.25 = 0.8
+        assert!((ones as f64 / n as f64 - 0.8).abs() < 0.03);
+    }
+}
@@ -0,0 +1,427 @@
+//! Definition of the tree CPD
+//!
+//! A `TreeCpd` represents context-specific independence, described in Koller & Friedman Section
+//! 5.3.1. Each internal node of the tree splits on the value of a parent, and each leaf holds the
+//! distribution over the child in every context that reaches it. Contexts in which some parents
+//! are irrelevant share a single leaf, rather than repeating the distribution in a table.
+
+use factor::Factor;
+use util::{JeromeError, Result};
+use variable::{relabel, Variable, VariableAssignment};
+
+use itertools::Itertools;
+use ndarray::prelude as nd;
+use rand;
+use rand::distributions::{IndependentSample, Range};
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+
+/// A node of a `TreeCpd`
+#[derive(Clone, Debug)]
+pub enum TreeNode {
+    /// A distribution over the values of the child
+    Leaf(Vec<f64>),
+
+    /// A split on a parent, with one subtree for each value of the parent
+    Split(Variable, Vec<TreeNode>)
+}
+
+
+/// A tree-structured CPD.
+///
+/// Defined in Koller & Friedman Definition 5.6. The leaves are numbered in depth-first order,
+/// visiting the subtrees of a split in order of the value of the parent.
+#[derive(Clone, Debug)]
+pub struct TreeCpd {
+
+    /// The discrete `Variable` described by the CPD
+    variable: Variable,
+
+    /// The discrete parents of `variable`
+    parents: Vec<Variable>,
+
+    /// The root of the tree
+    root: TreeNode,
+
+    /// The CPD expanded to a table `Factor`, computed the first time it is needed
+    table: OnceLock<Factor>
+
+}
+
+
+impl TreeCpd {
+
+    /// Construct a new `TreeCpd`.
+    ///
+    /// # Args
+    /// * `variable`: the discrete `Variable` described by the CPD
+    /// * `parents`: the discrete parents of `variable`. A parent need not appear in the tree, in
+    ///   which case `variable` is independent of it.
+    /// * `root`: the root of the tree
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if any `Variable` is continuous, `parents` contains
+    ///   `variable` or a duplicate, the tree splits on a `Variable` that is not a parent or splits
+    ///   on a parent twice along a path, or the tree does not match the cardinalities of the
+    ///   `Variable`s
+    /// * `JeromeError::NonPositiveProbability`, if any probability is negative
+    /// * `JeromeError::NotACPD`, if any leaf does not sum to one
+    pub fn new(variable: Variable, parents: Vec<Variable>, root: TreeNode) -> Result<Self> {
+        if variable.is_continuous()
+            || parents.iter().any(|p| p.is_continuous() || *p == variable)
+            || parents.iter().unique().count() != parents.len() {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        check(&root, variable.cardinality(), &parents, &mut Vec::new())?;
+
+        Ok(TreeCpd { variable, parents, root, table: OnceLock::new() })
+    }
+
+    /// Get the `Variable` described by the CPD
+    pub fn variable(&self) -> Variable {
+        self.variable
+    }
+
+    /// Get the parents of the `Variable`
+    pub fn parents(&self) -> &[Variable] {
+        &self.parents
+    }
+
+    /// Get the root of the tree
+    pub fn root(&self) -> &TreeNode {
+        &self.root
+    }
+
+    /// Get the scope of the CPD. As with a table CPD, the `Variable` is last.
+    pub fn scope(&self) -> Vec<Variable> {
+        let mut scope = self.parents.clone();
+        scope.push(self.variable);
+        scope
+    }
+
+    /// Get the distribution at each leaf, in depth-first order
+    pub fn leaves(&self) -> Vec<&[f64]> {
+        let mut leaves = Vec::new();
+        collect_leaves(&self.root, &mut leaves);
+        leaves
+    }
+
+    /// Construct a `TreeCpd` with the same structure, but with new distributions at the leaves.
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if there is not exactly one distribution per leaf
+    /// * see `TreeCpd::new`
+    pub fn with_leaves(&self, leaves: Vec<Vec<f64>>) -> Result<Self> {
+        if leaves.len() != self.leaves().len() {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        let mut leaves = leaves.into_iter();
+        let root = replace_leaves(&self.root, &mut leaves);
+
+        TreeCpd::new(self.variable, self.parents.clone(), root)
+    }
+
+    /// Find the leaf reached by an assignment to the parents.
+    ///
+    /// # Returns
+    /// the position of the leaf in depth-first order, and its distribution
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if a parent split on along the path is not assigned
+    pub fn leaf<A: VariableAssignment>(&self, assignment: &A) -> Result<(usize, &[f64])> {
+        find_leaf(&self.root, |v| assignment.value_of(v))
+    }
+
+    /// Compute the probability of a full assignment to the scope of the CPD
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if the assignment is not complete over the scope
+    pub fn value<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        let y = assignment.value_of(&self.variable).ok_or(JeromeError::IncompleteAssignment)?;
+        self.leaf(assignment).map(|(_, d)| d[y])
+    }
+
+    /// Compute the natural logarithm of the probability of a full assignment to the scope of the
+    /// CPD
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if the assignment is not complete over the scope
+    pub fn log_value<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        self.value(assignment).map(f64::ln)
+    }
+
+    /// Randomly sample a value of the `Variable` given an assignment to its parents. Only the
+    /// parents along the path to the leaf need be assigned.
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if a parent split on along the path is not assigned
+    pub fn sample_cpd<A: VariableAssignment>(&self, assignment: &A) -> Result<usize> {
+        let (_, d) = self.leaf(assignment)?;
+
+        let between = Range::new(0.0, 1.0);
+        let val = between.ind_sample(&mut rand::thread_rng());
+
+        let mut sum = 0.0;
+        for (i, p) in d.iter().enumerate() {
+            sum += p;
+            if val < sum {
+                return Ok(i);
+            }
+        }
+
+        Ok(d.len() - 1)
+    }
+
+    /// Reduce the CPD by the observed parents in `assignment`, replacing every split on an
+    /// observed parent by the subtree for its value. Any value of the `Variable` itself is
+    /// ignored.
+    pub fn reduce<A: VariableAssignment>(&self, assignment: &A) -> Self {
+        let parents = self.parents.iter().filter(|p| assignment.value_of(p).is_none()).cloned().collect();
+        let root = prune(&self.root, assignment);
+
+        TreeCpd::new(self.variable, parents, root).expect("reduce encountered unexpected error")
+    }
+
+    /// Replace the `Variable`s of the CPD by their images in `map`; see `Factor::relabel`
+    pub fn relabel(&self, map: &HashMap<Variable, Variable>) -> Result<Self> {
+        let mut scope = relabel(&self.scope(), map)?;
+        let variable = scope.pop().unwrap();
+
+        TreeCpd::new(variable, scope, relabel_node(&self.root, map))
+    }
+
+    /// Get the CPD as a table `Factor` over ```parents + variable```. The table has one entry per
+    /// assignment to the scope, so it is only built the first time it is requested.
+    pub fn as_factor(&self) -> &Factor {
+        self.table.get_or_init(|| {
+            let n = self.parents.len();
+            let shape: Vec<usize> = self.scope().iter().map(|v| v.cardinality()).collect();
+
+            let table = nd::ArrayD::from_shape_fn(shape, |idx| {
+                let value_of = |v: &Variable| self.parents.iter().position(|p| p == v).map(|i| idx[i]);
+                find_leaf(&self.root, value_of).unwrap().1[idx[n]]
+            });
+
+            Factor::cpd(self.variable, self.parents.clone(), table).expect("expansion encountered unexpected error")
+        })
+    }
+}
+
+
+/// Verify that the subtree at `node` is a valid CPD, given the parents split on above it
+fn check(node: &TreeNode, card: usize, parents: &[Variable], path: &mut Vec<Variable>) -> Result<()> {
+    match *node {
+        TreeNode::Leaf(ref d) => {
+            if d.len() != card {
+                return Err(JeromeError::InvalidScope);
+            }
+
+            if d.iter().any(|&p| p.is_nan() || p < 0.0) {
+                return Err(JeromeError::NonPositiveProbability);
+            }
+
+            // the same tolerance as a table CPD
+            if (d.iter().sum::<f64>() - 1.0).abs() >= 0.0001 {
+                return Err(JeromeError::NotACPD);
+            }
+
+            Ok(())
+        },
+        TreeNode::Split(v, ref children) => {
+            if ! parents.contains(&v) || path.contains(&v) || children.len() != v.cardinality() {
+                return Err(JeromeError::InvalidScope);
+            }
+
+            path.push(v);
+            let result = children.iter().try_for_each(|c| check(c, card, parents, path));
+            path.pop();
+
+            result
+        }
+    }
+}
+
+
+/// Descend from `node` to a leaf, following the value of each parent split on
+fn find_leaf<F: Fn(&Variable) -> Option<usize>>(node: &TreeNode, value_of: F) -> Result<(usize, &[f64])> {
+    let mut node = node;
+    let mut position = 0;
+
+    loop {
+        match *node {
+            TreeNode::Leaf(ref d) => return Ok((position, d)),
+            TreeNode::Split(ref v, ref children) => {
+                let u = value_of(v).ok_or(JeromeError::IncompleteAssignment)?;
+                // the leaves of the preceding subtrees come first
+                position += children[..u].iter().map(count_leaves).sum::<usize>();
+                node = &children[u];
+            }
+        }
+    }
+}
+
+
+/// The number of leaves in the subtree at `node`
+fn count_leaves(node: &TreeNode) -> usize {
+    match *node {
+        TreeNode::Leaf(_) => 1,
+        TreeNode::Split(_, ref children) => children.iter().map(count_leaves).sum()
+    }
+}
+
+
+/// Collect the leaves of the subtree at `node`, in depth-first order
+fn collect_leaves<'a>(node: &'a TreeNode, leaves: &mut Vec<&'a [f64]>) {
+    match *node {
+        TreeNode::Leaf(ref d) => leaves.push(d),
+        TreeNode::Split(_, ref children) => children.iter().for_each(|c| collect_leaves(c, leaves))
+    }
+}
+
+
+/// Copy the subtree at `node`, taking the distributions at its leaves from `leaves` in depth-first
+/// order
+fn replace_leaves<I: Iterator<Item = Vec<f64>>>(node: &TreeNode, leaves: &mut I) -> TreeNode {
+    match *node {
+        TreeNode::Leaf(_) => TreeNode::Leaf(leaves.next().unwrap()),
+        TreeNode::Split(v, ref children) => {
+            TreeNode::Split(v, children.iter().map(|c| replace_leaves(c, leaves)).collect())
+        }
+    }
+}
+
+
+/// Copy the subtree at `node`, replacing each split on an observed parent by the subtree for its
+/// value
+fn prune<A: VariableAssignment>(node: &TreeNode, assignment: &A) -> TreeNode {
+    match *node {
+        TreeNode::Leaf(ref d) => TreeNode::Leaf(d.clone()),
+        TreeNode::Split(v, ref children) => match assignment.value_of(&v) {
+            Some(u) => prune(&children[u], assignment),
+            None => TreeNode::Split(v, children.iter().map(|c| prune(c, assignment)).collect())
+        }
+    }
+}
+
+
+/// Replace the `Variable` of every split in the subtree at `node` by its image in `map`
+fn relabel_node(node: &TreeNode, map: &HashMap<Variable, Variable>) -> TreeNode {
+    match *node {
+        TreeNode::Leaf(ref d) => TreeNode::Leaf(d.clone()),
+        TreeNode::Split(v, ref children) => {
+            TreeNode::Split(*map.get(&v).unwrap_or(&v), children.iter().map(|c| relabel_node(c, map)).collect())
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use variable::{all_assignments, Assignment};
+
+    /// Y depends on B only when A = 1
+    fn example() -> (Variable, Variable, Variable, TreeCpd) {
+        let a = Variable::binary();
+        let b = Variable::discrete(3);
+        let y = Variable::binary();
+
+        let root = TreeNode::Split(a, vec![
+            TreeNode::Leaf(vec![0.9, 0.1]),
+            TreeNode::Split(b, vec![
+                TreeNode::Leaf(vec![0.7, 0.3]),
+                TreeNode::Leaf(vec![0.4, 0.6]),
+                TreeNode::Leaf(vec![0.2, 0.8])
+            ])
+        ]);
+
+        (a, b, y, TreeCpd::new(y, vec![a, b], root).unwrap())
+    }
+
+    #[test]
+    fn tree_cpd() {
+        let (a, b, y, cpd) = example();
+        let c = Variable::binary();
+
+        assert!(TreeCpd::new(y, vec![a], cpd.root().clone()).is_err());
+        assert!(TreeCpd::new(y, vec![a, b, y], cpd.root().clone()).is_err());
+        assert!(TreeCpd::new(y, vec![a], TreeNode::Split(a, vec![TreeNode::Leaf(vec![0.5, 0.5])])).is_err());
+        assert!(TreeCpd::new(y, vec![], TreeNode::Leaf(vec![0.5, 0.6])).is_err());
+        assert!(TreeCpd::new(y, vec![], TreeNode::Leaf(vec![1.5, -0.5])).is_err());
+        assert!(TreeCpd::new(y, vec![], TreeNode::Leaf(vec![1.0, 0.0])).is_ok());
+        assert!(TreeCpd::new(y, vec![a], TreeNode::Split(a, vec![
+            TreeNode::Leaf(vec![0.5, 0.5]),
+            TreeNode::Split(a, vec![TreeNode::Leaf(vec![0.5, 0.5]), TreeNode::Leaf(vec![0.5, 0.5])])
+        ])).is_err());
+
+        // a parent need not be split on
+        assert!(TreeCpd::new(y, vec![a, b, c], cpd.root().clone()).is_ok());
+
+        assert_eq!(4, cpd.leaves().len());
+        assert_eq!(vec![a, b, y], cpd.scope());
+
+        // the context A = 0 is enough to reach a leaf
+        let mut ctx = Assignment::new();
+        ctx.set(&a, 0);
+        assert_eq!(0, cpd.leaf(&ctx).unwrap().0);
+        assert!(cpd.sample_cpd(&ctx).is_ok());
+
+        ctx.set(&a, 1);
+        assert!(cpd.leaf(&ctx).is_err());
+        ctx.set(&b, 2);
+        assert_eq!(3, cpd.leaf(&ctx).unwrap().0);
+
+        ctx.set(&y, 1);
+        assert_eq!(0.8, cpd.value(&ctx).unwrap());
+
+        let f = cpd.as_factor();
+        assert!(f.is_cpd());
+        for a in all_assignments(&cpd.scope()) {
+            assert_eq!(cpd.value(&a).unwrap(), f.value(&a).unwrap());
+        }
+
+        let relearned = cpd.with_leaves(vec![vec![0.5, 0.5]; 4]).unwrap();
+        assert_eq!(0.5, relearned.value(&ctx).unwrap());
+        assert!(cpd.with_leaves(vec![vec![0.5, 0.5]; 3]).is_err());
+    }
+
+    #[test]
+    fn reduce() {
+        let (a, b, y, cpd) = example();
+
+        let mut e = Assignment::new();
+        e.set(&a, 0);
+        let reduced = cpd.reduce(&e);
+        assert_eq!(vec![b, y], reduced.scope());
+        assert_eq!(1, reduced.leaves().len());
+
+        let mut e = Assignment::new();
+        e.set(&b, 1);
+        let reduced = cpd.reduce(&e);
+        assert_eq!(vec![a, y], reduced.scope());
+        assert_eq!(2, reduced.leaves().len());
+
+        let expected = cpd.as_factor().reduce(&e);
+        for a in all_assignments(&reduced.scope()) {
+            assert_eq!(expected.value(&a).unwrap(), reduced.value(&a).unwrap());
+        }
+    }
+
+    #[test]
+    fn sample() {
+        let (a, b, _, cpd) = example();
+
+        let mut ctx = Assignment::new();
+        ctx.set(&a, 1);
+        ctx.set(&b, 1);
+
+        let n = 10000;
+        let ones = (0..n).filter(|_| cpd.sample_cpd(&ctx).unwrap() == 1).count();
+        assert!((ones as f64 / n as f64 - 0.6).abs() < 0.03);
+    }
+}
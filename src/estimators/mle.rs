@@ -1,16 +1,19 @@
-/// Defines `Estimator`s that use Maximum Likelihood Estimation to estimate the value of parameters
-/// given a dataset.
+//! Defines `Estimator`s that use Maximum Likelihood Estimation to estimate the value of parameters
+//! given a dataset.
 
+use cpd::{DiscreteCpd, TreeCpd};
 use factor::{Factor, Table};
 use init::Initialization;
 use model::directed::{DirectedModel, DirectedModelBuilder};
 use model::Model;
 use super::Estimator;
-use variable::{Assignment, Variable};
+use variable::{DenseAssignment, Variable, VariableAssignment, VariableIndex};
 use util::{JeromeError, Result};
 
 use ndarray::prelude as nd;
 
+use std::sync::Arc;
+
 /// Defines the `LocalMLEstimator`, a Maximum Likelihood `Estimator` for the Conditional Probability 
 /// Distribution of a single variable in a Bayesian framework.
 ///
@@ -47,20 +50,17 @@ impl LocalMLEstimator {
 
 impl<'a> Estimator<'a, Factor> for LocalMLEstimator {
 
-    fn estimate(&mut self, dataset: impl Iterator<Item = &'a Assignment>) -> Result<Factor> {
+    fn estimate<A: 'a + VariableAssignment>(&mut self, dataset: impl Iterator<Item = &'a A>) -> Result<Factor> {
         // each call to estimate must be independent, so first let's zero the table.
         self.table *= 0.0;
 
         // count the number of instances of each configuration, using self.table as an accumulator
         for sample in dataset {
-            let idx: Vec<Option<&usize>> = self.scope.iter().map(|v| sample.get(v)).collect();
-            if idx.iter().any(|i| i.is_none()) {
-                return Err(JeromeError::IncompleteAssignment);
+            let idx: Option<Vec<usize>> = self.scope.iter().map(|v| sample.value_of(v)).collect();
+            match idx {
+                Some(idx) => self.table[nd::IxDyn(&idx)] += 1.0,
+                None => return Err(JeromeError::IncompleteAssignment)
             }
-
-            let idx: Vec<usize> = idx.iter().map(|i| i.unwrap()).cloned().collect();
-
-            self.table[nd::IxDyn(&idx)] += 1.0;
         }
 
         // now, we estimate each parameter by using the sufficient statistics (see K&F Eq. 17.5):
@@ -73,7 +73,7 @@ impl<'a> Estimator<'a, Factor> for LocalMLEstimator {
             return Err(JeromeError::DivideByZero);
         }
 
-        let mut new_shape: Vec<usize> = m_u.shape().iter().cloned().collect();
+        let mut new_shape: Vec<usize> = m_u.shape().to_vec();
         new_shape.push(1);
         m_u = m_u.into_shape(new_shape).unwrap().into_dyn();
 
@@ -88,17 +88,121 @@ impl<'a> Estimator<'a, Factor> for LocalMLEstimator {
 }
 
 
+/// A Maximum Likelihood `Estimator` for a `TreeCpd`, which keeps the structure of the tree.
+///
+/// Every context that reaches a leaf shares its parameters, so the sufficient statistics of a leaf
+/// are the counts of the child over all of the samples that reach it (Koller & Friedman Section
+/// 17.5.1). A leaf is estimated from data even when some of the contexts that reach it never
+/// occur.
+pub struct TreeMLEstimator {
+
+    /// The structure of the estimated CPD
+    cpd: TreeCpd,
+
+    /// The count of each value of the child at each leaf, in depth-first order
+    counts: Vec<Vec<f64>>
+
+}
+
+
+impl TreeMLEstimator {
+
+    /// Construct an ML estimator for the leaves of the given tree CPD
+    pub fn new(cpd: &TreeCpd) -> Self {
+        let card = cpd.variable().cardinality();
+        let counts = vec![vec![0.0; card]; cpd.leaves().len()];
+
+        TreeMLEstimator { cpd: cpd.clone(), counts }
+    }
+
+}
+
+
+impl<'a> Estimator<'a, TreeCpd> for TreeMLEstimator {
+
+    fn estimate<A: 'a + VariableAssignment>(&mut self, dataset: impl Iterator<Item = &'a A>) -> Result<TreeCpd> {
+        // each call to estimate must be independent, so first let's zero the counts.
+        self.counts.iter_mut().for_each(|c| c.iter_mut().for_each(|ct| *ct = 0.0));
+
+        let y = self.cpd.variable();
+        for sample in dataset {
+            let (leaf, _) = self.cpd.leaf(sample)?;
+            let val = sample.value_of(&y).ok_or(JeromeError::IncompleteAssignment)?;
+            self.counts[leaf][val] += 1.0;
+        }
+
+        // theta x|leaf = M[leaf, x] / M[leaf]
+        let mut leaves = Vec::new();
+        for counts in self.counts.iter() {
+            let m: f64 = counts.iter().sum();
+            if m == 0.0 {
+                return Err(JeromeError::DivideByZero);
+            }
+
+            leaves.push(counts.iter().map(|ct| ct / m).collect());
+        }
+
+        self.cpd.with_leaves(leaves)
+    }
+}
+
+
+/// The `Estimator` for a single CPD of a `DirectedModel`
+enum CpdEstimator {
+    /// Estimates every entry of a table
+    Table(LocalMLEstimator),
+
+    /// Estimates the leaves of a tree, keeping its structure
+    Tree(TreeMLEstimator),
+
+    /// A CPD with no parameters, which is kept as-is
+    Fixed(DiscreteCpd)
+}
+
+
+impl CpdEstimator {
+
+    /// Construct the `Estimator` for a CPD. A deterministic CPD has no parameters to estimate, and
+    /// a noisy-max CPD is estimated as a table; see `ModelMLEstimator`.
+    fn new(cpd: &DiscreteCpd) -> Result<Self> {
+        match *cpd {
+            DiscreteCpd::Table(ref f) => LocalMLEstimator::new(f).map(CpdEstimator::Table),
+            DiscreteCpd::Tree(ref tree) => Ok(CpdEstimator::Tree(TreeMLEstimator::new(tree))),
+            DiscreteCpd::Deterministic(_) => Ok(CpdEstimator::Fixed(cpd.clone())),
+            DiscreteCpd::NoisyMax(ref noisy) => LocalMLEstimator::new(noisy.as_factor()).map(CpdEstimator::Table)
+        }
+    }
+
+    /// Estimate the CPD from the dataset
+    fn estimate(&mut self, data: &[DenseAssignment]) -> Result<DiscreteCpd> {
+        match *self {
+            CpdEstimator::Table(ref mut e) => e.estimate(data.iter()).map(DiscreteCpd::Table),
+            CpdEstimator::Tree(ref mut e) => e.estimate(data.iter()).map(DiscreteCpd::Tree),
+            CpdEstimator::Fixed(ref cpd) => Ok(cpd.clone())
+        }
+    }
+
+}
+
+
 /// A Maximium Likelihood estimator for a `DirectedModel`
 ///
 /// Based on the decomposability of the likelihood function, each CPD can be estimated separately
-/// and therefore the `ModelMLEstimator` is really just a 'bag-o-`LocalMLEstimator`s'
+/// and therefore the `ModelMLEstimator` is really just a 'bag-o-`LocalMLEstimator`s' (and
+/// `TreeMLEstimator`s, for tree CPDs)
+///
+/// # Structured CPDs
+/// Each CPD of the estimated model has the same kind as in the original model, except:
+/// * a deterministic CPD has no parameters, so it is kept unchanged
+/// * a noisy-max CPD has no closed-form maximum likelihood estimate, so it is converted to a table
+///   over the same scope, and estimated as a `DiscreteCpd::Table`
 pub struct ModelMLEstimator<'a> {
 
     /// The model for which to estimate the parameters
     model: &'a DirectedModel,
 
     /// The `Estimator` for each local CPD
-    estimators: Vec<LocalMLEstimator>
+    estimators: Vec<CpdEstimator>
 
 }
 
@@ -106,11 +210,11 @@ pub struct ModelMLEstimator<'a> {
 impl<'a> ModelMLEstimator<'a> {
     
     pub fn new(model: &'a DirectedModel) -> Result<Self> {
-        let estimators: Vec<Result<LocalMLEstimator>> = model.topological_order()
-                                                             .iter()
-                                                             .map(|v| model.cpd(&v).unwrap())
-                                                             .map(|f| LocalMLEstimator::new(&f))
-                                                             .collect();
+        let estimators: Vec<Result<CpdEstimator>> = model.topological_order()
+                                                         .iter()
+                                                         .map(|v| model.discrete_cpd(v).ok_or(JeromeError::NotACPD))
+                                                         .map(|cpd| cpd.and_then(CpdEstimator::new))
+                                                         .collect();
 
         if let Some(ref r) = estimators.iter().find(|r| r.is_err()) {
             return match r {
@@ -130,30 +234,32 @@ impl<'a> ModelMLEstimator<'a> {
 
 impl<'a> Estimator<'a, DirectedModel> for ModelMLEstimator<'a> {
 
-    fn estimate(&mut self, dataset: impl Iterator<Item = &'a Assignment>) -> Result<DirectedModel> {
-        let data: Vec<Assignment> = dataset.cloned().collect();
+    fn estimate<A: 'a + VariableAssignment>(&mut self, dataset: impl Iterator<Item = &'a A>) -> Result<DirectedModel> {
+        // the dataset is traversed once per estimator, so lay it out densely up front
+        let index = Arc::new(VariableIndex::new(&self.model.topological_order()));
+        let data: Vec<DenseAssignment> = dataset.map(|a| DenseAssignment::from_assignment(&index, a)).collect();
 
-        let new_factors: Vec<Result<Factor>> = self.estimators
-                                                   .iter_mut()
-                                                   .map(|e| e.estimate(data.iter()))
-                                                   .collect();
+        let new_cpds: Vec<Result<DiscreteCpd>> = self.estimators
+                                                     .iter_mut()
+                                                     .map(|e| e.estimate(&data))
+                                                     .collect();
 
-        let mut builder = DirectedModelBuilder::new();
+        let mut builder = DirectedModelBuilder::new().with_labels_of(self.model.labels());
 
-        for (v, r) in self.model.topological_order().iter().zip(new_factors.into_iter()) {
+        for (v, r) in self.model.topological_order().iter().zip(new_cpds) {
             if let Err(e) = r {
                 return Err(e);
             }
 
-            let f = r.unwrap();
-            let scope = f.scope();
+            let cpd = r.unwrap();
+            let scope = cpd.scope();
             let num_vars = scope.len();
 
             builder = builder.with_named_variable(
-                &v,
-                self.model.lookup_name(&v).unwrap(),
+                v,
+                self.model.lookup_name(v).unwrap(),
                 scope.into_iter().take(num_vars - 1).collect(),
-                Initialization::Table(f)
+                Initialization::from(cpd)
             );
         }
 
@@ -164,11 +270,13 @@ impl<'a> Estimator<'a, DirectedModel> for ModelMLEstimator<'a> {
 
 
 #[cfg(test)]
+#[allow(clippy::manual_repeat_n, clippy::useless_vec)]
 mod tests {
 
     use super::*;
     use std::iter::repeat;
-    use variable::all_assignments;
+    use cpd::{Deterministic, NoisyMax, TreeNode};
+    use variable::{all_assignments, Assignment};
     use std::collections::HashSet;
 
     #[test]
@@ -410,6 +518,7 @@ mod tests {
         let builder = DirectedModelBuilder::new();
         let model = builder.with_named_variable(&x, "X", HashSet::new(), Initialization::Binomial(0.5))
                            .with_named_variable(&y, "Y", vec![x].into_iter().collect(), Initialization::Uniform)
+                           .with_labels(&x, &["tails", "heads"])
                            .build()
                            .unwrap();
 
@@ -447,11 +556,119 @@ mod tests {
         assert_eq!(vec![x, y], new_model.topological_order());
         assert_eq!("X", new_model.lookup_name(&x).unwrap());
         assert_eq!("Y", new_model.lookup_name(&y).unwrap());
+        assert_eq!(model.labels(), new_model.labels());
 
         let vars = vec![x, y];
         let actual = all_assignments(&vars).map(|a| new_model.probability(&a).unwrap());
         let expected = vec![0.3 * 0.8, 0.3 * 0.2, 0.7 * 0.5, 0.7 * 0.5];
         assert!(expected.iter().zip(actual).all(|(e, a)| *e == a));
+
+        // the same dataset, laid out densely, yields the same estimate
+        let index = Arc::new(VariableIndex::new(&[y, x]));
+        let dense: Vec<DenseAssignment> = dataset.iter()
+                                                 .map(|a| DenseAssignment::from_assignment(&index, a))
+                                                 .collect();
+
+        let dense_model = estimator.estimate(dense.iter()).unwrap();
+        let actual = all_assignments(&vars).map(|a| dense_model.probability(&a).unwrap());
+        assert!(expected.iter().zip(actual).all(|(e, a)| *e == a));
     }
-}
 
+
+    #[test]
+    /// Test A (binomial), B (binomial) -> Y (binomial) model, where Y has a tree CPD that ignores B
+    /// when A = 0.
+    ///
+    /// The context ```A = 0, B = 0``` never occurs, which would leave a row of a table CPD with no
+    /// data, but the leaf for ```A = 0``` is estimated from the samples with ```A = 0, B = 1```.
+    fn tree_model() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+        let y = Variable::binary();
+
+        let root = TreeNode::Split(a, vec![
+            TreeNode::Leaf(vec![0.5, 0.5]),
+            TreeNode::Split(b, vec![TreeNode::Leaf(vec![0.5, 0.5]), TreeNode::Leaf(vec![0.5, 0.5])])
+        ]);
+        let cpd = TreeCpd::new(y, vec![a, b], root).unwrap();
+
+        let model = DirectedModelBuilder::new()
+                        .with_variable(&a, HashSet::new(), Initialization::Uniform)
+                        .with_variable(&b, HashSet::new(), Initialization::Uniform)
+                        .with_variable(&y, vec![a, b].into_iter().collect(), Initialization::Tree(cpd.clone()))
+                        .build()
+                        .unwrap();
+
+        let counts = [((0, 1, 0), 40), ((0, 1, 1), 60), ((1, 0, 0), 20), ((1, 0, 1), 80), ((1, 1, 0), 70), ((1, 1, 1), 30)];
+        let mut dataset: Vec<Assignment> = Vec::new();
+        for &((va, vb, vy), n) in counts.iter() {
+            let mut s = Assignment::new();
+            s.set(&a, va);
+            s.set(&b, vb);
+            s.set(&y, vy);
+            dataset.extend(std::iter::repeat_n(s, n));
+        }
+
+        let mut estimator = TreeMLEstimator::new(&cpd);
+        let estimate = estimator.estimate(dataset.iter()).unwrap();
+        let expected = [[0.4, 0.6], [0.2, 0.8], [0.7, 0.3]];
+        for (leaf, e) in estimate.leaves().iter().zip(expected.iter()) {
+            assert!(leaf.iter().zip(e.iter()).all(|(l, e)| (l - e).abs() < 1e-12));
+        }
+
+        // the model estimator keeps the structure of the tree
+        let new_model = ModelMLEstimator::new(&model).unwrap().estimate(dataset.iter()).unwrap();
+        let tree = new_model.tree_cpd(&y).unwrap();
+        assert_eq!(3, tree.leaves().len());
+
+        let mut q = Assignment::new();
+        q.set(&a, 0);
+        q.set(&b, 0);
+        q.set(&y, 1);
+        assert!((0.6 - tree.value(&q).unwrap()).abs() < 1e-12);
+
+        // a leaf that no sample reaches cannot be estimated
+        let unreached: Vec<Assignment> = dataset.into_iter().filter(|s| s.get(&a) == Some(&1)).collect();
+        assert!(matches!(estimator.estimate(unreached.iter()), Err(JeromeError::DivideByZero)));
+    }
+
+    #[test]
+    /// Test that a deterministic CPD is kept, and a noisy-max CPD is estimated as a table
+    fn structured_model() {
+        let x = Variable::binary();
+        let y = Variable::binary();
+        let z = Variable::binary();
+        let w = Variable::binary();
+
+        let noisy = NoisyMax::noisy_or(z, vec![x, y], vec![0.2, 0.4], 0.1).unwrap();
+        let copy = Deterministic::new(w, vec![x], |u: &[usize]| u[0]).unwrap();
+        let model = DirectedModelBuilder::new()
+                        .with_variable(&x, HashSet::new(), Initialization::Uniform)
+                        .with_variable(&y, HashSet::new(), Initialization::Uniform)
+                        .with_variable(&z, vec![x, y].into_iter().collect(), Initialization::NoisyMax(noisy))
+                        .with_variable(&w, vec![x].into_iter().collect(), Initialization::Deterministic(copy))
+                        .build()
+                        .unwrap();
+
+        // z is active in three of every four samples, whatever its parents
+        let dataset: Vec<Assignment> = all_assignments(&vec![x, y, z])
+                                           .flat_map(|mut a| {
+                                               a.set(&w, *a.get(&x).unwrap());
+                                               let n = if a.get(&z) == Some(&1) { 3 } else { 1 };
+                                               repeat(a).take(n)
+                                           })
+                                           .collect();
+
+        let new_model = ModelMLEstimator::new(&model).unwrap().estimate(dataset.iter()).unwrap();
+
+        assert!(matches!(new_model.discrete_cpd(&z), Some(DiscreteCpd::Table(_))));
+        assert!(matches!(new_model.discrete_cpd(&w), Some(DiscreteCpd::Deterministic(_))));
+        assert_eq!(vec![x, y, z], new_model.discrete_cpd(&z).unwrap().scope());
+
+        for a in all_assignments(&vec![x, y, z, w]) {
+            let expected = if a.get(&z) == Some(&1) { 0.75 } else { 0.25 };
+            assert!((expected - new_model.discrete_cpd(&z).unwrap().value(&a).unwrap()).abs() < 1e-12);
+            assert_eq!(model.cpd(&w).unwrap().value(&a).unwrap(), new_model.cpd(&w).unwrap().value(&a).unwrap());
+        }
+    }
+}
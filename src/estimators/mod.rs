@@ -1,17 +1,19 @@
 //! Defines an `Estimator`, which is used to estimate parameters of a `Model` from a dataset.
 
-use variable::Assignment;
+use variable::VariableAssignment;
 use util::Result;
 
 mod mle;
 pub use self::mle::LocalMLEstimator;
 pub use self::mle::ModelMLEstimator;
+pub use self::mle::TreeMLEstimator;
 
 /// A trait that represents the ability to estimate the parameters of some model (be it a `Model`
 /// or just a local CPD.
 pub trait Estimator<'a, T> {
 
-    /// Estimate the value of the parameters from the given dataset
-    fn estimate(&mut self, dataset: impl Iterator<Item = &'a Assignment>) -> Result<T>;
+    /// Estimate the value of the parameters from the given dataset. The rows of the dataset may be
+    /// `Assignment`s or `DenseAssignment`s.
+    fn estimate<A: 'a + VariableAssignment>(&mut self, dataset: impl Iterator<Item = &'a A>) -> Result<T>;
 
 }
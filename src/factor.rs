@@ -1,15 +1,19 @@
-///! Definition of the factor module
-///!
-///! A `Factor` represents a relationship between some set of `Variable`s.
+//! Definition of the factor module
+//!
+//! A `Factor` represents a relationship between some set of `Variable`s.
 
 use util::{Result, JeromeError};
-use variable::{Variable, Assignment, all_assignments};
+use variable::{all_assignments, relabel, Labels, Variable, VariableAssignment};
 
 use itertools::Itertools;
 use ndarray::prelude as nd;
+use ndarray::Zip;
 use rand;
 use rand::distributions::{IndependentSample, Range};
 
+use std::collections::HashMap;
+use std::fmt;
+
 /// Alias f64 ndarray::Array as Table
 pub type Table = nd::ArrayD<f64>;
 
@@ -30,7 +34,11 @@ pub enum Factor {
         table: Table,
 
         /// `true`, if the `Factor` is a conditional probability distribution (i.e. is normalized)
-        cpd: bool
+        cpd: bool,
+
+        /// `true`, if `table` holds the natural logarithm of the values of the `Factor`. Operations
+        /// on log-space `Factor`s are carried out in log-space, which prevents underflow.
+        log: bool
     }
 }
 
@@ -62,17 +70,32 @@ impl Factor {
             let mut scope: Vec<Variable> = parents.into_iter().collect();
             scope.push(v);
 
-            Factor::make_factor(scope, table, true)
+            Factor::make_factor(scope, table, true, false)
         }
     }
 
     pub fn new(scope: Vec<Variable>, table: Table) -> Result<Self> {
-        Factor::make_factor(scope, table, false)
+        Factor::make_factor(scope, table, false, false)
+    }
+
+    /// Create a new `Factor` over the given scope in which every value is one.
+    ///
+    /// This is useful as the starting point of a product over `Factor`s whose scopes are known to
+    /// be subsets of `scope`, but may not intersect one another.
+    pub fn ones(scope: Vec<Variable>) -> Result<Self> {
+        let shape: Vec<usize> = scope.iter().map(|v| v.cardinality()).collect();
+        Factor::make_factor(scope, Table::ones(shape), false, false)
+    }
+
+    /// Create a new log-space `Factor`, in which `table` holds the natural logarithm of the values
+    /// of the `Factor`.
+    pub fn new_log(scope: Vec<Variable>, table: Table) -> Result<Self> {
+        Factor::make_factor(scope, table, false, true)
     }
 
     /// Create a new `Factor`
-    fn make_factor(scope: Vec<Variable>, table: Table, cpd: bool) -> Result<Self> {
-        if scope.len() == 0 {
+    fn make_factor(scope: Vec<Variable>, table: Table, cpd: bool, log: bool) -> Result<Self> {
+        if scope.is_empty() {
             return Err(
                 JeromeError::General(
                     String::from("Invalid arguments. Scope may not be empty")
@@ -96,23 +119,20 @@ impl Factor {
             }
         }
 
-        // factors may not have negative values
-        if cpd && table.iter().any(|&v| v <= 0.0) {
-            return Err(JeromeError::NonPositiveProbability);
-        } else if !cpd && table.iter().any(|&v| v < 0.0) {
+        // factors may not have negative values. In log-space, any value other than NaN is valid.
+        // Zeros are valid, even in a CPD, so that deterministic relationships can be represented.
+        let zero = if log { f64::NEG_INFINITY } else { 0.0 };
+        if table.iter().any(|&v| v.is_nan() || v < zero) {
             return Err(JeromeError::NonPositiveProbability);
         }
 
-        Ok(Factor::TableFactor { scope, table, cpd })
+        Ok(Factor::TableFactor { scope, table, cpd, log })
     }
 
 
     /// Check if the `Factor` is the identity `Factor`
     pub fn is_identity(&self) -> bool {
-        match self {
-            &Factor::Identity => true,
-            _ => false
-        }
+        matches!(*self, Factor::Identity)
     }
 
 
@@ -123,9 +143,40 @@ impl Factor {
     /// A conditional probability distribution is a specialization of a `Factor`. All CPDs are
     /// `Factor`s, but not all `Factor`s are CPDs. The identity `Factor` is considered a CPD.
     pub fn is_cpd(&self) -> bool {
-        match self {
-            &Factor::Identity => true,
-            &Factor::TableFactor { cpd, .. } => cpd
+        match *self {
+            Factor::Identity => true,
+            Factor::TableFactor { cpd, .. } => cpd
+        }
+    }
+
+
+    /// Check if the values of the `Factor` are held in log-space. The identity `Factor` is not.
+    pub fn is_log(&self) -> bool {
+        match *self {
+            Factor::Identity => false,
+            Factor::TableFactor { log, .. } => log
+        }
+    }
+
+
+    /// Convert the `Factor` to log-space
+    pub fn to_log(&self) -> Self {
+        match *self {
+            Factor::TableFactor { ref scope, ref table, cpd, log: false } => {
+                Factor::TableFactor { scope: scope.clone(), table: table.mapv(f64::ln), cpd, log: true }
+            },
+            _ => self.clone()
+        }
+    }
+
+
+    /// Convert a log-space `Factor` back to a `Factor` that holds its values directly
+    pub fn to_linear(&self) -> Self {
+        match *self {
+            Factor::TableFactor { ref scope, ref table, cpd, log: true } => {
+                Factor::TableFactor { scope: scope.clone(), table: table.mapv(f64::exp), cpd, log: false }
+            },
+            _ => self.clone()
         }
     }
 
@@ -136,9 +187,9 @@ impl Factor {
     /// This method returns a clone of the `Factor`'s scope. `Variable`'s are lightweight and
     /// therefore this is an acceptable overhead
     pub fn scope(&self) -> Vec<Variable> {
-        match self {
-            &Factor::Identity => vec![],
-            &Factor::TableFactor { ref scope, .. } => scope.clone()
+        match *self {
+            Factor::Identity => vec![],
+            Factor::TableFactor { ref scope, .. } => scope.clone()
         }
     }
 
@@ -148,8 +199,9 @@ impl Factor {
     /// This operation is defined only on non-indentity `Factor`s. 
     ///
     /// # Args
-    /// assignment: a full assignment to the scope of a `Factor`. The assignment's scope  may be a
-    ///             superset  of the `Factor`s scope.
+    /// assignment: a full assignment to the scope of a `Factor`, as either an `Assignment` or a
+    ///             `DenseAssignment`. The assignment's scope  may be a superset  of the `Factor`s
+    ///             scope.
     ///
     /// # Returns
     /// the value of the assignment, or an error.
@@ -158,19 +210,35 @@ impl Factor {
     /// * `JeromeError::General` if the `Factor` is the identity
     /// * `JeromeError::IncompleteAssignment`, if assignment is not a complete assignment to the
     ///   scope of the `Factor`
-    pub fn value(&self, assignment: &Assignment) -> Result<f64> {
-        match self {
-            &Factor::Identity => {
+    pub fn value<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        let val = self.entry(assignment)?;
+        Ok(if self.is_log() { val.exp() } else { val })
+    }
+
+
+    /// Retrieve the natural logarithm of the value for a complete assignment over the scope of
+    /// this `Factor`. For log-space `Factor`s, this is exact even when the value would underflow.
+    ///
+    /// # Errors
+    /// see `Factor::value`
+    pub fn log_value<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        let val = self.entry(assignment)?;
+        Ok(if self.is_log() { val } else { val.ln() })
+    }
+
+
+    /// Retrieve the entry of the table for a complete assignment over the scope of this `Factor`
+    fn entry<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        match *self {
+            Factor::Identity => {
                 Err(JeromeError::General(String::from("The identity factor has no value")))
             },
-            &Factor::TableFactor { ref scope, ref table, .. } => {
-                let idxs: Vec<Option<&usize>> = scope.iter().map(|v| assignment.get(v)).collect();
-                if idxs.iter().any(|&v| v.is_none()) {
-                    return Err(JeromeError::IncompleteAssignment);
+            Factor::TableFactor { ref scope, ref table, .. } => {
+                let idxs: Option<Vec<usize>> = scope.iter().map(|v| assignment.value_of(v)).collect();
+                match idxs {
+                    Some(idxs) => Ok(table[nd::IxDyn(&idxs)]),
+                    None => Err(JeromeError::IncompleteAssignment)
                 }
-
-                let idxs: Vec<usize> = idxs.iter().map(|&v| *(v.unwrap())).collect();
-                Ok(table[nd::IxDyn(&idxs)])
             }
         }
     }
@@ -183,6 +251,8 @@ impl Factor {
     /// # Args
     /// other: the `Factor` to multiply with.
     ///
+    /// If either `Factor` is in log-space, the product is computed (and returned) in log-space.
+    ///
     /// # Returns
     /// A new `Factor` of scope union(self.scope(), other.scope()) 
     ///
@@ -216,24 +286,27 @@ impl Factor {
         }
 
         let new_shape: Vec<usize> = new_scope.iter().map(|&v| v.cardinality()).collect(); 
+        let (log1, log2) = (self.is_log(), other.is_log());
+        let log = log1 || log2;
+
+        // Align the axes of both tables with the new scope and broadcast them to the new shape, so
+        // that the product (or sum, in log-space) is a single elementwise pass that only allocates
+        // the result.
+        let aligned1 = self.aligned(&new_scope);
+        let aligned2 = other.aligned(&new_scope);
+        let tbl1 = aligned1.broadcast(nd::IxDyn(&new_shape)).unwrap();
+        let tbl2 = aligned2.broadcast(nd::IxDyn(&new_shape)).unwrap();
+
+        let mut tbl = Table::zeros(nd::IxDyn(&new_shape));
+        Zip::from(&mut tbl).and(&tbl1).and(&tbl2).apply(|psi_val, &phi1_val, &phi2_val| {
+            *psi_val = if log {
+                as_log(phi1_val, log1) + as_log(phi2_val, log2)
+            } else {
+                phi1_val * phi2_val
+            };
+        });
 
-        // Allocate space for new table
-        let mut tbl = nd::Array::ones(new_shape).into_dyn();
-        
-        for assn in all_assignments(&new_scope) {
-            // For each assignment, multiply the values in each and store the result in the
-            // new table
-            //
-            // Unwrapping here is safe because a failed lookup should be impossible if
-            // invariants are maintained
-            let phi1_val = self.value(&assn).unwrap();
-            let phi2_val = other.value(&assn).unwrap();
-
-            let idx: Vec<usize> = new_scope.iter().map(|v| *assn.get(&v).unwrap()).collect();
-            tbl[nd::IxDyn(&idx)] = phi1_val * phi2_val;
-        }
-
-        Factor::make_factor(new_scope, tbl, false)
+        Factor::make_factor(new_scope, tbl, false, log)
     }
 
 
@@ -246,6 +319,8 @@ impl Factor {
     /// In the context of this operation, 0/0 is defined as 0. However, X/0, where X != 0, is still
     /// undefined.
     ///
+    /// If either `Factor` is in log-space, the quotient is computed (and returned) in log-space.
+    ///
     /// # Args
     /// other: the denominator of the expression
     ///
@@ -269,36 +344,65 @@ impl Factor {
         // Okay... let's get to work
         // First - check to make sure the scopes are okay
         let my_scope = self.scope();
-        if ! other.scope().iter().all(|v| my_scope.contains(&v)) {
+        if ! other.scope().iter().all(|v| my_scope.contains(v)) {
             return Err(JeromeError::InvalidScope);
         }
 
-        // Allocate a table with the shape and scope as self
         let shape: Vec<usize> = my_scope.iter().map(|v| v.cardinality()).collect();
-        let mut tbl = nd::Array::ones(shape).into_dyn();
-
-        for assn in all_assignments(&my_scope) {
-            // For each assignment, divide the values in each and store the result in the
-            // new table
-            //
-            // Unwrapping here is safe because a failed lookup should be impossible if
-            // invariants are maintained
-            let phi1_val = self.value(&assn).unwrap();
-            let phi2_val = other.value(&assn).unwrap();
-
-            let idx: Vec<usize> = my_scope.iter().map(|v| *assn.get(&v).unwrap()).collect();
-            if phi2_val == 0. {
-                if phi1_val == 0. {
-                    tbl[nd::IxDyn(&idx)] = 0.;
+        let (log1, log2) = (self.is_log(), other.is_log());
+        let log = log1 || log2;
+        let zero = if log { f64::NEG_INFINITY } else { 0.0 };
+
+        // Broadcast the denominator to the shape of the numerator, and divide (or subtract, in
+        // log-space) elementwise
+        let aligned2 = other.aligned(&my_scope);
+        let tbl1 = self.aligned(&my_scope);
+        let tbl2 = aligned2.broadcast(nd::IxDyn(&shape)).unwrap();
+
+        let vals: Result<Vec<f64>> = tbl1.iter().zip(tbl2.iter()).map(|(&phi1_val, &phi2_val)| {
+            let (phi1_val, phi2_val) = if log { (as_log(phi1_val, log1), as_log(phi2_val, log2)) } else { (phi1_val, phi2_val) };
+            if phi2_val == zero {
+                if phi1_val == zero {
+                    Ok(zero)
                 } else {
-                    return Err(JeromeError::DivideByZero);
+                    Err(JeromeError::DivideByZero)
                 }
+            } else if log {
+                Ok(phi1_val - phi2_val)
             } else {
-                tbl[nd::IxDyn(&idx)] = phi1_val / phi2_val;
+                Ok(phi1_val / phi2_val)
             }
-        }
+        }).collect();
+
+        let tbl = Table::from_shape_vec(shape, vals?).expect("divide encountered unexpected error");
 
-        Factor::make_factor(my_scope, tbl, false)
+        Factor::make_factor(my_scope, tbl, false, log)
+    }
+
+
+    /// Get a view of the table of the `Factor` with its axes aligned to `scope`, a superset of the
+    /// scope of the `Factor`. The axes are permuted into the order of `scope`, and an axis of
+    /// length one is inserted for each `Variable` not in the scope of the `Factor`, so that the
+    /// view broadcasts against any table over `scope`.
+    fn aligned(&self, scope: &[Variable]) -> nd::ArrayViewD<'_, f64> {
+        match *self {
+            Factor::Identity => panic!("the identity factor has no table"),
+            Factor::TableFactor { scope: ref my_scope, ref table, .. } => {
+                let position = |v: &Variable| scope.iter().position(|u| u == v).unwrap();
+
+                let mut axes: Vec<usize> = (0..my_scope.len()).collect();
+                axes.sort_by_key(|&i| position(&my_scope[i]));
+
+                let mut view = table.view().permuted_axes(nd::IxDyn(&axes));
+                for (i, v) in scope.iter().enumerate() {
+                    if ! my_scope.contains(v) {
+                        view = view.insert_axis(nd::Axis(i));
+                    }
+                }
+
+                view
+            }
+        }
     }
 
 
@@ -311,17 +415,17 @@ impl Factor {
     ///
     /// # Returns
     /// A new `Factor` reduced over the given assignment
-    pub fn reduce(&self, assignment: &Assignment) -> Self {
-        match self {
-            &Factor::Identity => Factor::Identity,
-            &Factor::TableFactor { ref scope, ref table, cpd } => {
+    pub fn reduce<A: VariableAssignment>(&self, assignment: &A) -> Self {
+        match *self {
+            Factor::Identity => Factor::Identity,
+            Factor::TableFactor { ref scope, ref table, cpd, log } => {
                 // reduce table based on assignment
                 let mut view = table.view();
                 let mut new_shape: Vec<usize> = Vec::new();
                 let mut new_scope: Vec<Variable> = Vec::new();
 
                 for (i, &v) in scope.iter().enumerate() {
-                    if let Some(&val) = assignment.get(&v) {
+                    if let Some(val) = assignment.value_of(&v) {
                         view.subview_inplace(nd::Axis(i), val);
                     } else {
                         new_shape.push(table.len_of(nd::Axis(i)));
@@ -329,7 +433,7 @@ impl Factor {
                     }
                 }
 
-                if new_scope.len() == 0 {
+                if new_scope.is_empty() {
                     // complete assignment
                     Factor::Identity
                 } else if new_scope.len() == scope.len() {
@@ -340,7 +444,8 @@ impl Factor {
                         new_scope, 
                         view.to_owned().into_shape(new_shape).expect("reduce encountered error"), 
                         // If you reduce a CPD, you still get a CPD!
-                        cpd 
+                        cpd,
+                        log
                     ).expect(
                         "reduce encountered unexpected error"
                     )
@@ -350,6 +455,41 @@ impl Factor {
     }
 
 
+    /// Replace the `Variable`s in the scope of the `Factor` by their images in `map`, keeping the
+    /// values of the `Factor`. `Variable`s without an image are kept.
+    ///
+    /// # Errors
+    /// `JeromeError::InvalidScope` if an image has a different cardinality than its `Variable`, or
+    /// the new scope contains a duplicate
+    pub fn relabel(&self, map: &HashMap<Variable, Variable>) -> Result<Self> {
+        match *self {
+            Factor::Identity => Ok(Factor::Identity),
+            Factor::TableFactor { ref scope, ref table, cpd, log } => {
+                Factor::make_factor(relabel(scope, map)?, table.clone(), cpd, log)
+            }
+        }
+    }
+
+
+    /// Format the `Factor` as one line per assignment to its scope, as `Display` does, but showing
+    /// the value of each `Variable` labeled in `labels` by its label.
+    pub fn to_labeled_string(&self, labels: &Labels) -> String {
+        match *self {
+            Factor::Identity => String::from("Identity"),
+            Factor::TableFactor { ref scope, .. } => {
+                all_assignments(scope).map(|assn| {
+                    let values: Vec<String> = scope.iter()
+                                                   .map(|v| format!("{} = {}", v, labels.format_value(v, *assn.get(v).unwrap())))
+                                                   .collect();
+
+                    format!("{}: {}", values.join(", "), self.value(&assn).unwrap())
+                })
+                .join("\n")
+            }
+        }
+    }
+
+
     /// Marginalize the `Factor` over the given `Variable`
     ///
     /// Defined in Koller & Friedman 9.3.1
@@ -360,16 +500,20 @@ impl Factor {
     /// # Returns
     /// another `Factor`, marginalized over the given `Variable`
     pub fn marginalize(&self, other: Variable) -> Self {
-        match self {
+        match *self {
             // the identity factor marginalized over anything is the identity
-            &Factor::Identity => Factor::Identity,
+            Factor::Identity => Factor::Identity,
 
-            &Factor::TableFactor { ref scope, ref table, ..} => {
+            Factor::TableFactor { ref scope, ref table, log, ..} => {
                 if let Some(idx) = scope.iter().position(|&v| v == other) {
-                    let new_table = table.sum_axis(nd::Axis(idx));
+                    let new_table = if log {
+                        table.map_axis(nd::Axis(idx), |lane| log_sum_exp(lane.iter()))
+                    } else {
+                        table.sum_axis(nd::Axis(idx))
+                    };
                     let new_scope = scope.clone().into_iter().filter(|&v| v != other).collect();
 
-                    Factor::make_factor(new_scope, new_table, false).expect(
+                    Factor::make_factor(new_scope, new_table, false, log).expect(
                         "marginalize encountered error that should never occur"
                     )
                 } else {
@@ -383,12 +527,81 @@ impl Factor {
     }
 
 
-    pub fn normalize(&self) -> Self {
-        match self {
-            &Factor::Identity => Factor::Identity,
-            &Factor::TableFactor { ref scope, ref table, .. } => {
+    /// Maximize the `Factor` over the given `Variable`
+    ///
+    /// Defined in Koller & Friedman 13.2.1
+    ///
+    /// # Args
+    /// other: the `Variable` over which to maximize
+    ///
+    /// # Returns
+    /// another `Factor`, in which each value is the maximum value over all values of `other`
+    pub fn max_marginalize(&self, other: Variable) -> Self {
+        match *self {
+            // the identity factor maximized over anything is the identity
+            Factor::Identity => Factor::Identity,
+
+            Factor::TableFactor { ref scope, ref table, log, ..} => {
+                if let Some(idx) = scope.iter().position(|&v| v == other) {
+                    // the logarithm is monotonic, so this is the same in log-space
+                    let new_table = table.fold_axis(nd::Axis(idx), f64::NEG_INFINITY, |&acc, &x| acc.max(x));
+                    let new_scope = scope.clone().into_iter().filter(|&v| v != other).collect();
+
+                    Factor::make_factor(new_scope, new_table, false, log).expect(
+                        "max_marginalize encountered error that should never occur"
+                    )
+                } else {
+                    self.clone()
+                }
+            }
+        }
+    }
+
+
+    /// Marginalize the `Factor` over every `Variable` not in `vars`
+    ///
+    /// # Args
+    /// vars: the `Variable`s to keep
+    ///
+    /// # Returns
+    /// another `Factor`, with scope ```intersection(self.scope(), vars)```
+    ///
+    /// # Notes
+    /// The intersection of ```self.scope()``` and `vars` must not be empty.
+    pub fn marginalize_to(&self, vars: &[Variable]) -> Self {
+        self.scope()
+            .into_iter()
+            .filter(|v| ! vars.contains(v))
+            .fold(self.clone(), |f, v| f.marginalize(v))
+    }
+
+
+    /// Normalize the `Factor` into a distribution over its scope
+    ///
+    /// # Returns
+    /// a new CPD, proportional to the `Factor`, whose values sum to one
+    ///
+    /// # Errors
+    /// * `JeromeError::DivideByZero`, if every value of the `Factor` is zero, as happens when it
+    ///   has been reduced by evidence with probability zero
+    pub fn normalize(&self) -> Result<Self> {
+        match *self {
+            Factor::Identity => Ok(Factor::Identity),
+            Factor::TableFactor { ref scope, ref table, log: false, .. } => {
                 let z = table.scalar_sum();
-                Factor::make_factor(scope.clone(), table / z, true).unwrap()
+                if z == 0. {
+                    return Err(JeromeError::DivideByZero);
+                }
+
+                Factor::make_factor(scope.clone(), table / z, true, false)
+            },
+            Factor::TableFactor { ref scope, ref table, log: true, .. } => {
+                let z = log_sum_exp(table.iter());
+                if z == f64::NEG_INFINITY {
+                    return Err(JeromeError::DivideByZero);
+                }
+
+                Factor::make_factor(scope.clone(), table - z, true, true)
             }
         }
     }
@@ -400,26 +613,20 @@ impl Factor {
     ///
     /// # Args:
     /// * `assignment`: a full assignment to all `Variable`s in scope with the exception of the
-    /// last variable, which is the `Variable` that will be sampled
-    pub fn sample_cpd(&self, assignment: &Assignment) -> Result<usize> {
+    ///   last variable, which is the `Variable` that will be sampled
+    pub fn sample_cpd<A: VariableAssignment>(&self, assignment: &A) -> Result<usize> {
         if ! self.is_cpd() || self.is_identity() {
             panic!("Attempted to sample from a non-CPD");
         }
 
-        if let Factor::TableFactor { ref scope, ref table, .. } = self {
+        if let Factor::TableFactor { ref scope, ref table, log, .. } = self {
             let to_sample = scope.last().unwrap();
-            let idxs: Vec<Option<&usize>> = scope.iter()
-                                                 .filter(|&v| v != to_sample)
-                                                 .map(|v| assignment.get(v))
-                                                 .collect();
-
-            if idxs.iter().any(|i| i.is_none()) {
-                return Err(JeromeError::IncompleteAssignment)
-            } else {
-                let mut idx: Vec<usize> = idxs.into_iter()
-                                          .map(|i| *(i.unwrap()))
-                                          .collect();
+            let idxs: Option<Vec<usize>> = scope.iter()
+                                                .filter(|&v| v != to_sample)
+                                                .map(|v| assignment.value_of(v))
+                                                .collect();
 
+            if let Some(mut idx) = idxs {
                 let between = Range::new(0.0, 1.0);
                 let mut rng = rand::thread_rng();
                 let val = between.ind_sample(&mut rng);
@@ -428,7 +635,8 @@ impl Factor {
                 for i in 0..to_sample.cardinality() {
                     idx.push(i);
 
-                    sum = sum + table[nd::IxDyn(&idx)];
+                    let p = table[nd::IxDyn(&idx)];
+                    sum += if *log { p.exp() } else { p };
                     if val < sum {
                         return Ok(i)
                     }
@@ -436,7 +644,9 @@ impl Factor {
                     idx.pop();
                 }
 
-                return Ok(to_sample.cardinality() - 1);
+                Ok(to_sample.cardinality() - 1)
+            } else {
+                Err(JeromeError::IncompleteAssignment)
             }
         } else {
             panic!("unreachable");
@@ -444,12 +654,97 @@ impl Factor {
     }
 }
 
+
+/// Displays a `Factor` as one line per assignment to its scope; see `Factor::to_labeled_string` to
+/// show each value by its label.
+impl fmt::Display for Factor {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_labeled_string(&Labels::new()))
+    }
+
+}
+
+
+/// Get a value of a `Factor` in log-space, where `log` is `true` if the `Factor` is already in
+/// log-space
+fn as_log(val: f64, log: bool) -> f64 {
+    if log { val } else { val.ln() }
+}
+
+
+/// Compute ```ln(sum_i exp(x_i))``` without underflow, by factoring out the largest value
+pub(crate) fn log_sum_exp<'a, I>(values: I) -> f64
+    where I: Iterator<Item = &'a f64> + Clone
+{
+    let max = values.clone().fold(f64::NEG_INFINITY, |acc, &x| acc.max(x));
+    if max == f64::NEG_INFINITY {
+        // every value is zero
+        return max;
+    }
+
+    max + values.map(|&x| (x - max).exp()).sum::<f64>().ln()
+}
+
 // Unit tests
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants, clippy::nonminimal_bool, clippy::legacy_numeric_constants)]
 mod tests {
     #[cfg(test)]
     use super::*;
     #[cfg(test)]
     use std;
+    use variable::{Assignment, DenseAssignment, VariableIndex};
+    use ndarray_rand::RandomExt;
+    use std::sync::Arc;
+
+    /// Reference implementation of `Factor::product`, computed one assignment at a time
+    fn naive_product(phi1: &Factor, phi2: &Factor) -> Table {
+        let scope: Vec<Variable> = phi1.scope().into_iter().chain(phi2.scope()).unique().collect();
+        let shape: Vec<usize> = scope.iter().map(|v| v.cardinality()).collect();
+
+        let mut tbl = Table::ones(shape);
+        for assn in all_assignments(&scope) {
+            let idx: Vec<usize> = scope.iter().map(|v| *assn.get(v).unwrap()).collect();
+            tbl[nd::IxDyn(&idx)] = phi1.value(&assn).unwrap() * phi2.value(&assn).unwrap();
+        }
+
+        tbl
+    }
+
+    /// Reference implementation of `Factor::divide`, computed one assignment at a time
+    fn naive_divide(phi1: &Factor, phi2: &Factor) -> Result<Table> {
+        let scope = phi1.scope();
+        let shape: Vec<usize> = scope.iter().map(|v| v.cardinality()).collect();
+
+        let mut tbl = Table::ones(shape);
+        for assn in all_assignments(&scope) {
+            let idx: Vec<usize> = scope.iter().map(|v| *assn.get(v).unwrap()).collect();
+            let (x, y) = (phi1.value(&assn).unwrap(), phi2.value(&assn).unwrap());
+            tbl[nd::IxDyn(&idx)] = if y == 0. {
+                if x == 0. { 0. } else { return Err(JeromeError::DivideByZero) }
+            } else {
+                x / y
+            };
+        }
+
+        Ok(tbl)
+    }
+
+    /// A random factor over `scope`, with roughly a quarter of its entries zero
+    fn random_factor(scope: Vec<Variable>) -> Factor {
+        let shape: Vec<usize> = scope.iter().map(|v| v.cardinality()).collect();
+        let tbl = Table::random(shape, Range::new(-0.5, 1.5)).mapv(|x| x.max(0.));
+        Factor::new(scope, tbl).unwrap()
+    }
+
+    /// Assert that the table of a `Factor` matches the given table exactly
+    fn assert_table(expected: &Table, phi: &Factor) {
+        for assn in all_assignments(&phi.scope()) {
+            let idx: Vec<usize> = phi.scope().iter().map(|v| *assn.get(v).unwrap()).collect();
+            assert_eq!(expected[nd::IxDyn(&idx)], phi.value(&assn).unwrap());
+        }
+    }
 
     #[test]
     fn identity() {
@@ -585,6 +880,16 @@ mod tests {
             assert_eq!(i as f64, f.value(&assn).expect("unexpected error"));
         }
 
+        // verify behavior on a dense assignment
+        let index = Arc::new(VariableIndex::new(&[v3, vars[1], vars[0]]));
+        for (i, (x, y)) in (0..2).zip(0..2).enumerate() {
+            let mut assn = DenseAssignment::new(&index);
+            assn.set(&vars[0], x);
+            assn.set(&vars[1], y);
+
+            assert_eq!(i as f64, f.value(&assn).expect("unexpected error"));
+        }
+
         // verify behavior on incomplete assignment
         let mut assn = Assignment::new();
         assn.set(&vars[0], 0);
@@ -992,6 +1297,152 @@ mod tests {
         }
     }
 
+    #[test]
+    /// Example taken from Koller & Friedman Figure 13.1
+    fn max_marginalize() {
+        let a = Variable::discrete(3);
+        let b = Variable::binary();
+        let c = Variable::binary();
+       
+        let table = nd::Array::from_shape_vec(
+            (3, 2, 2), 
+            vec![ 0.25, 0.35, 0.08, 0.16, 0.05, 0.07, 0., 0., 0.15, 0.21, 0.09, 0.18 ]
+        ).expect("Unexpected error").into_dyn();
+
+        let phi = Factor::new(vec![a, b, c], table).expect("Unexpected error");
+
+        let maximized = phi.max_marginalize(b);
+        assert_eq!(vec![a, c], maximized.scope());
+
+        let expected = array![[0.25, 0.35], [0.05, 0.07], [0.15, 0.21]].into_dyn();
+        for (x, y) in iproduct!(0..3, 0..2) {
+            let mut assn = Assignment::new();
+            assn.set(&a, x);
+            assn.set(&c, y);
+
+            let idx = [ x, y ];
+            assert_eq!(expected[nd::IxDyn(&idx)], maximized.value(&assn).unwrap());
+        }
+    }
+
+    #[test]
+    /// Operations on log-space factors must agree with the same operations on linear factors
+    fn log_space() {
+        let a = Variable::discrete(3);
+        let b = Variable::binary();
+        let c = Variable::binary();
+
+        let tbl1 = nd::Array::from_shape_vec((3, 2), vec![ 0.5, 0.8, 0.1, 0., 0.3, 0.9 ])
+            .expect("Unexpected error").into_dyn();
+        let tbl2 = nd::Array::from_shape_vec((2, 2), vec![ 0.5, 0.7, 0.1, 0.2 ])
+            .expect("Unexpected error").into_dyn();
+        let phi1 = Factor::new(vec![ a, b ], tbl1).expect("Unexpected error");
+        let phi2 = Factor::new(vec![ b, c ], tbl2).expect("Unexpected error");
+
+        let log1 = phi1.to_log();
+        assert!(log1.is_log());
+        assert!(! phi1.is_log());
+
+        let assert_same = |linear: &Factor, log: &Factor| {
+            assert!(log.is_log());
+            for assn in all_assignments(&linear.scope()) {
+                let expected = linear.value(&assn).unwrap();
+                assert!((expected - log.value(&assn).unwrap()).abs() < 1e-12);
+                assert!((expected.ln() - log.log_value(&assn).unwrap()).abs() < 1e-12 || expected == 0.);
+            }
+        };
+
+        let product = phi1.product(&phi2).unwrap();
+        assert_same(&product, &log1.product(&phi2.to_log()).unwrap());
+        // mixed products are computed in log-space
+        assert_same(&product, &log1.product(&phi2).unwrap());
+
+        assert_same(&product.marginalize(b), &log1.product(&phi2).unwrap().marginalize(b));
+        assert_same(&product.max_marginalize(a), &product.to_log().max_marginalize(a));
+        assert_same(&phi2.normalize().unwrap(), &phi2.to_log().normalize().unwrap());
+
+        let mut assn = Assignment::new();
+        assn.set(&c, 1);
+        assert_same(&product.reduce(&assn), &product.to_log().reduce(&assn));
+
+        // 0 / 0 is 0, even in log-space
+        let marginal = phi1.marginalize(b);
+        let quotient = phi1.divide(&marginal).unwrap();
+        assert_same(&quotient, &log1.divide(&marginal.to_log()).unwrap());
+        assert_same(&quotient, &phi1.divide(&marginal.to_log()).unwrap());
+        assert!(phi1.to_log().to_linear().value(&assn).is_err());
+
+        let zeros = Factor::new(vec![ a ], array![ 0., 1., 1. ].into_dyn()).unwrap();
+        assert!(phi1.divide(&zeros).is_err());
+        assert!(log1.divide(&zeros.to_log()).is_err());
+        assert!(log1.divide(&zeros).is_err());
+    }
+
+    #[test]
+    /// Values that underflow in linear space are preserved in log-space
+    fn log_space_underflow() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+
+        let table = array![[ -1000., -1001. ], [ -2000., -1000. ]].into_dyn();
+        let phi = Factor::new_log(vec![ a, b ], table).unwrap();
+
+        let mut assn = Assignment::new();
+        assn.set(&a, 0);
+        assert_eq!(0., phi.marginalize(b).value(&assn).unwrap());
+
+        let expected = -1000. + (1. + (-1f64).exp()).ln();
+        assert!((expected - phi.marginalize(b).log_value(&assn).unwrap()).abs() < 1e-10);
+
+        // the normalized factor is well defined
+        let normalized = phi.normalize().unwrap();
+        assn.set(&b, 1);
+        assert!(normalized.value(&assn).unwrap() > 0.1);
+
+        // NaN is never valid
+        assert!(Factor::new_log(vec![ a ], array![ 0., f64::NAN ].into_dyn()).is_err());
+    }
+
+    #[test]
+    /// The broadcast implementations of product and divide must match the per-assignment
+    /// implementations for any alignment of the scopes
+    fn broadcast_equivalence() {
+        let a = Variable::binary();
+        let b = Variable::discrete(3);
+        let c = Variable::discrete(4);
+        let d = Variable::binary();
+        let e = Variable::discrete(5);
+
+        let scopes = vec![
+            (vec![a, b, c], vec![c, d]),
+            (vec![a, b, c], vec![d, c, a]),
+            (vec![c, a], vec![a, b, c, d, e]),
+            (vec![e, d, c, b, a], vec![b, e]),
+            (vec![b], vec![b]),
+        ];
+
+        for (s1, s2) in scopes.into_iter() {
+            let phi1 = random_factor(s1.clone());
+            let phi2 = random_factor(s2.clone());
+
+            let product = phi1.product(&phi2).unwrap();
+            assert_eq!(s1.iter().chain(s2.iter()).cloned().unique().collect::<Vec<Variable>>(), product.scope());
+            assert_table(&naive_product(&phi1, &phi2), &product);
+
+            // the denominator is a marginal of the numerator, so 0 / 0 occurs but X / 0 does not
+            let denominator = product.marginalize_to(&s2);
+            assert_table(&naive_divide(&product, &denominator).unwrap(), &product.divide(&denominator).unwrap());
+
+            // in the other order, X / 0 may occur
+            let numerator = random_factor(product.scope());
+            match (naive_divide(&numerator, &product), numerator.divide(&product)) {
+                (Ok(expected), Ok(actual)) => assert_table(&expected, &actual),
+                (Err(JeromeError::DivideByZero), Err(JeromeError::DivideByZero)) => (),
+                _ => panic!("divide does not match the reference implementation")
+            }
+        }
+    }
+
     #[test]
     fn sample_cpd() {
         let i = Variable::binary();
@@ -1015,4 +1466,24 @@ mod tests {
             assert!(val <= 2);
         }
     }
+
+
+    #[test]
+    fn display() {
+        let weather = Variable::binary();
+        let count = Variable::binary();
+        let mut labels = Labels::new();
+        labels.set(&weather, &["sunny", "rainy"]).unwrap();
+
+        let f = Factor::new(vec![weather, count], array![[0.5, 1.0], [2.0, 0.25]].into_dyn()).unwrap();
+        let expected = format!(
+            "{w} = sunny, {c} = 0: 0.5\n{w} = sunny, {c} = 1: 1\n{w} = rainy, {c} = 0: 2\n{w} = rainy, {c} = 1: 0.25",
+            w = weather,
+            c = count
+        );
+
+        assert_eq!(expected, f.to_labeled_string(&labels));
+        assert_eq!(expected.replace("sunny", "0").replace("rainy", "1"), f.to_string());
+        assert_eq!("Identity", Factor::Identity.to_string());
+    }
 }
@@ -0,0 +1,757 @@
+//! Definition of the gaussian module
+//!
+//! A `LinearGaussian` is the CPD of a continuous `Variable` whose mean is a linear function of its
+//! continuous parents. A `ConditionalLinearGaussian` additionally has discrete parents, which select
+//! among `LinearGaussian`s. A `CanonicalForm` is the factor used to reason about products of
+//! `LinearGaussian`s exactly, as described in Koller & Friedman Section 14.2.1.
+
+use util::{JeromeError, Result};
+use variable::{Variable, VariableAssignment};
+
+use itertools::Itertools;
+use ndarray::prelude as nd;
+use rand;
+use rand::distributions::{IndependentSample, Normal};
+
+use std::f64::consts::PI;
+
+
+/// A linear-Gaussian CPD, ```X | U ~ N(w·u + b, σ²)```.
+///
+/// Defined in Koller & Friedman Definition 7.4
+#[derive(Clone, Debug)]
+pub struct LinearGaussian {
+
+    /// The continuous `Variable` described by the CPD
+    variable: Variable,
+
+    /// The continuous parents of `variable`
+    parents: Vec<Variable>,
+
+    /// The weight of each parent in the mean of `variable`
+    weights: Vec<f64>,
+
+    /// The intercept of the mean of `variable`
+    bias: f64,
+
+    /// The variance of `variable`, which does not depend on the parents
+    variance: f64
+
+}
+
+
+impl LinearGaussian {
+
+    /// Construct a new `LinearGaussian` CPD.
+    ///
+    /// # Args
+    /// * `variable`: the continuous `Variable` described by the CPD
+    /// * `parents`: the continuous parents of `variable`
+    /// * `weights`: the weight of each parent, in the same order as `parents`
+    /// * `bias`: the intercept of the mean
+    /// * `variance`: the variance of `variable` given its parents
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if any `Variable` is discrete, `parents` contains `variable`
+    ///   or a duplicate, or there is not exactly one weight per parent
+    /// * `JeromeError::NonPositiveProbability`, if the variance is not positive
+    pub fn new(
+        variable: Variable,
+        parents: Vec<Variable>,
+        weights: Vec<f64>,
+        bias: f64,
+        variance: f64
+    ) -> Result<Self> {
+        if ! variable.is_continuous()
+            || parents.iter().any(|p| ! p.is_continuous() || *p == variable)
+            || parents.iter().unique().count() != parents.len()
+            || weights.len() != parents.len() {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        if variance.is_nan() || variance <= 0.0 || variance.is_infinite() {
+            return Err(JeromeError::NonPositiveProbability);
+        }
+
+        Ok(LinearGaussian { variable, parents, weights, bias, variance })
+    }
+
+    /// Construct a `LinearGaussian` CPD with no parents, i.e. ```X ~ N(mean, variance)```
+    ///
+    /// # Errors
+    /// see `LinearGaussian::new`
+    pub fn normal(variable: Variable, mean: f64, variance: f64) -> Result<Self> {
+        LinearGaussian::new(variable, Vec::new(), Vec::new(), mean, variance)
+    }
+
+    /// Get the `Variable` described by the CPD
+    pub fn variable(&self) -> Variable {
+        self.variable
+    }
+
+    /// Get the parents of the `Variable`
+    pub fn parents(&self) -> &[Variable] {
+        &self.parents
+    }
+
+    /// Get the weight of each parent
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    /// Get the intercept of the mean
+    pub fn bias(&self) -> f64 {
+        self.bias
+    }
+
+    /// Get the variance
+    pub fn variance(&self) -> f64 {
+        self.variance
+    }
+
+    /// Get the scope of the CPD. As with a table CPD, the `Variable` is last.
+    pub fn scope(&self) -> Vec<Variable> {
+        let mut scope = self.parents.clone();
+        scope.push(self.variable);
+        scope
+    }
+
+    /// Compute the mean of the `Variable` given an assignment to its parents
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if a parent is not assigned
+    pub fn mean<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        self.parents
+            .iter()
+            .zip(self.weights.iter())
+            .try_fold(self.bias, |mean, (p, w)| {
+                assignment.continuous_value_of(p)
+                          .map(|u| mean + w * u)
+                          .ok_or(JeromeError::IncompleteAssignment)
+            })
+    }
+
+    /// Compute the natural logarithm of the density of a full assignment to the scope of the CPD
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if the assignment is not complete over the scope
+    pub fn log_density<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        let x = assignment.continuous_value_of(&self.variable)
+                          .ok_or(JeromeError::IncompleteAssignment)?;
+        let mean = self.mean(assignment)?;
+
+        Ok(-0.5 * ((2.0 * PI * self.variance).ln() + (x - mean).powi(2) / self.variance))
+    }
+
+    /// Compute the density of a full assignment to the scope of the CPD
+    ///
+    /// # Errors
+    /// see `LinearGaussian::log_density`
+    pub fn density<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        self.log_density(assignment).map(f64::exp)
+    }
+
+    /// Draw a value of the `Variable` given an assignment to its parents
+    ///
+    /// # Errors
+    /// see `LinearGaussian::mean`
+    pub fn sample<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        let mean = self.mean(assignment)?;
+        let normal = Normal::new(mean, self.variance.sqrt());
+        Ok(normal.ind_sample(&mut rand::thread_rng()))
+    }
+
+    /// Reduce the CPD by the parents observed in the assignment, folding their contribution into the
+    /// bias. Any assignment to the `Variable` itself is ignored.
+    pub fn reduce<A: VariableAssignment>(&self, assignment: &A) -> Self {
+        let mut reduced = LinearGaussian {
+            variable: self.variable,
+            parents: Vec::new(),
+            weights: Vec::new(),
+            bias: self.bias,
+            variance: self.variance
+        };
+
+        for (&p, &w) in self.parents.iter().zip(self.weights.iter()) {
+            match assignment.continuous_value_of(&p) {
+                Some(u) => reduced.bias += w * u,
+                None => {
+                    reduced.parents.push(p);
+                    reduced.weights.push(w);
+                }
+            }
+        }
+
+        reduced
+    }
+
+    /// Convert the CPD to a `CanonicalForm` over its scope.
+    ///
+    /// Writing the CPD as ```exp(-(a'z - b)² / 2σ²) / sqrt(2πσ²)``` with ```z = (u, x)``` and
+    /// ```a = (-w, 1)``` gives ```K = aa' / σ²```, ```h = ab / σ²``` and
+    /// ```g = -b² / 2σ² - ln(2πσ²) / 2```.
+    pub fn to_canonical(&self) -> CanonicalForm {
+        let a: Vec<f64> = self.weights.iter().map(|w| -w).chain(Some(1.0)).collect();
+        let n = a.len();
+
+        let k = nd::Array2::from_shape_fn((n, n), |(i, j)| a[i] * a[j] / self.variance);
+        let h = nd::Array1::from_shape_fn(n, |i| a[i] * self.bias / self.variance);
+        let g = -self.bias.powi(2) / (2.0 * self.variance) - 0.5 * (2.0 * PI * self.variance).ln();
+
+        CanonicalForm { scope: self.scope(), k, h, g }
+    }
+
+}
+
+
+/// A conditional linear-Gaussian CPD: a continuous `Variable` with discrete parents ```D``` and
+/// continuous parents ```U```, where each assignment ```d``` selects a `LinearGaussian`
+/// ```X | U, D = d ~ N(w_d·u + b_d, σ²_d)```.
+///
+/// Defined in Koller & Friedman Definition 5.15
+#[derive(Clone, Debug)]
+pub struct ConditionalLinearGaussian {
+
+    /// The continuous `Variable` described by the CPD
+    variable: Variable,
+
+    /// The discrete parents of `variable`
+    discrete_parents: Vec<Variable>,
+
+    /// The `LinearGaussian` for each assignment to `discrete_parents`, laid out like a table
+    /// `Factor` over `discrete_parents` (i.e. the last parent varies fastest)
+    regressions: Vec<LinearGaussian>
+
+}
+
+
+impl ConditionalLinearGaussian {
+
+    /// Construct a new `ConditionalLinearGaussian` CPD.
+    ///
+    /// # Args
+    /// * `variable`: the continuous `Variable` described by the CPD
+    /// * `discrete_parents`: the discrete parents of `variable`
+    /// * `regressions`: one `LinearGaussian` CPD of `variable` per assignment to
+    ///   `discrete_parents`, with the last parent varying fastest. Each must have the same
+    ///   continuous parents.
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if `discrete_parents` has a continuous or duplicate
+    ///   `Variable`, there is not one regression per assignment to `discrete_parents`, or the
+    ///   regressions disagree on the `Variable` or its continuous parents
+    pub fn new(
+        variable: Variable,
+        discrete_parents: Vec<Variable>,
+        regressions: Vec<LinearGaussian>
+    ) -> Result<Self> {
+        let num_assignments: usize = discrete_parents.iter().map(|v| v.cardinality()).product();
+
+        if discrete_parents.iter().any(|v| v.is_continuous())
+            || discrete_parents.iter().unique().count() != discrete_parents.len()
+            || regressions.len() != num_assignments {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        let first = &regressions[0];
+        let consistent = regressions.iter().all(|r| {
+            r.variable == variable
+                && r.parents.len() == first.parents.len()
+                && r.parents.iter().all(|p| first.parents.contains(p))
+        });
+
+        if ! consistent {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        Ok(ConditionalLinearGaussian { variable, discrete_parents, regressions })
+    }
+
+    /// Get the `Variable` described by the CPD
+    pub fn variable(&self) -> Variable {
+        self.variable
+    }
+
+    /// Get the discrete parents of the `Variable`
+    pub fn discrete_parents(&self) -> &[Variable] {
+        &self.discrete_parents
+    }
+
+    /// Get the continuous parents of the `Variable`
+    pub fn continuous_parents(&self) -> &[Variable] {
+        self.regressions[0].parents()
+    }
+
+    /// Get the `LinearGaussian` for each assignment to the discrete parents
+    pub fn regressions(&self) -> &[LinearGaussian] {
+        &self.regressions
+    }
+
+    /// Get the `LinearGaussian` if the CPD has no discrete parents
+    pub fn as_linear_gaussian(&self) -> Option<&LinearGaussian> {
+        if self.discrete_parents.is_empty() {
+            Some(&self.regressions[0])
+        } else {
+            None
+        }
+    }
+
+    /// Get the scope of the CPD: the discrete parents, then the continuous parents, then the
+    /// `Variable`
+    pub fn scope(&self) -> Vec<Variable> {
+        let mut scope = self.discrete_parents.clone();
+        scope.extend(self.regressions[0].scope());
+        scope
+    }
+
+    /// Get the `LinearGaussian` selected by an assignment to the discrete parents
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if a discrete parent is not assigned
+    pub fn regression<A: VariableAssignment>(&self, assignment: &A) -> Result<&LinearGaussian> {
+        let idx = self.discrete_parents
+                      .iter()
+                      .try_fold(0, |idx, v| assignment.value_of(v).map(|val| idx * v.cardinality() + val))
+                      .ok_or(JeromeError::IncompleteAssignment)?;
+
+        Ok(&self.regressions[idx])
+    }
+
+    /// Compute the natural logarithm of the density of a full assignment to the scope of the CPD
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if the assignment is not complete over the scope
+    pub fn log_density<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        self.regression(assignment)?.log_density(assignment)
+    }
+
+    /// Compute the density of a full assignment to the scope of the CPD
+    ///
+    /// # Errors
+    /// see `ConditionalLinearGaussian::log_density`
+    pub fn density<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        self.log_density(assignment).map(f64::exp)
+    }
+
+    /// Draw a value of the `Variable` given an assignment to its parents
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if a parent is not assigned
+    pub fn sample<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        self.regression(assignment)?.sample(assignment)
+    }
+
+    /// Reduce the CPD by the parents observed in the assignment. Observed discrete parents select
+    /// a subset of the regressions, and observed continuous parents are folded into the bias of
+    /// each regression.
+    pub fn reduce<A: VariableAssignment>(&self, assignment: &A) -> Self {
+        let observed: Vec<Option<usize>> = self.discrete_parents.iter().map(|v| assignment.value_of(v)).collect();
+
+        let regressions = self.regressions
+                              .iter()
+                              .enumerate()
+                              .filter(|&(idx, _)| {
+                                  // decode the assignment to the discrete parents at idx
+                                  let mut rest = idx;
+                                  let mut keep = true;
+                                  for (v, obs) in self.discrete_parents.iter().zip(observed.iter()).rev() {
+                                      let val = rest % v.cardinality();
+                                      rest /= v.cardinality();
+                                      keep &= obs.is_none_or(|o| o == val);
+                                  }
+
+                                  keep
+                              })
+                              .map(|(_, r)| r.reduce(assignment))
+                              .collect();
+
+        let discrete_parents = self.discrete_parents
+                                   .iter()
+                                   .zip(observed.iter())
+                                   .filter(|&(_, obs)| obs.is_none())
+                                   .map(|(&v, _)| v)
+                                   .collect();
+
+        ConditionalLinearGaussian { variable: self.variable, discrete_parents, regressions }
+    }
+
+}
+
+
+impl From<LinearGaussian> for ConditionalLinearGaussian {
+
+    fn from(cpd: LinearGaussian) -> Self {
+        ConditionalLinearGaussian { variable: cpd.variable, discrete_parents: Vec::new(), regressions: vec![cpd] }
+    }
+
+}
+
+
+/// A canonical form ```C(X; K, h, g) = exp(-X'KX / 2 + h'X + g)``` over continuous `Variable`s.
+///
+/// Defined in Koller & Friedman Section 14.2.1.1
+#[derive(Clone, Debug)]
+pub struct CanonicalForm {
+
+    /// The scope of the `CanonicalForm`
+    scope: Vec<Variable>,
+
+    /// The quadratic term, indexed by the positions of `scope`
+    k: nd::Array2<f64>,
+
+    /// The linear term, indexed by the positions of `scope`
+    h: nd::Array1<f64>,
+
+    /// The constant term
+    g: f64
+
+}
+
+
+impl CanonicalForm {
+
+    /// Construct a new `CanonicalForm`
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if the scope has a discrete or duplicate `Variable`, or the
+    ///   shapes of `k` and `h` do not match the scope
+    pub fn new(scope: Vec<Variable>, k: nd::Array2<f64>, h: nd::Array1<f64>, g: f64) -> Result<Self> {
+        let n = scope.len();
+        if scope.iter().any(|v| ! v.is_continuous())
+            || scope.iter().unique().count() != n
+            || k.shape() != [n, n]
+            || h.len() != n {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        Ok(CanonicalForm { scope, k, h, g })
+    }
+
+    /// Construct the vacuous `CanonicalForm`, which has an empty scope and is the identity of
+    /// `CanonicalForm::product`
+    pub fn vacuous() -> Self {
+        CanonicalForm { scope: Vec::new(), k: nd::Array2::zeros((0, 0)), h: nd::Array1::zeros(0), g: 0.0 }
+    }
+
+    /// Get the scope of the `CanonicalForm`
+    pub fn scope(&self) -> Vec<Variable> {
+        self.scope.clone()
+    }
+
+    /// Get the quadratic term
+    pub fn k(&self) -> &nd::Array2<f64> {
+        &self.k
+    }
+
+    /// Get the linear term
+    pub fn h(&self) -> &nd::Array1<f64> {
+        &self.h
+    }
+
+    /// Get the constant term
+    pub fn g(&self) -> f64 {
+        self.g
+    }
+
+    /// Extend the `CanonicalForm` to a superset of its scope, in the given order
+    pub(crate) fn extend(&self, scope: &[Variable]) -> Self {
+        let positions: Vec<Option<usize>> = scope.iter()
+                                                 .map(|v| self.scope.iter().position(|s| s == v))
+                                                 .collect();
+        let n = scope.len();
+
+        let k = nd::Array2::from_shape_fn((n, n), |(i, j)| {
+            match (positions[i], positions[j]) {
+                (Some(a), Some(b)) => self.k[[a, b]],
+                _ => 0.0
+            }
+        });
+        let h = nd::Array1::from_shape_fn(n, |i| positions[i].map_or(0.0, |a| self.h[a]));
+
+        CanonicalForm { scope: scope.to_vec(), k, h, g: self.g }
+    }
+
+    /// Product of this `CanonicalForm` and another. Unlike `Factor::product`, the scopes need not
+    /// intersect.
+    ///
+    /// Defined in Koller & Friedman Section 14.2.1.2
+    pub fn product(&self, other: &Self) -> Self {
+        let scope: Vec<Variable> = self.scope.iter().chain(other.scope.iter()).unique().cloned().collect();
+
+        let lhs = self.extend(&scope);
+        let rhs = other.extend(&scope);
+
+        CanonicalForm { scope, k: lhs.k + rhs.k, h: lhs.h + rhs.h, g: self.g + other.g }
+    }
+
+    /// Reduce the `CanonicalForm` by the continuous values in the assignment
+    ///
+    /// Defined in Koller & Friedman Section 14.2.1.2
+    pub fn reduce<A: VariableAssignment>(&self, assignment: &A) -> Self {
+        let values: Vec<Option<f64>> = self.scope.iter().map(|v| assignment.continuous_value_of(v)).collect();
+        let keep: Vec<usize> = (0..self.scope.len()).filter(|&i| values[i].is_none()).collect();
+
+        if keep.len() == self.scope.len() {
+            return self.clone();
+        }
+
+        let observed: Vec<(usize, f64)> = values.iter()
+                                                .enumerate()
+                                                .filter_map(|(i, y)| y.map(|y| (i, y)))
+                                                .collect();
+
+        let k = nd::Array2::from_shape_fn((keep.len(), keep.len()), |(i, j)| self.k[[keep[i], keep[j]]]);
+        let h = nd::Array1::from_shape_fn(keep.len(), |i| {
+            self.h[keep[i]] - observed.iter().map(|&(j, y)| self.k[[keep[i], j]] * y).sum::<f64>()
+        });
+
+        let mut g = self.g;
+        for &(i, y_i) in observed.iter() {
+            g += self.h[i] * y_i;
+            for &(j, y_j) in observed.iter() {
+                g -= 0.5 * y_i * self.k[[i, j]] * y_j;
+            }
+        }
+
+        CanonicalForm { scope: keep.iter().map(|&i| self.scope[i]).collect(), k, h, g }
+    }
+
+    /// Marginalize the `CanonicalForm` over the given `Variable`
+    ///
+    /// Defined in Koller & Friedman Section 14.2.1.2
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if the `Variable` is not in the scope
+    /// * `JeromeError::NonPositiveProbability`, if the `CanonicalForm` cannot be integrated over the
+    ///   `Variable`, i.e. its diagonal entry of ```K``` is not positive
+    pub fn marginalize(&self, v: Variable) -> Result<Self> {
+        let y = self.scope.iter().position(|&s| s == v).ok_or(JeromeError::InvalidScope)?;
+        let k_yy = self.k[[y, y]];
+        let h_y = self.h[y];
+
+        if k_yy.is_nan() || k_yy <= 0.0 {
+            return Err(JeromeError::NonPositiveProbability);
+        }
+
+        let keep: Vec<usize> = (0..self.scope.len()).filter(|&i| i != y).collect();
+
+        let k = nd::Array2::from_shape_fn((keep.len(), keep.len()), |(i, j)| {
+            self.k[[keep[i], keep[j]]] - self.k[[keep[i], y]] * self.k[[y, keep[j]]] / k_yy
+        });
+        let h = nd::Array1::from_shape_fn(keep.len(), |i| self.h[keep[i]] - self.k[[keep[i], y]] * h_y / k_yy);
+        let g = self.g + 0.5 * ((2.0 * PI / k_yy).ln() + h_y * h_y / k_yy);
+
+        Ok(CanonicalForm { scope: keep.iter().map(|&i| self.scope[i]).collect(), k, h, g })
+    }
+
+    /// Normalize the `CanonicalForm` so that it integrates to 1 over its scope
+    ///
+    /// # Errors
+    /// see `CanonicalForm::marginalize`
+    pub fn normalize(&self) -> Result<Self> {
+        let log_z = self.scope.iter().try_fold(self.clone(), |c, &v| c.marginalize(v))?.g;
+        Ok(CanonicalForm { g: self.g - log_z, ..self.clone() })
+    }
+
+    /// Compute the natural logarithm of the value of a full assignment to the scope
+    ///
+    /// # Errors
+    /// * `JeromeError::IncompleteAssignment`, if the assignment is not complete over the scope
+    pub fn log_value<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        let x: Option<Vec<f64>> = self.scope.iter().map(|v| assignment.continuous_value_of(v)).collect();
+        let x = nd::Array1::from_vec(x.ok_or(JeromeError::IncompleteAssignment)?);
+
+        Ok(-0.5 * x.dot(&self.k.dot(&x)) + self.h.dot(&x) + self.g)
+    }
+
+    /// Compute the value of a full assignment to the scope
+    ///
+    /// # Errors
+    /// see `CanonicalForm::log_value`
+    pub fn value<A: VariableAssignment>(&self, assignment: &A) -> Result<f64> {
+        self.log_value(assignment).map(f64::exp)
+    }
+
+    /// Compute the covariance ```K⁻¹``` of the Gaussian represented by this `CanonicalForm`,
+    /// indexed by the positions of the scope
+    ///
+    /// # Errors
+    /// * `JeromeError::General`, if ```K``` is singular
+    pub fn covariance(&self) -> Result<nd::Array2<f64>> {
+        invert(&self.k)
+    }
+
+    /// Compute the mean ```K⁻¹h``` of the Gaussian represented by this `CanonicalForm`, indexed
+    /// by the positions of the scope
+    ///
+    /// # Errors
+    /// see `CanonicalForm::covariance`
+    pub fn mean(&self) -> Result<nd::Array1<f64>> {
+        Ok(self.covariance()?.dot(&self.h))
+    }
+
+}
+
+
+/// Invert a square matrix by Gauss-Jordan elimination with partial pivoting
+fn invert(m: &nd::Array2<f64>) -> Result<nd::Array2<f64>> {
+    let n = m.rows();
+    let mut a = m.clone();
+    let mut inv = nd::Array2::eye(n);
+
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[[i, col]].abs().partial_cmp(&a[[j, col]].abs()).unwrap())
+                            .unwrap();
+
+        if a[[pivot, col]].abs() < f64::EPSILON {
+            return Err(JeromeError::General(String::from("Error - matrix is singular")));
+        }
+
+        for j in 0..n {
+            a.swap([col, j], [pivot, j]);
+            inv.swap([col, j], [pivot, j]);
+        }
+
+        let p = a[[col, col]];
+        for j in 0..n {
+            a[[col, j]] /= p;
+            inv[[col, j]] /= p;
+        }
+
+        for i in (0..n).filter(|&i| i != col) {
+            let factor = a[[i, col]];
+            for j in 0..n {
+                a[[i, j]] -= factor * a[[col, j]];
+                inv[[i, j]] -= factor * inv[[col, j]];
+            }
+        }
+    }
+
+    Ok(inv)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use variable::Assignment;
+
+    fn assert_close(expected: f64, actual: f64) {
+        assert!((expected - actual).abs() < 1e-9, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn linear_gaussian() {
+        let u = Variable::continuous();
+        let x = Variable::continuous();
+        let d = Variable::binary();
+
+        assert!(LinearGaussian::new(x, vec![d], vec![1.0], 0.0, 1.0).is_err());
+        assert!(LinearGaussian::new(x, vec![u], vec![], 0.0, 1.0).is_err());
+        assert!(LinearGaussian::new(d, vec![], vec![], 0.0, 1.0).is_err());
+        assert!(LinearGaussian::normal(x, 0.0, 0.0).is_err());
+
+        // X | U ~ N(2u + 3, 4)
+        let cpd = LinearGaussian::new(x, vec![u], vec![2.0], 3.0, 4.0).unwrap();
+
+        let mut a = Assignment::new();
+        a.set_continuous(&u, 1.0);
+        assert!(cpd.density(&a).is_err());
+        assert_close(5.0, cpd.mean(&a).unwrap());
+
+        a.set_continuous(&x, 6.0);
+        let expected = (-1.0f64 / 8.0).exp() / (8.0 * PI).sqrt();
+        assert_close(expected, cpd.density(&a).unwrap());
+        assert_close(expected.ln(), cpd.to_canonical().log_value(&a).unwrap());
+
+        let reduced = cpd.reduce(&a);
+        assert!(reduced.parents().is_empty());
+        assert_close(5.0, reduced.bias());
+        assert_close(expected, reduced.density(&a).unwrap());
+
+        let samples: Vec<f64> = (0..5000).map(|_| cpd.sample(&a).unwrap()).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        assert!((mean - 5.0).abs() < 0.2);
+    }
+
+    /// X ~ N(1, 4), Y | X ~ N(2x + 3, 1)
+    #[test]
+    fn canonical_form() {
+        let x = Variable::continuous();
+        let y = Variable::continuous();
+
+        let cpd_x = LinearGaussian::normal(x, 1.0, 4.0).unwrap();
+        let cpd_y = LinearGaussian::new(y, vec![x], vec![2.0], 3.0, 1.0).unwrap();
+
+        let joint = cpd_x.to_canonical().product(&cpd_y.to_canonical());
+        assert_eq!(vec![x, y], joint.scope());
+
+        // the joint has mean (1, 5) and covariance [[4, 8], [8, 17]]
+        let mean = joint.mean().unwrap();
+        let cov = joint.covariance().unwrap();
+        assert_close(1.0, mean[0]);
+        assert_close(5.0, mean[1]);
+        assert_close(4.0, cov[[0, 0]]);
+        assert_close(8.0, cov[[0, 1]]);
+        assert_close(17.0, cov[[1, 1]]);
+
+        // the joint is normalized, so marginalizing everything leaves g = ln(1)
+        let y_marginal = joint.marginalize(x).unwrap();
+        assert_close(5.0, y_marginal.mean().unwrap()[0]);
+        assert_close(17.0, y_marginal.covariance().unwrap()[[0, 0]]);
+        assert_close(0.0, y_marginal.marginalize(y).unwrap().g());
+
+        // conditioning on Y = 7 gives X ~ N(1 + 8/17 * 2, 4 - 64/17)
+        let mut evidence = Assignment::new();
+        evidence.set_continuous(&y, 7.0);
+        let posterior = joint.reduce(&evidence);
+        assert_eq!(vec![x], posterior.scope());
+        assert_close(1.0 + 16.0 / 17.0, posterior.mean().unwrap()[0]);
+        assert_close(4.0 - 64.0 / 17.0, posterior.covariance().unwrap()[[0, 0]]);
+
+        // and what is left after marginalizing is the density of the evidence
+        let p_y = (-(2.0f64).powi(2) / 34.0).exp() / (34.0 * PI).sqrt();
+        assert_close(p_y.ln(), posterior.marginalize(x).unwrap().g());
+
+        assert!(joint.marginalize(Variable::continuous()).is_err());
+        assert!(CanonicalForm::new(vec![x], nd::Array2::zeros((2, 2)), nd::Array1::zeros(1), 0.0).is_err());
+        assert!(CanonicalForm::new(vec![x, y], nd::Array2::zeros((2, 2)), nd::Array1::zeros(2), 0.0).unwrap().mean().is_err());
+    }
+
+    /// X | D, U ~ N(u + 1, 1) if D = 0, N(-u, 4) if D = 1
+    #[test]
+    fn conditional_linear_gaussian() {
+        let d = Variable::binary();
+        let u = Variable::continuous();
+        let x = Variable::continuous();
+
+        let r0 = LinearGaussian::new(x, vec![u], vec![1.0], 1.0, 1.0).unwrap();
+        let r1 = LinearGaussian::new(x, vec![u], vec![-1.0], 0.0, 4.0).unwrap();
+        let other = LinearGaussian::normal(x, 0.0, 1.0).unwrap();
+
+        assert!(ConditionalLinearGaussian::new(x, vec![d], vec![r0.clone()]).is_err());
+        assert!(ConditionalLinearGaussian::new(x, vec![u], vec![r0.clone()]).is_err());
+        assert!(ConditionalLinearGaussian::new(x, vec![d], vec![r0.clone(), other]).is_err());
+
+        let cpd = ConditionalLinearGaussian::new(x, vec![d], vec![r0.clone(), r1.clone()]).unwrap();
+        assert_eq!(vec![d, u, x], cpd.scope());
+        assert!(cpd.as_linear_gaussian().is_none());
+
+        let mut a = Assignment::new();
+        a.set_continuous(&u, 2.0);
+        a.set_continuous(&x, 1.0);
+        assert!(cpd.density(&a).is_err());
+
+        a.set(&d, 1);
+        assert_close(r1.density(&a).unwrap(), cpd.density(&a).unwrap());
+        a.set(&d, 0);
+        assert_close(r0.density(&a).unwrap(), cpd.density(&a).unwrap());
+
+        // observing D leaves a single regression, observing U folds it into the bias
+        let reduced = cpd.reduce(&a);
+        assert!(reduced.discrete_parents().is_empty());
+        assert!(reduced.continuous_parents().is_empty());
+        assert_close(3.0, reduced.as_linear_gaussian().unwrap().bias());
+    }
+}
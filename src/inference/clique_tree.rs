@@ -0,0 +1,457 @@
+//! Defines a `ConditionalInferenceEngine` that uses exact inference by message passing over a
+//! calibrated clique tree to answer conditional inference queries.
+//!
+//! Unlike the `VariableEliminationEngine`, the clique tree is calibrated once, when the engine is
+//! constructed. Any query over a set of `Variable`s that is contained in a single clique is then
+//! answered directly from the calibrated beliefs.
+//!
+//! Implementation of Koller & Friedman Section 10.4.1 (clique trees from variable elimination),
+//! Algorithm 10.2 (Sum-Product message passing for calibrating a clique tree) and Section 10.3.3.2
+//! (out-of-clique queries)
+
+use factor::Factor;
+use model::Model;
+use model::directed::DirectedModel;
+use model::graph::UndirectedGraph;
+use model::undirected::UndirectedModel;
+use super::ConditionalInferenceEngine;
+use super::elimination_ordering::{EliminationOrdering, MaxCardinality};
+use super::variable_elimination::{log_observed, sum_product_ve};
+use util::{JeromeError, Result};
+use variable::{Assignment, Variable};
+
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+
+pub struct CliqueTreeEngine {
+
+    /// The `Variable`s of the model, once reduced by the evidence
+    variables: HashSet<Variable>,
+
+    /// The elimination order used to construct the clique tree
+    order: Vec<Variable>,
+
+    /// The scope of each clique in the tree
+    cliques: Vec<Vec<Variable>>,
+
+    /// The neighbors of each clique in the tree. Note that the 'tree' is in fact a forest if the
+    /// reduced model is not connected.
+    neighbors: Vec<Vec<usize>>,
+
+    /// The calibrated belief of each clique
+    beliefs: Vec<Factor>,
+
+    /// The calibrated belief of each sepset, keyed by the (ordered) pair of cliques it joins
+    sepsets: HashMap<(usize, usize), Factor>,
+
+    /// An error encountered during calibration, which is reported by `infer`
+    err: Option<JeromeError>
+
+}
+
+
+impl CliqueTreeEngine {
+
+    /// Construct the engine for a `DirectedModel`, by converting it to an `UndirectedModel`.
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if the model has a continuous `Variable`
+    pub fn for_directed(model: &DirectedModel, evidence: &Assignment) -> Result<Self> {
+        Ok(CliqueTreeEngine::for_undirected(
+            &UndirectedModel::decomposed(model)?,
+            evidence
+        ))
+    }
+
+    pub fn for_undirected(model: &UndirectedModel, evidence: &Assignment) -> Self {
+        // reduce the provided model with the evidence - this is the model we will calibrate
+        let reduced = model.condition(evidence);
+        let order = MaxCardinality.order(&reduced);
+
+        // invariant: the order produced by the heuristic contains every variable exactly once
+        let cliques = UndirectedGraph::from(&reduced).induced_cliques(&order).unwrap();
+        let neighbors = max_spanning_tree(&cliques);
+
+        ///////////////////////////////////////////////////////////////////////
+        // 1) Assign each factor to a clique that contains its scope. Starting from a unit factor
+        //    over the clique ensures the product is defined even if the assigned factors do not
+        //    share any variables.
+        let mut potentials: Vec<Factor> = cliques.iter()
+                                                 .map(|c| Factor::ones(c.clone()).unwrap())
+                                                 .collect();
+
+        for f in reduced.factors().iter() {
+            let scope = f.scope();
+
+            // invariant: the cliques induced by variable elimination cover every factor
+            let idx = cliques.iter()
+                             .position(|c| scope.iter().all(|v| c.contains(v)))
+                             .expect("factor not covered by any clique");
+
+            potentials[idx] = potentials[idx].product(f).unwrap();
+        }
+
+        ///////////////////////////////////////////////////////////////////////
+        // 2) Calibrate the tree. Each tree in the forest is rooted at its lowest index clique; an
+        //    upward pass sends messages from the leaves to the root, and a downward pass sends
+        //    them back out to the leaves.
+        let mut messages: HashMap<(usize, usize), Factor> = HashMap::new();
+
+        let mut visited = vec![false; cliques.len()];
+        for root in 0..cliques.len() {
+            if visited[root] {
+                continue;
+            }
+
+            let (traversal, parent) = traverse(&neighbors, root);
+            for &i in traversal.iter() {
+                visited[i] = true;
+            }
+
+            for &i in traversal.iter().rev() {
+                if let Some(j) = parent[&i] {
+                    let delta = message(&potentials, &neighbors, &messages, &cliques, i, j);
+                    messages.insert((i, j), delta);
+                }
+            }
+
+            for &i in traversal.iter() {
+                for &j in neighbors[i].iter().filter(|&&j| parent[&i] != Some(j)) {
+                    let delta = message(&potentials, &neighbors, &messages, &cliques, i, j);
+                    messages.insert((i, j), delta);
+                }
+            }
+        }
+
+        ///////////////////////////////////////////////////////////////////////
+        // 3) Compute the calibrated beliefs of the cliques and sepsets
+        let beliefs: Vec<Factor> = (0..cliques.len())
+            .map(|i| {
+                neighbors[i].iter()
+                            .fold(potentials[i].clone(), |acc, &k| acc.product(&messages[&(k, i)]).unwrap())
+            })
+            .collect();
+
+        let mut sepsets = HashMap::new();
+        for (i, adj) in neighbors.iter().enumerate() {
+            for &j in adj.iter().filter(|&&j| i < j) {
+                let mu = messages[&(i, j)].product(&messages[&(j, i)]).unwrap();
+                sepsets.insert((i, j), mu);
+            }
+        }
+
+        // the evidence has probability zero if a fully observed factor is zero, or if the beliefs
+        // of any tree in the forest are zero everywhere
+        let err = if log_observed(model, evidence) == f64::NEG_INFINITY || beliefs.iter().any(|b| b.normalize().is_err()) {
+            Some(JeromeError::DivideByZero)
+        } else {
+            None
+        };
+
+        CliqueTreeEngine {
+            variables: reduced.variables(),
+            order,
+            cliques,
+            neighbors,
+            beliefs,
+            sepsets,
+            err
+        }
+    }
+
+
+    /// Answer a query whose `Variable`s are not contained in a single clique.
+    ///
+    /// The distribution over the smallest subtree containing the query is represented by the
+    /// calibrated beliefs as ```prod_i beta_i / prod_(i-j) mu_i,j```, from which the query is
+    /// computed by variable elimination.
+    fn out_of_clique(&self, variables: &HashSet<Variable>) -> Result<Factor> {
+        // the cliques that must be kept - one for each variable in the query
+        let required: HashSet<usize> = variables.iter()
+                                                .map(|v| self.cliques.iter().position(|c| c.contains(v)).unwrap())
+                                                .collect();
+
+        // prune leaves that are not required until the minimal subtree(s) remain
+        let mut keep: HashSet<usize> = required.iter()
+                                               .flat_map(|&i| traverse(&self.neighbors, i).0)
+                                               .collect();
+        loop {
+            let leaf = keep.iter()
+                           .cloned()
+                           .find(|&i| {
+                               ! required.contains(&i) &&
+                               self.neighbors[i].iter().filter(|k| keep.contains(k)).count() <= 1
+                           });
+
+            match leaf {
+                Some(i) => { keep.remove(&i); },
+                None => break
+            }
+        }
+
+        // each clique in the subtree contributes its belief, divided by the belief of the sepset
+        // joining it to its parent
+        let mut factors = Vec::new();
+        let mut visited = HashSet::new();
+        let mut roots: Vec<usize> = keep.iter().cloned().collect();
+        roots.sort();
+
+        for root in roots {
+            if visited.contains(&root) {
+                continue;
+            }
+
+            let mut stack = vec![(root, None)];
+            while let Some((i, parent)) = stack.pop() {
+                visited.insert(i);
+
+                let factor = match parent {
+                    None => self.beliefs[i].clone(),
+                    Some(j) => {
+                        let key = if i < j { (i, j) } else { (j, i) };
+                        self.beliefs[i].divide(&self.sepsets[&key]).unwrap()
+                    }
+                };
+                factors.push(factor);
+
+                for &k in self.neighbors[i].iter() {
+                    if keep.contains(&k) && Some(k) != parent {
+                        stack.push((k, Some(i)));
+                    }
+                }
+            }
+        }
+
+        sum_product_ve(factors, &self.order, variables)
+    }
+
+}
+
+
+/// Connect the cliques by a maximum spanning tree, where the weight of an edge is the size of the
+/// sepset. Cliques with no shared `Variable`s are never connected.
+///
+/// # Returns
+/// the neighbors of each clique
+fn max_spanning_tree(cliques: &[Vec<Variable>]) -> Vec<Vec<usize>> {
+    let mut edges: Vec<(usize, usize, usize)> = Vec::new();
+    for i in 0..cliques.len() {
+        for j in (i + 1)..cliques.len() {
+            let weight = cliques[i].iter().filter(|v| cliques[j].contains(v)).count();
+            if weight > 0 {
+                edges.push((weight, i, j));
+            }
+        }
+    }
+
+    // Kruskal's algorithm - heaviest edges first, ties broken by clique index
+    edges.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+    let mut component: Vec<usize> = (0..cliques.len()).collect();
+    let mut neighbors = vec![Vec::new(); cliques.len()];
+
+    for (_, i, j) in edges {
+        let (ci, cj) = (component[i], component[j]);
+        if ci == cj {
+            continue;
+        }
+
+        for c in component.iter_mut().filter(|c| **c == cj) {
+            *c = ci;
+        }
+
+        neighbors[i].push(j);
+        neighbors[j].push(i);
+    }
+
+    neighbors
+}
+
+
+/// Traverse the tree containing `root` in breadth-first order.
+///
+/// # Returns
+/// the cliques in the order visited, and the parent of each visited clique
+fn traverse(neighbors: &[Vec<usize>], root: usize) -> (Vec<usize>, HashMap<usize, Option<usize>>) {
+    let mut order = vec![root];
+    let mut parent = HashMap::new();
+    parent.insert(root, None);
+
+    let mut idx = 0;
+    while idx < order.len() {
+        let i = order[idx];
+        for &j in neighbors[i].iter() {
+            if let Entry::Vacant(e) = parent.entry(j) {
+                e.insert(Some(i));
+                order.push(j);
+            }
+        }
+
+        idx += 1;
+    }
+
+    (order, parent)
+}
+
+
+/// Compute the sum-product message from clique `i` to clique `j`.
+///
+/// All messages from the other neighbors of `i` must already have been computed.
+fn message(
+    potentials: &[Factor],
+    neighbors: &[Vec<usize>],
+    messages: &HashMap<(usize, usize), Factor>,
+    cliques: &[Vec<Variable>],
+    i: usize,
+    j: usize
+) -> Factor {
+    let psi = neighbors[i].iter()
+                          .filter(|&&k| k != j)
+                          .fold(potentials[i].clone(), |acc, &k| acc.product(&messages[&(k, i)]).unwrap());
+
+    let sepset: Vec<Variable> = cliques[i].iter().filter(|v| cliques[j].contains(v)).cloned().collect();
+    psi.marginalize_to(&sepset)
+}
+
+
+impl ConditionalInferenceEngine for CliqueTreeEngine {
+
+    fn infer(&mut self, variables: &HashSet<Variable>) -> Result<Factor> {
+        if let Some(ref e) = self.err {
+            return Err(e.clone());
+        }
+
+        // check input arguments
+        if variables.is_empty() || variables.iter().any(|v| ! self.variables.contains(v)) {
+            // a variable requested is not found in the (reduced) model
+            return Err(JeromeError::InvalidScope);
+        }
+
+        // prefer the smallest clique that contains the entire query
+        let clique = self.cliques.iter()
+                                 .enumerate()
+                                 .filter(|&(_, c)| variables.iter().all(|v| c.contains(v)))
+                                 .min_by_key(|&(_, c)| c.len())
+                                 .map(|(i, _)| i);
+
+        let phi = match clique {
+            Some(i) => {
+                let vars: Vec<Variable> = variables.iter().cloned().collect();
+                self.beliefs[i].marginalize_to(&vars)
+            },
+            None => self.out_of_clique(variables)?
+        };
+
+        phi.normalize()
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::VariableEliminationEngine;
+    use model::directed::DirectedModelBuilder;
+    use init::Initialization;
+    use variable::all_assignments;
+
+    /// Build the extended student network from Koller & Friedman Figure 9.8 with random CPDs
+    fn build_extended_student() -> (Vec<Variable>, DirectedModel) {
+        let c = Variable::binary();
+        let d = Variable::binary();
+        let i = Variable::binary();
+        let g = Variable::discrete(3);
+        let s = Variable::binary();
+        let l = Variable::binary();
+        let j = Variable::binary();
+        let h = Variable::binary();
+
+        let model = DirectedModelBuilder::new()
+            .with_variable(&c, HashSet::new(), Initialization::Random)
+            .with_variable(&d, vec![c].into_iter().collect(), Initialization::Random)
+            .with_variable(&i, HashSet::new(), Initialization::Random)
+            .with_variable(&g, vec![d, i].into_iter().collect(), Initialization::Random)
+            .with_variable(&s, vec![i].into_iter().collect(), Initialization::Random)
+            .with_variable(&l, vec![g].into_iter().collect(), Initialization::Random)
+            .with_variable(&j, vec![l, s].into_iter().collect(), Initialization::Random)
+            .with_variable(&h, vec![g, j].into_iter().collect(), Initialization::Random)
+            .build()
+            .unwrap();
+
+        (vec![c, d, i, g, s, l, j, h], model)
+    }
+
+    /// Assert that two factors represent the same distribution
+    fn assert_same(scope: &Vec<Variable>, f1: &Factor, f2: &Factor) {
+        for a in all_assignments(scope) {
+            assert!((f1.value(&a).unwrap() - f2.value(&a).unwrap()).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn all_marginals() {
+        let (vars, model) = build_extended_student();
+
+        let mut evidence = Assignment::new();
+        evidence.set(&vars[5], 1);
+
+        let mut ve = VariableEliminationEngine::for_directed(&model, &evidence).unwrap();
+        let mut engine = CliqueTreeEngine::for_directed(&model, &evidence).unwrap();
+
+        for v in vars.iter().filter(|&&v| v != vars[5]) {
+            let query = vec![*v].into_iter().collect();
+            let scope = vec![*v];
+            assert_same(&scope, &ve.infer(&query).unwrap(), &engine.infer(&query).unwrap());
+        }
+    }
+
+    #[test]
+    fn out_of_clique() {
+        let (vars, model) = build_extended_student();
+
+        let mut ve = VariableEliminationEngine::for_directed(&model, &Assignment::new()).unwrap();
+        let mut engine = CliqueTreeEngine::for_directed(&model, &Assignment::new()).unwrap();
+
+        // C and H are never in the same clique
+        let scope = vec![vars[0], vars[7]];
+        let query = scope.iter().cloned().collect();
+        assert!(! engine.cliques.iter().any(|c| c.contains(&vars[0]) && c.contains(&vars[7])));
+        assert_same(&scope, &ve.infer(&query).unwrap(), &engine.infer(&query).unwrap());
+    }
+
+    #[test]
+    fn disconnected() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+
+        let model = DirectedModelBuilder::new()
+            .with_variable(&a, HashSet::new(), Initialization::Binomial(0.2))
+            .with_variable(&b, HashSet::new(), Initialization::Binomial(0.6))
+            .build()
+            .unwrap();
+
+        let mut engine = CliqueTreeEngine::for_directed(&model, &Assignment::new()).unwrap();
+        let f = engine.infer(&vec![a, b].into_iter().collect()).unwrap();
+
+        let mut assn = Assignment::new();
+        assn.set(&a, 0);
+        assn.set(&b, 1);
+        assert!((f.value(&assn).unwrap() - 0.2 * 0.4).abs() < 1e-8);
+    }
+
+    #[test]
+    fn invalid_scope() {
+        let (vars, model) = build_extended_student();
+
+        let mut evidence = Assignment::new();
+        evidence.set(&vars[0], 0);
+
+        let mut engine = CliqueTreeEngine::for_directed(&model, &evidence).unwrap();
+        match engine.infer(&vec![vars[0]].into_iter().collect()) {
+            Err(JeromeError::InvalidScope) => (),
+            _ => panic!("incorrect error")
+        };
+    }
+
+}
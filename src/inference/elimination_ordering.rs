@@ -0,0 +1,356 @@
+//! Defines the `EliminationOrdering` trait - a heuristic for choosing the order in which variable
+//! elimination removes `Variable`s - and the cost of an elimination order.
+//!
+//! All orderings are deterministic: ties are broken in favor of the `Variable` created first.
+//!
+//! Implementation of Koller & Friedman Algorithm 9.4 (Greedy-Ordering) and Algorithm 9.3
+//! (Max-Cardinality).
+
+use model::Model;
+use model::graph::UndirectedGraph;
+use model::undirected::UndirectedModel;
+use util::{JeromeError, Result};
+use variable::Variable;
+
+use std::collections::HashSet;
+
+
+/// An `EliminationOrdering` chooses an order in which to eliminate the `Variable`s of a `Model`.
+pub trait EliminationOrdering {
+
+    /// Compute an elimination order containing every `Variable` in `model`
+    fn order(&self, model: &UndirectedModel) -> Vec<Variable>;
+
+}
+
+
+/// Order by the max-cardinality heuristic: repeatedly select the `Variable` with the most
+/// previously selected neighbors, then eliminate in the reverse order of selection.
+pub struct MaxCardinality;
+
+/// Greedily eliminate the `Variable` with the fewest neighbors in the induced graph.
+pub struct MinNeighbors;
+
+/// Greedily eliminate the `Variable` that produces the smallest factor, i.e. the one minimizing
+/// the product of the cardinalities of it and its neighbors in the induced graph.
+pub struct MinWeight;
+
+/// Greedily eliminate the `Variable` that adds the fewest fill edges to the induced graph.
+pub struct MinFill;
+
+/// Greedily eliminate the `Variable` that minimizes the total weight of the fill edges it adds,
+/// where the weight of an edge is the product of the cardinalities of its endpoints.
+pub struct WeightedMinFill;
+
+
+/// The cost of running variable elimination with an elimination order
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EliminationCost {
+
+    /// the number of `Variable`s in the largest clique of the induced graph, minus one
+    pub induced_width: usize,
+
+    /// the number of entries in the largest intermediate factor
+    pub max_factor_size: usize
+
+}
+
+
+impl EliminationOrdering for MaxCardinality {
+
+    fn order(&self, model: &UndirectedModel) -> Vec<Variable> {
+        let graph = UndirectedGraph::from(model);
+        let vars = sorted_variables(model);
+
+        // set of marked variables
+        let mut marked = HashSet::new();
+        // the (reverse) elimination order
+        let mut elimination = Vec::new();
+
+        for _ in 0..vars.len() {
+            // select the unmarked variable with the most marked neighbors. max_by_key returns the
+            // last maximum, so iterate in reverse to favor the first created variable.
+            let var = vars.iter()
+                          .rev()
+                          .filter(|v| ! marked.contains(*v))
+                          .max_by_key(|v| graph.neighbors(v).iter().filter(|n| marked.contains(*n)).count())
+                          .cloned()
+                          .unwrap();
+
+            elimination.push(var);
+            marked.insert(var);
+        }
+
+        // we need to reverse the elimination order before returning
+        elimination.reverse();
+        elimination
+    }
+
+}
+
+
+impl EliminationOrdering for MinNeighbors {
+
+    fn order(&self, model: &UndirectedModel) -> Vec<Variable> {
+        greedy(model, |graph, var| graph.degree(&var))
+    }
+
+}
+
+
+impl EliminationOrdering for MinWeight {
+
+    fn order(&self, model: &UndirectedModel) -> Vec<Variable> {
+        greedy(model, |graph, var| weight(graph.neighbors(&var).iter().chain(Some(&var))))
+    }
+
+}
+
+
+impl EliminationOrdering for MinFill {
+
+    fn order(&self, model: &UndirectedModel) -> Vec<Variable> {
+        greedy(model, |graph, var| graph.fill_edges(&var).len())
+    }
+
+}
+
+
+impl EliminationOrdering for WeightedMinFill {
+
+    fn order(&self, model: &UndirectedModel) -> Vec<Variable> {
+        greedy(model, |graph, var| {
+            graph.fill_edges(&var).iter()
+                                  .fold(0, |acc: usize, &(a, b)| acc.saturating_add(weight([a, b].iter())))
+        })
+    }
+
+}
+
+
+/// Compute the cost of eliminating the `Variable`s of `model` in the given order, without running
+/// variable elimination.
+///
+/// # Errors
+/// `JeromeError::InvalidScope` if `order` does not contain every `Variable` in `model` exactly once
+pub fn elimination_cost(model: &UndirectedModel, order: &[Variable]) -> Result<EliminationCost> {
+    let variables = model.variables();
+    let distinct: HashSet<Variable> = order.iter().cloned().collect();
+    if distinct.len() != order.len() || distinct != variables {
+        return Err(JeromeError::InvalidScope);
+    }
+
+    let mut graph = UndirectedGraph::from(model);
+    let mut cost = EliminationCost { induced_width: 0, max_factor_size: 0 };
+    for var in order.iter() {
+        let neighbors = graph.eliminate(var).unwrap();
+        cost.induced_width = cost.induced_width.max(neighbors.len());
+        cost.max_factor_size = cost.max_factor_size.max(weight(neighbors.iter().chain(Some(var))));
+    }
+
+    Ok(cost)
+}
+
+
+/// Restrict a user-supplied elimination order to the `Variable`s of `model`. `Variable`s that are
+/// not in `model` (e.g. because they were observed) are dropped.
+///
+/// # Errors
+/// `JeromeError::InvalidScope` if the restricted order does not contain every `Variable` in
+/// `model` exactly once
+pub(super) fn restrict_order(model: &UndirectedModel, order: &[Variable]) -> Result<Vec<Variable>> {
+    let variables = model.variables();
+    let restricted: Vec<Variable> = order.iter().cloned().filter(|v| variables.contains(v)).collect();
+    elimination_cost(model, &restricted)?;
+
+    Ok(restricted)
+}
+
+
+/// Greedily construct an elimination order by repeatedly eliminating the `Variable` of minimum
+/// cost in the induced graph.
+fn greedy<F>(model: &UndirectedModel, cost: F) -> Vec<Variable>
+    where F: Fn(&UndirectedGraph, Variable) -> usize
+{
+    let mut graph = UndirectedGraph::from(model);
+    let mut remaining = sorted_variables(model);
+    let mut order = Vec::with_capacity(remaining.len());
+
+    while ! remaining.is_empty() {
+        // min_by_key returns the first minimum, which favors the first created variable
+        let idx = (0..remaining.len()).min_by_key(|&i| cost(&graph, remaining[i])).unwrap();
+        let var = remaining.remove(idx);
+
+        graph.eliminate(&var);
+        order.push(var);
+    }
+
+    order
+}
+
+
+/// The product of the cardinalities of a set of `Variable`s
+fn weight<'a, I>(vars: I) -> usize
+    where I: Iterator<Item = &'a Variable>
+{
+    vars.fold(1, |acc: usize, v| acc.saturating_mul(v.cardinality()))
+}
+
+
+/// The `Variable`s of `model`, in the order they were created
+fn sorted_variables(model: &UndirectedModel) -> Vec<Variable> {
+    let mut vars: Vec<Variable> = model.variables().into_iter().collect();
+    vars.sort_by_key(|&v| usize::from(v));
+    vars
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use model::directed::DirectedModelBuilder;
+    use model::undirected::UndirectedModelBuilder;
+    use init::Initialization;
+    use std::convert::TryFrom;
+
+    /// The student example of Koller & Friedman Example 9.3 and Figure 9.11
+    fn student() -> (Vec<Variable>, UndirectedModel) {
+        let c = Variable::binary();
+        let d = Variable::binary();
+        let i = Variable::binary();
+        let g = Variable::binary();
+        let s = Variable::binary();
+        let l = Variable::binary();
+        let j = Variable::binary();
+        let h = Variable::binary();
+
+        let model = DirectedModelBuilder::new()
+            .with_variable(&c, HashSet::new(), Initialization::Uniform)
+            .with_variable(&d, vec![c].into_iter().collect(), Initialization::Uniform)
+            .with_variable(&i, HashSet::new(), Initialization::Uniform)
+            .with_variable(&g, vec![d, i].into_iter().collect(), Initialization::Uniform)
+            .with_variable(&s, vec![i].into_iter().collect(), Initialization::Uniform)
+            .with_variable(&l, vec![g].into_iter().collect(), Initialization::Uniform)
+            .with_variable(&j, vec![l, s].into_iter().collect(), Initialization::Uniform)
+            .with_variable(&h, vec![g, j].into_iter().collect(), Initialization::Uniform)
+            .build()
+            .unwrap();
+
+        (vec![c, d, i, g, s, l, j, h], UndirectedModel::try_from(&model).unwrap())
+    }
+
+    /// A chain ```A - B - C``` with increasing cardinalities
+    fn chain() -> (Vec<Variable>, UndirectedModel) {
+        let a = Variable::discrete(2);
+        let b = Variable::discrete(3);
+        let c = Variable::discrete(4);
+
+        let model = DirectedModelBuilder::new()
+            .with_variable(&a, HashSet::new(), Initialization::Uniform)
+            .with_variable(&b, vec![a].into_iter().collect(), Initialization::Uniform)
+            .with_variable(&c, vec![b].into_iter().collect(), Initialization::Uniform)
+            .build()
+            .unwrap();
+
+        (vec![a, b, c], UndirectedModel::try_from(&model).unwrap())
+    }
+
+    #[test]
+    fn max_cardinality() {
+        let (v, model) = student();
+
+        // Marking proceeds C, D, I, G, S, L, J, H, with ties broken by creation order
+        let expected = vec![v[7], v[6], v[5], v[4], v[3], v[2], v[1], v[0]];
+        assert_eq!(expected, MaxCardinality.order(&model));
+
+        // the largest clique of the induced graph is {G, J, L, S}
+        let cost = elimination_cost(&model, &expected).unwrap();
+        assert_eq!(3, cost.induced_width);
+        assert_eq!(16, cost.max_factor_size);
+    }
+
+    #[test]
+    fn min_neighbors() {
+        let (v, model) = chain();
+        assert_eq!(vec![v[0], v[1], v[2]], MinNeighbors.order(&model));
+
+        let (v, model) = student();
+        let order = MinNeighbors.order(&model);
+        assert_eq!(v[0], order[0]);
+        assert_eq!(3, elimination_cost(&model, &order).unwrap().induced_width);
+    }
+
+    #[test]
+    fn min_weight() {
+        let (v, model) = chain();
+
+        // A produces a factor of size 6 while C produces a factor of size 12
+        let order = MinWeight.order(&model);
+        assert_eq!(vec![v[0], v[1], v[2]], order);
+
+        let cost = elimination_cost(&model, &order).unwrap();
+        assert_eq!(1, cost.induced_width);
+        assert_eq!(12, cost.max_factor_size);
+
+        // eliminating B first connects A and C
+        let cost = elimination_cost(&model, &[v[1], v[0], v[2]]).unwrap();
+        assert_eq!(2, cost.induced_width);
+        assert_eq!(24, cost.max_factor_size);
+    }
+
+    #[test]
+    fn min_fill() {
+        let (_, model) = student();
+
+        for order in [MinFill.order(&model), WeightedMinFill.order(&model)] {
+            let cost = elimination_cost(&model, &order).unwrap();
+            assert_eq!(3, cost.induced_width);
+            assert_eq!(16, cost.max_factor_size);
+        }
+    }
+
+    #[test]
+    fn weighted_min_fill() {
+        // A loop X - A - Y - B - X, in which eliminating any variable adds exactly one fill edge
+        let x = Variable::binary();
+        let a = Variable::discrete(5);
+        let y = Variable::binary();
+        let b = Variable::discrete(5);
+
+        let model = UndirectedModelBuilder::new()
+            .with_factor(vec![x, a].into_iter().collect(), Initialization::Uniform)
+            .with_factor(vec![a, y].into_iter().collect(), Initialization::Uniform)
+            .with_factor(vec![y, b].into_iter().collect(), Initialization::Uniform)
+            .with_factor(vec![b, x].into_iter().collect(), Initialization::Uniform)
+            .build()
+            .unwrap();
+
+        // min-fill breaks the tie by eliminating X, which joins A and B
+        let order = MinFill.order(&model);
+        assert_eq!(x, order[0]);
+        assert_eq!(50, elimination_cost(&model, &order).unwrap().max_factor_size);
+
+        // weighted min-fill eliminates A instead, which joins X and Y
+        let order = WeightedMinFill.order(&model);
+        assert_eq!(a, order[0]);
+        assert_eq!(20, elimination_cost(&model, &order).unwrap().max_factor_size);
+    }
+
+    #[test]
+    fn user_order() {
+        let (v, model) = chain();
+
+        assert_eq!(vec![v[2], v[0], v[1]], restrict_order(&model, &[v[2], v[0], v[1]]).unwrap());
+
+        // variables outside the model are dropped
+        let other = Variable::binary();
+        assert_eq!(vec![v[1], v[0], v[2]], restrict_order(&model, &[v[1], other, v[0], v[2]]).unwrap());
+
+        // missing and repeated variables are errors
+        assert!(restrict_order(&model, &[v[0], v[1]]).is_err());
+        assert!(restrict_order(&model, &[v[0], v[1], v[2], v[0]]).is_err());
+        assert!(elimination_cost(&model, &[v[0], v[1]]).is_err());
+    }
+
+}
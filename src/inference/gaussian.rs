@@ -0,0 +1,95 @@
+//! Defines an inference engine for networks of continuous `Variable`s with linear-Gaussian CPDs.
+//!
+//! Each CPD is converted to a `CanonicalForm`, reduced by the evidence, and the unobserved,
+//! unqueried `Variable`s are integrated out one at a time. This is exact, and is the continuous
+//! analog of variable elimination. Described in Koller & Friedman Section 14.2.
+
+use gaussian::CanonicalForm;
+use model::directed::DirectedModel;
+use super::EvidenceProbabilityEngine;
+use util::{JeromeError, Result};
+use variable::{Assignment, Variable};
+
+pub struct GaussianInferenceEngine {
+
+    /// the `CanonicalForm` of each CPD, reduced by the evidence
+    factors: Vec<CanonicalForm>,
+
+    /// the unobserved `Variable`s, in reverse topological order. This is the elimination order.
+    order: Vec<Variable>
+
+}
+
+
+impl GaussianInferenceEngine {
+
+    /// Construct a `GaussianInferenceEngine` for a `DirectedModel` in which every `Variable` is
+    /// continuous
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if the model has a discrete `Variable`
+    pub fn for_directed(model: &DirectedModel, evidence: &Assignment) -> Result<Self> {
+        let topological_order = model.topological_order();
+
+        let factors = topological_order.iter()
+                                       .map(|v| model.gaussian_cpd(v).ok_or(JeromeError::InvalidScope))
+                                       .map(|cpd| cpd.map(|cpd| cpd.to_canonical().reduce(evidence)))
+                                       .collect::<Result<Vec<CanonicalForm>>>()?;
+
+        let order = topological_order.into_iter()
+                                     .rev()
+                                     .filter(|v| evidence.get_continuous(v).is_none())
+                                     .collect();
+
+        Ok(GaussianInferenceEngine { factors, order })
+    }
+
+    /// Infer the joint density ```p(variables | evidence)```. The scope of the resulting
+    /// `CanonicalForm` is in the order of `variables`; see `CanonicalForm::mean` and
+    /// `CanonicalForm::covariance` to recover the moments.
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if `variables` is empty or has a `Variable` that is observed
+    ///   or not in the model
+    pub fn infer(&mut self, variables: &[Variable]) -> Result<CanonicalForm> {
+        if variables.is_empty() || variables.iter().any(|v| ! self.order.contains(v)) {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        let to_eliminate: Vec<Variable> = self.order.iter().filter(|v| ! variables.contains(v)).cloned().collect();
+        let joint = eliminate(self.factors.clone(), &to_eliminate)?;
+
+        joint.extend(variables).normalize()
+    }
+
+}
+
+
+impl EvidenceProbabilityEngine for GaussianInferenceEngine {
+
+    /// Compute the density of the evidence, ```p(E = e)```
+    fn probability_of_evidence(&mut self) -> Result<f64> {
+        self.log_probability_of_evidence().map(f64::exp)
+    }
+
+    fn log_probability_of_evidence(&mut self) -> Result<f64> {
+        eliminate(self.factors.clone(), &self.order).map(|c| c.g())
+    }
+
+}
+
+
+/// Integrate the `Variable`s out of the product of the `CanonicalForm`s, in the given order
+fn eliminate(mut factors: Vec<CanonicalForm>, order: &[Variable]) -> Result<CanonicalForm> {
+    for &v in order.iter() {
+        let (with_v, without_v): (Vec<CanonicalForm>, Vec<CanonicalForm>) = factors.into_iter()
+                                                                                   .partition(|f| f.scope().contains(&v));
+
+        let psi = with_v.iter().fold(CanonicalForm::vacuous(), |acc, f| acc.product(f));
+
+        factors = without_v;
+        factors.push(psi.marginalize(v)?);
+    }
+
+    Ok(factors.iter().fold(CanonicalForm::vacuous(), |acc, f| acc.product(f)))
+}
@@ -6,7 +6,7 @@
 
 use factor::{Factor, Table};
 use samplers::{WeightedSampler, WeightedSample};
-use super::ConditionalInferenceEngine;
+use super::{ConditionalInferenceEngine, EvidenceProbabilityEngine};
 use util::{JeromeError, Result};
 use variable::Variable;
 
@@ -46,10 +46,8 @@ impl<'a, S: 'a + WeightedSampler> ConditionalInferenceEngine for ImportanceSampl
             let idx: Vec<Option<&usize>> = scope.iter().map(|v| a.get(v)).collect();
 
             // on the first iteration, verify the assignment matches the scope
-            if i == 0 {
-                if idx.iter().any(|v| v.is_none()) {
-                    return Err(JeromeError::InvalidScope);
-                }
+            if i == 0 && idx.iter().any(|v| v.is_none()) {
+                return Err(JeromeError::InvalidScope);
             }
 
             let idx: Vec<usize> = idx.iter().map(|v| v.unwrap()).cloned().collect();
@@ -58,8 +56,23 @@ impl<'a, S: 'a + WeightedSampler> ConditionalInferenceEngine for ImportanceSampl
         }
 
         let factor = Factor::new(scope, table)?;
-        Ok(factor.normalize())
+        factor.normalize()
     }
 
 }
 
+
+impl<'a, S: 'a + WeightedSampler> EvidenceProbabilityEngine for ImportanceSamplingEngine<'a, S> {
+
+    /// Estimate ```P(e)``` as the mean of the sample weights. This is an unbiased estimator when the
+    /// weights are likelihood weights.
+    fn probability_of_evidence(&mut self) -> Result<f64> {
+        if self.samples == 0 {
+            return Err(JeromeError::General(String::from("Cannot estimate from zero samples")));
+        }
+
+        let total: f64 = (0..self.samples).map(|_| self.sampler.weighted_sample().1).sum();
+        Ok(total / self.samples as f64)
+    }
+
+}
@@ -0,0 +1,569 @@
+//! Defines a `ConditionalInferenceEngine` that uses approximate inference by loopy belief
+//! propagation to answer conditional inference queries.
+//!
+//! Messages are passed over the Bethe cluster graph of the model, which has one cluster for each
+//! `Factor` and one cluster for each `Variable`, with an edge between a `Factor` and each
+//! `Variable` in its scope. On a model whose Bethe cluster graph is a tree, the result is exact.
+//!
+//! Implementation of Koller & Friedman Section 11.3 - Sum-Product Belief Propagation over a
+//! cluster graph, including message damping (11.3.4.1) and residual belief propagation
+//! (11.3.4.2).
+
+use factor::{Factor, Table};
+use model::Model;
+use model::directed::DirectedModel;
+use model::undirected::UndirectedModel;
+use super::ConditionalInferenceEngine;
+use super::variable_elimination::log_observed;
+use util::{JeromeError, Result};
+use variable::{Assignment, Variable};
+
+use std::convert::TryFrom;
+use std::collections::{HashMap, HashSet};
+
+/// The order in which messages are updated during loopy belief propagation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Schedule {
+    /// Every message is recomputed from the messages of the previous iteration, and all messages
+    /// are updated simultaneously.
+    Synchronous,
+
+    /// The message that would change the most is updated first, and only the messages affected by
+    /// it are recomputed.
+    Residual
+}
+
+
+pub struct LoopyBeliefPropagationEngine {
+
+    /// The `Variable`s of the model, once reduced by the evidence
+    variables: Vec<Variable>,
+
+    /// The `Factor`s of the model, once reduced by the evidence
+    factors: Vec<Factor>,
+
+    /// The `Factor`s (by index) in which each `Variable` appears
+    adjacent: HashMap<Variable, Vec<usize>>,
+
+    /// The current messages from each `Factor` cluster to the `Variable` clusters in its scope
+    messages: HashMap<(usize, Variable), Factor>,
+
+    /// The message passing schedule
+    schedule: Schedule,
+
+    /// The weight given to the previous value of a message when it is updated
+    damping: f64,
+
+    /// The largest change in any message below which the messages are considered converged
+    tolerance: f64,
+
+    /// The maximum number of iterations to run
+    max_iterations: usize,
+
+    /// `true`, once the messages have been calibrated
+    calibrated: bool,
+
+    /// `true`, if the messages converged during calibration
+    converged: bool,
+
+    /// The number of iterations run during calibration
+    iterations: usize,
+
+    /// An error encountered during construction or configuration, which is reported by `calibrate`
+    /// and `infer`
+    err: Option<JeromeError>
+
+}
+
+
+impl LoopyBeliefPropagationEngine {
+
+    /// Construct the engine for a `DirectedModel`, by converting it to an `UndirectedModel`.
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if the model has a continuous `Variable`
+    pub fn for_directed(model: &DirectedModel, evidence: &Assignment) -> Result<Self> {
+        Ok(LoopyBeliefPropagationEngine::for_undirected(
+            &UndirectedModel::try_from(model)?,
+            evidence
+        ))
+    }
+
+    pub fn for_undirected(model: &UndirectedModel, evidence: &Assignment) -> Self {
+        // reduce the provided model with the evidence - this is the model we will calibrate
+        let reduced = model.condition(evidence);
+
+        let mut variables: Vec<Variable> = reduced.variables().into_iter().collect();
+        variables.sort_by_key(|&v| usize::from(v));
+
+        let factors = reduced.factors().clone();
+
+        let mut adjacent: HashMap<Variable, Vec<usize>> = variables.iter()
+                                                                   .map(|&v| (v, Vec::new()))
+                                                                   .collect();
+        let mut messages = HashMap::new();
+
+        // all messages are initialized to one
+        for (i, f) in factors.iter().enumerate() {
+            for v in f.scope() {
+                adjacent.get_mut(&v).unwrap().push(i);
+                messages.insert((i, v), Factor::ones(vec![v]).unwrap());
+            }
+        }
+
+        LoopyBeliefPropagationEngine {
+            variables,
+            factors,
+            adjacent,
+            messages,
+            schedule: Schedule::Synchronous,
+            damping: 0.0,
+            tolerance: 1e-6,
+            max_iterations: 100,
+            calibrated: false,
+            converged: false,
+            iterations: 0,
+            // a fully observed factor with value zero means the evidence has probability zero
+            err: if log_observed(model, evidence) == f64::NEG_INFINITY { Some(JeromeError::DivideByZero) } else { None }
+        }
+    }
+
+
+    /// Set the message passing schedule. Defaults to `Schedule::Synchronous`.
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+
+    /// Set the damping weight. Each updated message is ```damping * old + (1 - damping) * new```.
+    /// Defaults to 0 (no damping).
+    ///
+    /// # Errors
+    /// `JeromeError::General`, reported by `calibrate` and `infer`, if `damping` is not in
+    /// ```[0, 1)```
+    pub fn with_damping(mut self, damping: f64) -> Self {
+        if self.err.is_some() {
+            return self;
+        }
+
+        if (0.0..1.0).contains(&damping) {
+            self.damping = damping;
+        } else {
+            self.err = Some(JeromeError::General(format!("Error - damping must be in [0, 1), not {}", damping)));
+        }
+
+        self
+    }
+
+
+    /// Set the convergence tolerance on the largest change of any message. Defaults to 1e-6.
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+
+    /// Set the maximum number of iterations. Defaults to 100.
+    ///
+    /// Under the `Schedule::Residual` schedule, an iteration is as many single message updates as
+    /// there are messages in the cluster graph.
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+
+    /// Calibrate the cluster graph by passing messages until they converge or the maximum number
+    /// of iterations is reached. Calibration runs only once; it is invoked implicitly by the
+    /// first call to `infer`.
+    ///
+    /// # Returns
+    /// `true`, if the messages converged
+    ///
+    /// # Errors
+    /// * `JeromeError::DivideByZero`, if the evidence has probability zero, or a message is zero
+    ///   everywhere
+    pub fn calibrate(&mut self) -> Result<bool> {
+        if let Some(ref e) = self.err {
+            return Err(e.clone());
+        }
+
+        if ! self.calibrated {
+            match self.schedule {
+                Schedule::Synchronous => self.run_synchronous()?,
+                Schedule::Residual => self.run_residual()?
+            };
+
+            self.calibrated = true;
+
+            // a belief that is zero everywhere means the evidence has probability zero
+            if self.variables.iter().any(|&v| self.belief(v).normalize().is_err()) {
+                self.err = Some(JeromeError::DivideByZero);
+                return Err(JeromeError::DivideByZero);
+            }
+        }
+
+        Ok(self.converged)
+    }
+
+
+    /// `true`, if calibration has run and the messages converged
+    pub fn converged(&self) -> bool {
+        self.converged
+    }
+
+
+    /// The number of iterations run during calibration
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+
+    /// Run belief propagation, updating all messages at once each iteration
+    fn run_synchronous(&mut self) -> Result<()> {
+        let edges = self.edges();
+
+        for iter in 0..self.max_iterations {
+            let updates: Vec<(Factor, f64)> = edges.iter()
+                                                   .map(|&(i, v)| self.update(i, v))
+                                                   .collect::<Result<_>>()?;
+
+            let residual = updates.iter().fold(0.0, |acc: f64, u| acc.max(u.1));
+
+            for (&edge, (msg, _)) in edges.iter().zip(updates) {
+                self.messages.insert(edge, msg);
+            }
+
+            self.iterations = iter + 1;
+            if residual < self.tolerance {
+                self.converged = true;
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+
+    /// Run residual belief propagation, updating the message that would change the most first
+    fn run_residual(&mut self) -> Result<()> {
+        let edges = self.edges();
+        if edges.is_empty() {
+            self.converged = true;
+            return Ok(());
+        }
+
+        // the pending update (and its residual) for each message
+        let mut pending: HashMap<(usize, Variable), (Factor, f64)> = edges.iter()
+                                                                         .map(|&(i, v)| self.update(i, v).map(|u| ((i, v), u)))
+                                                                         .collect::<Result<_>>()?;
+
+        let max_updates = self.max_iterations * edges.len();
+        for count in 0..max_updates {
+            // select the message with the largest residual. Ties are broken by the order of the
+            // edges to keep the schedule deterministic.
+            let mut best = edges[0];
+            for &edge in edges.iter() {
+                if pending[&edge].1 > pending[&best].1 {
+                    best = edge;
+                }
+            }
+
+            self.iterations = count / edges.len() + 1;
+            if pending[&best].1 < self.tolerance {
+                self.converged = true;
+                return Ok(());
+            }
+
+            let (msg, _) = pending.remove(&best).unwrap();
+            self.messages.insert(best, msg);
+
+            // the message best = (i, v) changes the messages from every other factor containing v
+            let (i, v) = best;
+            pending.insert(best, self.update(i, v)?);
+            for &j in self.adjacent[&v].iter().filter(|&&j| j != i) {
+                for u in self.factors[j].scope().into_iter().filter(|&u| u != v) {
+                    pending.insert((j, u), self.update(j, u)?);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+
+    /// All edges of the Bethe cluster graph, in a fixed order
+    fn edges(&self) -> Vec<(usize, Variable)> {
+        self.factors.iter()
+                    .enumerate()
+                    .flat_map(|(i, f)| f.scope().into_iter().map(move |v| (i, v)))
+                    .collect()
+    }
+
+
+    /// The message from the cluster of `Variable` `v` to the cluster of `Factor` `i`. This is
+    /// the product of the messages from every other `Factor` containing `v`.
+    fn variable_message(&self, v: Variable, i: usize) -> Factor {
+        self.adjacent[&v].iter()
+                         .filter(|&&j| j != i)
+                         .fold(Factor::ones(vec![v]).unwrap(), |acc, &j| acc.product(&self.messages[&(j, v)]).unwrap())
+    }
+
+
+    /// The belief of the cluster of `Variable` `v`: the product of all incoming messages
+    fn belief(&self, v: Variable) -> Factor {
+        self.adjacent[&v].iter()
+                         .fold(Factor::ones(vec![v]).unwrap(), |acc, &j| acc.product(&self.messages[&(j, v)]).unwrap())
+    }
+
+
+    /// Compute the damped update of the message from `Factor` `i` to `Variable` `v`
+    ///
+    /// # Returns
+    /// the new message and its residual - the largest change in any entry of the message
+    fn update(&self, i: usize, v: Variable) -> Result<(Factor, f64)> {
+        let psi = self.factors[i].scope()
+                                 .into_iter()
+                                 .filter(|&u| u != v)
+                                 .fold(self.factors[i].clone(), |acc, u| {
+                                     acc.product(&self.variable_message(u, i)).unwrap()
+                                 });
+
+        let new = table(&psi.marginalize_to(&[v]).normalize()?.to_linear()).clone();
+        let old = table(&self.messages[&(i, v)]).clone();
+
+        let msg = &old * self.damping + &new * (1.0 - self.damping);
+        let residual = (&msg - &old).iter().fold(0.0, |acc: f64, d| acc.max(d.abs()));
+
+        Ok((Factor::new(vec![v], msg).unwrap(), residual))
+    }
+
+}
+
+
+/// Get the table of a (non-identity) `Factor`
+fn table(f: &Factor) -> &Table {
+    match *f {
+        Factor::TableFactor { ref table, .. } => table,
+        Factor::Identity => panic!("the identity factor has no table")
+    }
+}
+
+
+impl ConditionalInferenceEngine for LoopyBeliefPropagationEngine {
+
+    /// Infer the (approximate) distribution ```P(variables | evidence)```.
+    ///
+    /// The query must either be a single `Variable`, or be contained in the scope of a `Factor` of
+    /// the model.
+    fn infer(&mut self, variables: &HashSet<Variable>) -> Result<Factor> {
+        // check input arguments
+        if variables.is_empty() || variables.iter().any(|v| ! self.variables.contains(v)) {
+            // a variable requested is not found in the (reduced) model
+            return Err(JeromeError::InvalidScope);
+        }
+
+        self.calibrate()?;
+
+        if variables.len() == 1 {
+            let v = *variables.iter().next().unwrap();
+            return self.belief(v).normalize();
+        }
+
+        // otherwise, the belief of the smallest factor cluster containing the query is used
+        let cluster = self.factors.iter()
+                                  .enumerate()
+                                  .filter(|&(_, f)| variables.iter().all(|v| f.scope().contains(v)))
+                                  .min_by_key(|&(_, f)| f.scope().len())
+                                  .map(|(i, _)| i);
+
+        match cluster {
+            Some(i) => {
+                let belief = self.factors[i].scope()
+                                            .into_iter()
+                                            .fold(self.factors[i].clone(), |acc, u| {
+                                                acc.product(&self.variable_message(u, i)).unwrap()
+                                            });
+
+                let vars: Vec<Variable> = variables.iter().cloned().collect();
+                belief.marginalize_to(&vars).normalize()
+            },
+            None => Err(JeromeError::InvalidScope)
+        }
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::VariableEliminationEngine;
+    use init::Initialization;
+    use model::undirected::UndirectedModelBuilder;
+    use variable::all_assignments;
+
+    /// The Misconception example from Koller & Friedman Section 4.1 - a single loop
+    fn build_misconception() -> (Vec<Variable>, UndirectedModel) {
+        let a = Variable::binary();
+        let b = Variable::binary();
+        let c = Variable::binary();
+        let d = Variable::binary();
+
+        let ab = Factor::new(vec![a, b], array![[30.0, 5.0], [1.0, 10.0]].into_dyn()).unwrap();
+        let bc = Factor::new(vec![b, c], array![[100.0, 1.0], [1.0, 100.0]].into_dyn()).unwrap();
+        let cd = Factor::new(vec![c, d], array![[1.0, 100.0], [100.0, 1.0]].into_dyn()).unwrap();
+        let da = Factor::new(vec![d, a], array![[100.0, 1.0], [1.0, 100.0]].into_dyn()).unwrap();
+
+        let model = UndirectedModelBuilder::new()
+            .with_factor(vec![a, b].into_iter().collect(), Initialization::Table(ab))
+            .with_factor(vec![b, c].into_iter().collect(), Initialization::Table(bc))
+            .with_factor(vec![c, d].into_iter().collect(), Initialization::Table(cd))
+            .with_factor(vec![d, a].into_iter().collect(), Initialization::Table(da))
+            .build()
+            .unwrap();
+
+        (vec![a, b, c, d], model)
+    }
+
+    /// A chain A - B - C, on which belief propagation is exact
+    fn build_chain() -> (Vec<Variable>, UndirectedModel) {
+        let a = Variable::binary();
+        let b = Variable::discrete(3);
+        let c = Variable::binary();
+
+        let model = UndirectedModelBuilder::new()
+            .with_factor(vec![a, b].into_iter().collect(), Initialization::Random)
+            .with_factor(vec![b, c].into_iter().collect(), Initialization::Random)
+            .build()
+            .unwrap();
+
+        (vec![a, b, c], model)
+    }
+
+    fn assert_close(scope: &Vec<Variable>, f1: &Factor, f2: &Factor, precision: f64) {
+        for a in all_assignments(scope) {
+            assert!((f1.value(&a).unwrap() - f2.value(&a).unwrap()).abs() < precision);
+        }
+    }
+
+    #[test]
+    fn exact_on_tree() {
+        let (vars, model) = build_chain();
+
+        for &schedule in [Schedule::Synchronous, Schedule::Residual].iter() {
+            let mut ve = VariableEliminationEngine::for_undirected(&model, &Assignment::new());
+            let mut engine = LoopyBeliefPropagationEngine::for_undirected(&model, &Assignment::new())
+                                                          .with_schedule(schedule);
+
+            assert!(engine.calibrate().unwrap());
+
+            for &v in vars.iter() {
+                let query = vec![v].into_iter().collect();
+                assert_close(&vec![v], &ve.infer(&query).unwrap(), &engine.infer(&query).unwrap(), 1e-6);
+            }
+
+            // a query over the scope of a factor
+            let scope = vec![vars[0], vars[1]];
+            let query = scope.iter().cloned().collect();
+            assert_close(&scope, &ve.infer(&query).unwrap(), &engine.infer(&query).unwrap(), 1e-6);
+        }
+    }
+
+    #[test]
+    fn loopy() {
+        let (vars, model) = build_misconception();
+
+        for &schedule in [Schedule::Synchronous, Schedule::Residual].iter() {
+            let mut engine = LoopyBeliefPropagationEngine::for_undirected(&model, &Assignment::new())
+                                                          .with_schedule(schedule)
+                                                          .with_damping(0.5)
+                                                          .with_max_iterations(1000);
+
+            assert!(engine.calibrate().unwrap());
+            assert!(engine.converged());
+            assert!(engine.iterations() > 1);
+
+            for &v in vars.iter() {
+                let f = engine.infer(&vec![v].into_iter().collect()).unwrap();
+                let sum: f64 = all_assignments(&vec![v]).map(|a| f.value(&a).unwrap()).sum();
+                assert!((sum - 1.0).abs() < 1e-8);
+            }
+        }
+    }
+
+    #[test]
+    /// On a weakly coupled 2x3 grid, which has two loops, the beliefs are close to the exact
+    /// marginals. Loopy belief propagation is not exact here (the largest error is about 8e-4), so
+    /// they are only required to agree within 2e-3.
+    fn approximates_exact() {
+        let vars: Vec<Variable> = (0..6).map(|_| Variable::binary()).collect();
+        let edges = [(0, 1), (1, 2), (3, 4), (4, 5), (0, 3), (1, 4), (2, 5)];
+
+        let coupling = array![[1.5, 1.0], [1.0, 1.5]].into_dyn();
+        let builder = edges.iter().fold(UndirectedModelBuilder::new(), |builder, &(i, j)| {
+            let phi = Factor::new(vec![vars[i], vars[j]], coupling.clone()).unwrap();
+            builder.with_factor(vec![vars[i], vars[j]].into_iter().collect(), Initialization::Table(phi))
+        });
+        let model = vars.iter().enumerate().fold(builder, |builder, (i, &v)| {
+            let field = Factor::new(vec![v], array![1.0, 0.5 + 0.3 * i as f64].into_dyn()).unwrap();
+            builder.with_factor(vec![v].into_iter().collect(), Initialization::Table(field))
+        }).build().unwrap();
+
+        let mut ve = VariableEliminationEngine::for_undirected(&model, &Assignment::new());
+
+        for &schedule in [Schedule::Synchronous, Schedule::Residual].iter() {
+            let mut engine = LoopyBeliefPropagationEngine::for_undirected(&model, &Assignment::new())
+                                                          .with_schedule(schedule);
+
+            assert!(engine.calibrate().unwrap());
+            assert!(engine.converged());
+            assert!(engine.iterations() <= 20);
+
+            for &v in vars.iter() {
+                let query = vec![v].into_iter().collect();
+                assert_close(&vec![v], &ve.infer(&query).unwrap(), &engine.infer(&query).unwrap(), 2e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn not_converged() {
+        let (_, model) = build_misconception();
+
+        let mut engine = LoopyBeliefPropagationEngine::for_undirected(&model, &Assignment::new())
+                                                      .with_max_iterations(1);
+
+        assert!(! engine.calibrate().unwrap());
+        assert!(! engine.converged());
+        assert_eq!(1, engine.iterations());
+    }
+
+    #[test]
+    fn invalid_damping() {
+        let (vars, model) = build_misconception();
+
+        for &damping in [-0.5, 1.0, f64::NAN].iter() {
+            let mut engine = LoopyBeliefPropagationEngine::for_undirected(&model, &Assignment::new())
+                                                          .with_damping(damping);
+
+            assert!(matches!(engine.calibrate(), Err(JeromeError::General(_))));
+            assert!(matches!(engine.infer(&vec![vars[0]].into_iter().collect()), Err(JeromeError::General(_))));
+        }
+    }
+
+    #[test]
+    fn invalid_scope() {
+        let (vars, model) = build_misconception();
+
+        let mut engine = LoopyBeliefPropagationEngine::for_undirected(&model, &Assignment::new());
+
+        // A and C do not share a factor
+        match engine.infer(&vec![vars[0], vars[2]].into_iter().collect()) {
+            Err(JeromeError::InvalidScope) => (),
+            _ => panic!("incorrect error")
+        };
+    }
+
+}
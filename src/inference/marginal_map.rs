@@ -0,0 +1,315 @@
+//! Defines a `MapInferenceEngine` that answers marginal MAP queries by variable elimination.
+//!
+//! A marginal MAP query maximizes over the query variables ```Y``` and sums over the remaining
+//! variables ```Z```:
+//!     ```argmax_y sum_z P(Y = y, Z = z | E = e)```
+//!
+//! Since summation and maximization do not commute, the elimination order is constrained so that
+//! every variable in ```Z``` is summed out before any variable in ```Y``` is maximized over.
+//! Described in Koller & Friedman Chapter 13.
+
+use model::Model;
+use model::directed::DirectedModel;
+use model::undirected::UndirectedModel;
+use super::elimination_ordering::{elimination_cost, restrict_order, EliminationCost, EliminationOrdering, MaxCardinality};
+use super::MapInferenceEngine;
+use super::max_product::max_product_ve;
+use super::variable_elimination::{eliminate, log_observed, Elimination};
+use util::{JeromeError, Result};
+use variable::{Assignment, Variable};
+
+use std::collections::HashSet;
+
+pub struct MarginalMapEngine {
+
+    /// the `UndirectedModel` (a 'bag of factors'), conditioned on the provided evidence, to use
+    /// for the inference task
+    model: UndirectedModel,
+
+    /// precomputed preferred elimination order based on max-cardinality heuristic. The order is
+    /// constrained per query by moving the query variables to the end.
+    order: Vec<Variable>,
+
+    /// An error encountered during construction, which is reported by every query
+    err: Option<JeromeError>
+
+}
+
+
+impl MarginalMapEngine {
+
+    /// Construct the engine for a `DirectedModel`, by converting it to an `UndirectedModel`.
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if the model has a continuous `Variable`
+    pub fn for_directed(model: &DirectedModel, evidence: &Assignment) -> Result<Self> {
+        Ok(MarginalMapEngine::for_undirected(
+            &UndirectedModel::decomposed(model)?,
+            evidence
+        ))
+    }
+
+    pub fn for_undirected(model: &UndirectedModel, evidence: &Assignment) -> Self {
+        // reduce the provided model with the evidence - this is the model we will use for variable
+        // elimination
+        let reduced = model.condition(evidence);
+        // precompute the preferred elimination order using the max-cardinatlity heuristic.
+        let order = MaxCardinality.order(&reduced);
+
+        MarginalMapEngine {
+            model: reduced,
+            order,
+            err: if log_observed(model, evidence) == f64::NEG_INFINITY { Some(JeromeError::DivideByZero) } else { None }
+        }
+    }
+
+    /// Choose the elimination order with the given `EliminationOrdering` heuristic, rather than
+    /// the default max-cardinality heuristic.
+    pub fn with_ordering(mut self, ordering: &dyn EliminationOrdering) -> Self {
+        self.order = ordering.order(&self.model);
+        self
+    }
+
+    /// Use a user-supplied elimination order. Observed `Variable`s may be included; they are
+    /// ignored.
+    ///
+    /// # Errors
+    /// `JeromeError::InvalidScope` if `order` does not contain every unobserved `Variable` exactly
+    /// once
+    pub fn with_order(mut self, order: &[Variable]) -> Result<Self> {
+        self.order = restrict_order(&self.model, order)?;
+        Ok(self)
+    }
+
+    /// The cost of answering a marginal MAP query over `variables` with the current elimination
+    /// order, once it is constrained to sum out every other `Variable` first. This may be higher
+    /// than the cost of the unconstrained order.
+    ///
+    /// # Errors
+    /// `JeromeError::InvalidScope` if a `Variable` of the query is not in the reduced model
+    pub fn cost(&self, variables: &HashSet<Variable>) -> Result<EliminationCost> {
+        let (max_order, sum_order) = self.constrained_order(variables)?;
+        elimination_cost(&self.model, &[sum_order, max_order].concat())
+    }
+
+    /// Split the elimination order into the query `variables`, which are maximized over, and the
+    /// remaining `Variable`s, which are summed out first. Each part keeps its relative order.
+    fn constrained_order(&self, variables: &HashSet<Variable>) -> Result<(Vec<Variable>, Vec<Variable>)> {
+        // check input arguments
+        if variables.is_empty() || variables.iter().any(|v| ! self.model.variables().contains(v)) {
+            // a variable requested is not found in the (reduced) model
+            return Err(JeromeError::InvalidScope);
+        }
+
+        Ok(self.order.iter().partition(|v| variables.contains(v)))
+    }
+
+}
+
+
+impl MapInferenceEngine for MarginalMapEngine {
+
+    /// Infer the marginal MAP assignment to `variables`, summing over all other unobserved
+    /// `Variable`s.
+    ///
+    /// # Returns
+    /// the most probable `Assignment` ```y*``` to `variables`, and its marginal probability
+    /// ```P(Y = y* | e)```
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if a `Variable` of the query is not in the reduced model
+    /// * `JeromeError::DivideByZero`, if the evidence has probability zero
+    fn infer(&mut self, variables: &HashSet<Variable>) -> Result<(Assignment, f64)> {
+        if let Some(ref e) = self.err {
+            return Err(e.clone());
+        }
+
+        // constrain the elimination order - sum variables first, then max variables
+        let (max_order, sum_order) = self.constrained_order(variables)?;
+
+        // the partition function of the reduced model is computed by the first query, and cached
+        // for the rest
+        let log_z = self.model.log_partition();
+        if log_z == f64::NEG_INFINITY {
+            return Err(JeromeError::DivideByZero);
+        }
+
+        // sum step - eliminate the variables that are not in the query
+        let mut phis = self.model.factors().clone();
+        let mut log_constant = 0.0;
+        for &var in sum_order.iter() {
+            let (remaining, _, c) = eliminate(phis, var, Elimination::Sum);
+            phis = remaining;
+            log_constant += c;
+        }
+
+        // max step - maximize over the query variables, tracing back the argmax decisions
+        let (assignment, log_max) = max_product_ve(phis, &max_order)?;
+
+        // normalize to obtain a probability
+        Ok((assignment, (log_constant + log_max - log_z).exp()))
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use cpd::Deterministic;
+    use factor::Factor;
+    use inference::{ConditionalInferenceEngine, MaxProductEngine, VariableEliminationEngine};
+    use init::Initialization;
+    use model::directed::DirectedModelBuilder;
+    use variable::all_assignments;
+
+    #[test]
+    /// A two variable network where the marginal MAP assignment to ```A``` differs from the
+    /// assignment to ```A``` in the most probable explanation.
+    fn differs_from_mpe() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+
+        let cpd_b = Factor::cpd(b, vec![a], array![[0.1, 0.9], [0.5, 0.5]].into_dyn()).unwrap();
+        let model = DirectedModelBuilder::new()
+            .with_variable(&a, HashSet::new(), Initialization::Binomial(0.4))
+            .with_variable(&b, vec![a].into_iter().collect(), Initialization::Table(cpd_b))
+            .build()
+            .unwrap();
+
+        let query: HashSet<Variable> = vec![a].into_iter().collect();
+
+        // MPE is (a0, b1) with probability 0.36
+        let mut mpe = MaxProductEngine::for_directed(&model, &Assignment::new()).unwrap();
+        let (assn, p) = mpe.infer(&query).expect("Inference failed");
+        assert_eq!(Some(&0), assn.get(&a));
+        assert!((p - 0.36).abs() < 1e-10);
+
+        // but P(a1) = 0.6
+        let mut engine = MarginalMapEngine::for_directed(&model, &Assignment::new()).unwrap();
+        let (assn, p) = engine.infer(&query).expect("Inference failed");
+        assert_eq!(Some(&1), assn.get(&a));
+        assert!(assn.get(&b).is_none());
+        assert!((p - 0.6).abs() < 1e-10);
+    }
+
+    #[test]
+    fn matches_marginal() {
+        let c = Variable::binary();
+        let d = Variable::binary();
+        let i = Variable::binary();
+        let g = Variable::discrete(3);
+        let s = Variable::binary();
+        let l = Variable::binary();
+
+        let model = DirectedModelBuilder::new()
+            .with_variable(&c, HashSet::new(), Initialization::Random)
+            .with_variable(&d, vec![c].into_iter().collect(), Initialization::Random)
+            .with_variable(&i, HashSet::new(), Initialization::Random)
+            .with_variable(&g, vec![d, i].into_iter().collect(), Initialization::Random)
+            .with_variable(&s, vec![i].into_iter().collect(), Initialization::Random)
+            .with_variable(&l, vec![g].into_iter().collect(), Initialization::Random)
+            .build()
+            .unwrap();
+
+        let mut evidence = Assignment::new();
+        evidence.set(&l, 0);
+
+        let scope = vec![d, g];
+        let query: HashSet<Variable> = scope.iter().cloned().collect();
+
+        // brute force over the marginal computed by variable elimination
+        let marginal = VariableEliminationEngine::for_directed(&model, &evidence).unwrap()
+                            .infer(&query)
+                            .expect("Inference failed");
+        let best = all_assignments(&scope).map(|a| marginal.value(&a).unwrap()).fold(0.0, f64::max);
+
+        let mut engine = MarginalMapEngine::for_directed(&model, &evidence).unwrap();
+        let (assn, p) = engine.infer(&query).expect("Inference failed");
+
+        assert!((p - best).abs() < 1e-10);
+        assert!((marginal.value(&assn).unwrap() - best).abs() < 1e-10);
+        assert!(assn.get(&c).is_none());
+        assert!(assn.get(&l).is_none());
+    }
+
+    #[test]
+    /// Evidence with probability zero has no marginal MAP assignment
+    fn impossible_evidence() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+        let y = Variable::binary();
+
+        let and = Deterministic::new(y, vec![a, b], |u: &[usize]| u[0] & u[1]).unwrap();
+        let model = DirectedModelBuilder::new()
+            .with_variable(&a, HashSet::new(), Initialization::Binomial(1.0))
+            .with_variable(&b, HashSet::new(), Initialization::Binomial(0.5))
+            .with_variable(&y, vec![a, b].into_iter().collect(), Initialization::Deterministic(and))
+            .build()
+            .unwrap();
+
+        let divide_by_zero = |e: JeromeError| matches!(e, JeromeError::DivideByZero);
+        let query: HashSet<Variable> = vec![b].into_iter().collect();
+
+        // the zero is in a factor reduced to a constant
+        let mut evidence = Assignment::new();
+        evidence.set(&a, 1);
+        let mut engine = MarginalMapEngine::for_directed(&model, &evidence).unwrap();
+        assert!(divide_by_zero(engine.infer(&query).unwrap_err()));
+
+        // the zero is found by summing over A
+        let mut evidence = Assignment::new();
+        evidence.set(&y, 1);
+        let mut engine = MarginalMapEngine::for_directed(&model, &evidence).unwrap();
+        assert!(divide_by_zero(engine.infer(&query).unwrap_err()));
+    }
+
+    #[test]
+    /// On the chain ```A -> B -> C```, summing out ```B``` before maximizing over ```A``` and
+    /// ```C``` connects them, which the unconstrained order avoids.
+    fn constrained_cost() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+        let c = Variable::binary();
+
+        let model = DirectedModelBuilder::new()
+            .with_variable(&a, HashSet::new(), Initialization::Random)
+            .with_variable(&b, vec![a].into_iter().collect(), Initialization::Random)
+            .with_variable(&c, vec![b].into_iter().collect(), Initialization::Random)
+            .build()
+            .unwrap();
+
+        let engine = MarginalMapEngine::for_directed(&model, &Assignment::new()).unwrap();
+
+        let cost = engine.cost(&vec![a, c].into_iter().collect()).unwrap();
+        assert_eq!(2, cost.induced_width);
+        assert_eq!(8, cost.max_factor_size);
+
+        let cost = engine.cost(&vec![b].into_iter().collect()).unwrap();
+        assert_eq!(1, cost.induced_width);
+
+        assert!(engine.cost(&HashSet::new()).is_err());
+    }
+
+    #[test]
+    fn invalid_scope() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+
+        let model = DirectedModelBuilder::new()
+            .with_variable(&a, HashSet::new(), Initialization::Random)
+            .with_variable(&b, vec![a].into_iter().collect(), Initialization::Random)
+            .build()
+            .unwrap();
+
+        let mut evidence = Assignment::new();
+        evidence.set(&b, 1);
+
+        let mut engine = MarginalMapEngine::for_directed(&model, &evidence).unwrap();
+        assert!(engine.infer(&HashSet::new()).is_err());
+        assert!(engine.infer(&vec![b].into_iter().collect()).is_err());
+        assert!(engine.infer(&vec![a].into_iter().collect()).is_ok());
+    }
+
+}
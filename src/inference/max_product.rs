@@ -0,0 +1,616 @@
+//! Defines a `MapInferenceEngine` that uses exact inference by max-product variable elimination
+//! to answer MAP queries.
+//!
+//! Implementation of Koller & Friedman Algorithm 13.1 - Max-Product-VE, with the traceback
+//! procedure of Algorithm 13.1 used to recover the most probable assignment.
+
+use factor::Factor;
+use model::Model;
+use model::directed::DirectedModel;
+use model::undirected::UndirectedModel;
+use super::elimination_ordering::{elimination_cost, restrict_order, EliminationCost, EliminationOrdering, MaxCardinality};
+use super::MapInferenceEngine;
+use super::variable_elimination::{eliminate, log_observed, Elimination};
+use util::{JeromeError, Result};
+use variable::{Assignment, Variable};
+
+use std::convert::TryFrom;
+use std::collections::HashSet;
+
+/// A `MapInferenceEngine` that computes the most probable explanation of the evidence by
+/// max-product variable elimination.
+///
+/// Every unobserved `Variable` is maximized over, so the answer to a query ```Y``` is the
+/// restriction of the most probable full assignment ```x* = argmax_x P(x | e)``` to ```Y```. The
+/// associated probability is the max-marginal ```max_z P(Y = y*, Z = z | e)```, which is
+/// ```P(x* | e)```. To sum out the non-query variables instead, see Marginal MAP.
+pub struct MaxProductEngine {
+
+    /// the `UndirectedModel` (a 'bag of factors'), conditioned on the provided evidence, to use
+    /// for the inference task
+    model: UndirectedModel,
+
+    /// precomputed preferred elimination order based on max-cardinality heuristic
+    order: Vec<Variable>,
+
+    /// the most probable full assignment and its probability, computed on the first query
+    mpe: Option<(Assignment, f64)>,
+
+    /// An error encountered during construction, which is reported by every query
+    err: Option<JeromeError>
+
+}
+
+
+impl MaxProductEngine {
+
+    /// Construct the engine for a `DirectedModel`, by converting it to an `UndirectedModel`.
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if the model has a continuous `Variable`
+    pub fn for_directed(model: &DirectedModel, evidence: &Assignment) -> Result<Self> {
+        Ok(MaxProductEngine::for_undirected(
+            &UndirectedModel::try_from(model)?,
+            evidence
+        ))
+    }
+
+    pub fn for_undirected(model: &UndirectedModel, evidence: &Assignment) -> Self {
+        // reduce the provided model with the evidence - this is the model we will use for variable
+        // elimination
+        let reduced = model.condition(evidence);
+        // precompute the preferred elimination order using the max-cardinatlity heuristic.
+        let order = MaxCardinality.order(&reduced);
+
+        MaxProductEngine {
+            model: reduced,
+            order,
+            mpe: None,
+            // a fully observed factor with value zero means the evidence has probability zero
+            err: if log_observed(model, evidence) == f64::NEG_INFINITY { Some(JeromeError::DivideByZero) } else { None }
+        }
+    }
+
+    /// Choose the elimination order with the given `EliminationOrdering` heuristic, rather than
+    /// the default max-cardinality heuristic.
+    pub fn with_ordering(mut self, ordering: &dyn EliminationOrdering) -> Self {
+        self.order = ordering.order(&self.model);
+        self.mpe = None;
+        self
+    }
+
+    /// Use a user-supplied elimination order. Observed `Variable`s may be included; they are
+    /// ignored.
+    ///
+    /// # Errors
+    /// `JeromeError::InvalidScope` if `order` does not contain every unobserved `Variable` exactly
+    /// once
+    pub fn with_order(mut self, order: &[Variable]) -> Result<Self> {
+        self.order = restrict_order(&self.model, order)?;
+        self.mpe = None;
+        Ok(self)
+    }
+
+    /// The cost of running variable elimination with the current elimination order
+    pub fn cost(&self) -> EliminationCost {
+        // invariant: the order always contains every variable in the model
+        elimination_cost(&self.model, &self.order).unwrap()
+    }
+
+    /// Infer the ```k``` most probable assignments to `variables` given the evidence.
+    ///
+    /// As with `infer`, every unobserved `Variable` not in `variables` is maximized over, so each
+    /// result is scored by its max-marginal ```max_z P(Y = y, Z = z | e)```. When `variables`
+    /// contains every unobserved `Variable`, the results are the ```k``` most probable full
+    /// assignments.
+    ///
+    /// # Args
+    /// * `variables`: the query variables
+    /// * `k`: the number of assignments to return
+    ///
+    /// # Returns
+    /// up to ```k``` distinct `Assignment`s to `variables` and their probabilities, in decreasing
+    /// order of probability. Fewer than ```k``` are returned only if there are fewer than ```k```
+    /// possible assignments.
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if a `Variable` of the query is not in the reduced model
+    /// * `JeromeError::DivideByZero`, if the evidence has probability zero
+    pub fn infer_top_k(&mut self, variables: &HashSet<Variable>, k: usize) -> Result<Vec<(Assignment, f64)>> {
+        if let Some(ref e) = self.err {
+            return Err(e.clone());
+        }
+
+        // check input arguments
+        if variables.is_empty() || variables.iter().any(|v| ! self.model.variables().contains(v)) {
+            // a variable requested is not found in the (reduced) model
+            return Err(JeromeError::InvalidScope);
+        }
+
+        // the partition function of the reduced model is computed by the first query, and cached
+        // for the rest
+        let log_z = self.model.log_partition();
+        if log_z == f64::NEG_INFINITY {
+            return Err(JeromeError::DivideByZero);
+        }
+
+        // eliminate the variables that are not in the query first, keeping only the best value
+        let (max_order, rest): (Vec<Variable>, Vec<Variable>) = self.order
+                                                                    .iter()
+                                                                    .partition(|v| variables.contains(v));
+        let mut phis = self.model.factors().clone();
+        let mut log_constant = 0.0;
+        for &var in rest.iter() {
+            let (remaining, _, c) = eliminate(phis, var, Elimination::Max);
+            phis = remaining;
+            log_constant += c;
+        }
+
+        // then eliminate the query variables, keeping the k best values for each entry
+        let mut tables: Vec<KBestTable> = phis.iter().map(KBestTable::from).collect();
+        for &var in max_order.iter() {
+            let (psi, remaining): (Vec<KBestTable>, Vec<KBestTable>) = tables
+                                                                       .into_iter()
+                                                                       .partition(|t| t.scope.contains(&var));
+            tables = remaining;
+            let psi = psi.iter().fold(KBestTable::unit(), |acc, t| acc.product(t, k));
+            tables.push(psi.max_out(var, k));
+        }
+
+        let best = tables.iter().fold(KBestTable::unit(), |acc, t| acc.product(t, k));
+
+        Ok(best.entries[0].iter().map(|&(lp, ref decisions)| {
+            let mut assignment = Assignment::new();
+            for &(var, val) in decisions.iter() {
+                assignment.set(&var, val);
+            }
+
+            (assignment, (lp + log_constant - log_z).exp())
+        }).collect())
+    }
+
+    /// Run Max-Product-VE over the reduced model and trace back the argmax decisions.
+    fn most_probable_explanation(&self) -> Result<(Assignment, f64)> {
+        let (assignment, log_max) = max_product_ve(self.model.factors().clone(), &self.order)?;
+
+        // every assignment has probability zero only if the evidence does
+        if log_max == f64::NEG_INFINITY {
+            return Err(JeromeError::DivideByZero);
+        }
+
+        let p = self.model.probability(&assignment)?;
+        Ok((assignment, p))
+    }
+
+}
+
+
+/// Run Max-Product-VE over a set of `Factor`s, maximizing over every `Variable` in `order`, and
+/// trace back the argmax decisions.
+///
+/// # Args
+/// * `factors`: the `Factor`s
+/// * `order`: an elimination order that contains every `Variable` in the scope of `factors`
+///
+/// # Returns
+/// the maximizing `Assignment` to the `Variable`s in `order`, and the log of the (unnormalized)
+/// maximum value of the product of `factors`
+pub(super) fn max_product_ve(factors: Vec<Factor>, order: &[Variable]) -> Result<(Assignment, f64)> {
+    // eliminate every variable, retaining the intermediate factors for the traceback
+    let mut phis = factors;
+    let mut psis: Vec<Factor> = Vec::with_capacity(order.len());
+    let mut log_max = 0.0;
+    for &var in order.iter() {
+        let (remaining, psi, log_constant) = eliminate(phis, var, Elimination::Max);
+        phis = remaining;
+        psis.push(psi);
+        log_max += log_constant;
+    }
+
+    // traceback - the factor produced when eliminating a variable only involves variables
+    // eliminated after it, so walking the order in reverse means each factor is fully assigned
+    // except for the variable being decoded.
+    let mut assignment = Assignment::new();
+    for (&var, psi) in order.iter().zip(psis.iter()).rev() {
+        let mut best = (0, f64::NEG_INFINITY);
+        if ! psi.is_identity() {
+            for val in 0..var.cardinality() {
+                assignment.set(&var, val);
+                let p = psi.log_value(&assignment)?;
+                // strict comparison - ties go to the lowest value
+                if p > best.1 {
+                    best = (val, p);
+                }
+            }
+        }
+
+        assignment.set(&var, best.0);
+    }
+
+    Ok((assignment, log_max))
+}
+
+
+/// A candidate in a `KBestTable` - the log of a value and the argmax decisions that produced it
+type Candidate = (f64, Vec<(Variable, usize)>);
+
+
+/// A max-product message table that retains the ```k``` best values of each entry, rather than only
+/// the maximum. Each candidate carries the decisions made when eliminating variables, so that no
+/// separate traceback is required. Values are kept in log space, so that long products do not
+/// underflow.
+struct KBestTable {
+
+    /// the scope of the table
+    scope: Vec<Variable>,
+
+    /// the candidates for each assignment to `scope`, in row-major order, sorted by decreasing
+    /// value
+    entries: Vec<Vec<Candidate>>
+
+}
+
+
+impl KBestTable {
+
+    /// The table with empty scope and a single candidate of value 1
+    fn unit() -> Self {
+        KBestTable { scope: Vec::new(), entries: vec![vec![(0.0, Vec::new())]] }
+    }
+
+    /// Compute the row-major index of an assignment to `scope`
+    fn index(scope: &[Variable], assignment: &Assignment) -> usize {
+        scope.iter().fold(0, |idx, v| idx * v.cardinality() + assignment.get(v).unwrap())
+    }
+
+    /// Compute the assignment to `scope` at a row-major index
+    fn assignment(scope: &[Variable], idx: usize) -> Assignment {
+        let mut assignment = Assignment::new();
+        let mut rem = idx;
+        for v in scope.iter().rev() {
+            assignment.set(v, rem % v.cardinality());
+            rem /= v.cardinality();
+        }
+
+        assignment
+    }
+
+    /// The number of entries in a table over `scope`
+    fn size(scope: &[Variable]) -> usize {
+        scope.iter().map(|v| v.cardinality()).product()
+    }
+
+    /// Keep the best ```k``` candidates. The sort is stable, so ties are broken deterministically.
+    fn best(mut candidates: Vec<Candidate>, k: usize) -> Vec<Candidate> {
+        candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+        candidates.truncate(k);
+        candidates
+    }
+
+    /// Multiply two tables, keeping the best ```k``` combinations of candidates for each entry
+    fn product(&self, other: &KBestTable, k: usize) -> Self {
+        let mut scope = self.scope.clone();
+        scope.extend(other.scope.iter().filter(|v| ! self.scope.contains(v)));
+
+        let entries = (0..KBestTable::size(&scope)).map(|idx| {
+            let assignment = KBestTable::assignment(&scope, idx);
+            let mine = &self.entries[KBestTable::index(&self.scope, &assignment)];
+            let theirs = &other.entries[KBestTable::index(&other.scope, &assignment)];
+
+            let candidates = mine.iter().flat_map(|a| theirs.iter().map(move |b| {
+                let mut decisions = a.1.clone();
+                decisions.extend(b.1.iter().cloned());
+                (a.0 + b.0, decisions)
+            })).collect();
+
+            KBestTable::best(candidates, k)
+        }).collect();
+
+        KBestTable { scope, entries }
+    }
+
+    /// Maximize over `var`, keeping the best ```k``` candidates over all values of `var`
+    fn max_out(&self, var: Variable, k: usize) -> Self {
+        let scope: Vec<Variable> = self.scope.iter().cloned().filter(|&v| v != var).collect();
+
+        let entries = (0..KBestTable::size(&scope)).map(|idx| {
+            let mut assignment = KBestTable::assignment(&scope, idx);
+            let mut candidates = Vec::new();
+            for val in 0..var.cardinality() {
+                assignment.set(&var, val);
+                for &(lp, ref decisions) in self.entries[KBestTable::index(&self.scope, &assignment)].iter() {
+                    let mut decisions = decisions.clone();
+                    decisions.push((var, val));
+                    candidates.push((lp, decisions));
+                }
+            }
+
+            KBestTable::best(candidates, k)
+        }).collect();
+
+        KBestTable { scope, entries }
+    }
+
+}
+
+
+impl<'a> From<&'a Factor> for KBestTable {
+
+    fn from(phi: &'a Factor) -> Self {
+        let scope = phi.scope();
+        let entries = (0..KBestTable::size(&scope)).map(|idx| {
+            let assignment = KBestTable::assignment(&scope, idx);
+            vec![(phi.log_value(&assignment).unwrap(), Vec::new())]
+        }).collect();
+
+        KBestTable { scope, entries }
+    }
+
+}
+
+
+impl MapInferenceEngine for MaxProductEngine {
+
+    fn infer(&mut self, variables: &HashSet<Variable>) -> Result<(Assignment, f64)> {
+        if let Some(ref e) = self.err {
+            return Err(e.clone());
+        }
+
+        // check input arguments
+        if variables.is_empty() || variables.iter().any(|v| ! self.model.variables().contains(v)) {
+            // a variable requested is not found in the (reduced) model
+            return Err(JeromeError::InvalidScope);
+        }
+
+        if self.mpe.is_none() {
+            self.mpe = Some(self.most_probable_explanation()?);
+        }
+
+        let (ref mpe, p) = *self.mpe.as_ref().unwrap();
+        let mut assignment = Assignment::new();
+        for v in variables.iter() {
+            assignment.set(v, mpe.get(v).cloned().unwrap());
+        }
+
+        Ok((assignment, p))
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use cpd::Deterministic;
+    use inference::{ConditionalInferenceEngine, VariableEliminationEngine};
+    use init::Initialization;
+    use model::directed::DirectedModelBuilder;
+    use variable::all_assignments;
+
+    /// Build the extended student example with random CPDs
+    fn student() -> (Vec<Variable>, DirectedModel) {
+        let c = Variable::binary();
+        let d = Variable::binary();
+        let i = Variable::binary();
+        let g = Variable::discrete(3);
+        let s = Variable::binary();
+        let l = Variable::binary();
+        let j = Variable::binary();
+        let h = Variable::binary();
+
+        let model = DirectedModelBuilder::new()
+            .with_variable(&c, HashSet::new(), Initialization::Random)
+            .with_variable(&d, vec![c].into_iter().collect(), Initialization::Random)
+            .with_variable(&i, HashSet::new(), Initialization::Random)
+            .with_variable(&g, vec![d, i].into_iter().collect(), Initialization::Random)
+            .with_variable(&s, vec![i].into_iter().collect(), Initialization::Random)
+            .with_variable(&l, vec![g].into_iter().collect(), Initialization::Random)
+            .with_variable(&j, vec![l, s].into_iter().collect(), Initialization::Random)
+            .with_variable(&h, vec![g, j].into_iter().collect(), Initialization::Random)
+            .build()
+            .unwrap();
+
+        (vec![c, d, i, g, s, l, j, h], model)
+    }
+
+    #[test]
+    fn most_probable_explanation() {
+        let (vars, model) = student();
+        let mut evidence = Assignment::new();
+        evidence.set(&vars[5], 1);
+        let reduced = UndirectedModel::try_from(&model).unwrap().condition(&evidence);
+
+        // brute force the MPE
+        let unobserved: Vec<Variable> = vars.iter().cloned().filter(|v| *v != vars[5]).collect();
+        let best = all_assignments(&unobserved)
+                        .map(|a| { let p = reduced.probability(&a).unwrap(); (a, p) })
+                        .fold(None, |acc: Option<(Assignment, f64)>, (a, p)| match acc {
+                            Some((_, q)) if q >= p => acc,
+                            _ => Some((a, p))
+                        })
+                        .unwrap();
+
+        let mut engine = MaxProductEngine::for_directed(&model, &evidence).unwrap();
+        let query: HashSet<Variable> = unobserved.iter().cloned().collect();
+        let (mpe, p) = engine.infer(&query).expect("Inference failed");
+
+        assert!((p - best.1).abs() < 1e-10);
+        for v in unobserved.iter() {
+            assert_eq!(mpe.get(v), best.0.get(v));
+        }
+    }
+
+    #[test]
+    fn query_subset() {
+        let (vars, model) = student();
+        let mut evidence = Assignment::new();
+        evidence.set(&vars[7], 0);
+
+        let mut engine = MaxProductEngine::for_directed(&model, &evidence).unwrap();
+        let all: HashSet<Variable> = vars.iter().cloned().filter(|v| *v != vars[7]).collect();
+        let (full, p_full) = engine.infer(&all).expect("Inference failed");
+
+        let query: HashSet<Variable> = vec![vars[2], vars[3]].into_iter().collect();
+        let (mpe, p) = engine.infer(&query).expect("Inference failed");
+
+        // the query is answered with the restriction of the full MPE
+        assert_eq!(p_full, p);
+        assert_eq!(mpe.get(&vars[2]), full.get(&vars[2]));
+        assert_eq!(mpe.get(&vars[3]), full.get(&vars[3]));
+        assert!(mpe.get(&vars[0]).is_none());
+
+        // the probability of the MPE cannot exceed the marginal probability of its restriction
+        let mut ve = VariableEliminationEngine::for_directed(&model, &evidence).unwrap();
+        let marginal = ve.infer(&query).expect("Inference failed");
+        assert!(p <= marginal.value(&mpe).unwrap() + 1e-10);
+    }
+
+    #[test]
+    fn top_k() {
+        let (vars, model) = student();
+        let mut evidence = Assignment::new();
+        evidence.set(&vars[4], 0);
+        let reduced = UndirectedModel::try_from(&model).unwrap().condition(&evidence);
+
+        // brute force the ranking of full assignments
+        let unobserved: Vec<Variable> = vars.iter().cloned().filter(|v| *v != vars[4]).collect();
+        let mut ranked: Vec<f64> = all_assignments(&unobserved)
+                                        .map(|a| reduced.probability(&a).unwrap())
+                                        .collect();
+        ranked.sort_by(|a, b| b.partial_cmp(a).unwrap());
+
+        let mut engine = MaxProductEngine::for_directed(&model, &evidence).unwrap();
+        let query: HashSet<Variable> = unobserved.iter().cloned().collect();
+        let best = engine.infer_top_k(&query, 10).expect("Inference failed");
+
+        assert_eq!(10, best.len());
+        for (&(ref assn, p), &expected) in best.iter().zip(ranked.iter()) {
+            assert!((p - expected).abs() < 1e-10);
+            assert!((reduced.probability(assn).unwrap() - expected).abs() < 1e-10);
+        }
+
+        // the best assignment is the MPE
+        let (mpe, p) = engine.infer(&query).expect("Inference failed");
+        assert!((best[0].1 - p).abs() < 1e-10);
+        for v in unobserved.iter() {
+            assert_eq!(best[0].0.get(v), mpe.get(v));
+        }
+    }
+
+    #[test]
+    /// The probability of every assignment to a long chain underflows in linear space, so ranking
+    /// the candidates must be done in log space.
+    fn top_k_long_chain() {
+        let vars: Vec<Variable> = (0..1500).map(|_| Variable::binary()).collect();
+
+        // each variable prefers to be 1, whatever its parent
+        let builder = vars.windows(2).fold(
+            DirectedModelBuilder::new().with_variable(&vars[0], HashSet::new(), Initialization::Binomial(0.5)),
+            |builder, w| {
+                let cpd = Factor::cpd(w[1], vec![w[0]], array![[0.4, 0.6], [0.45, 0.55]].into_dyn()).unwrap();
+                builder.with_variable(&w[1], vec![w[0]].into_iter().collect(), Initialization::Table(cpd))
+            }
+        );
+        let model = UndirectedModel::try_from(&builder.build().unwrap()).unwrap().to_log();
+
+        let mut engine = MaxProductEngine::for_undirected(&model, &Assignment::new());
+        let query: HashSet<Variable> = vars.iter().cloned().collect();
+        let best = engine.infer_top_k(&query, 2).expect("Inference failed");
+        let (mpe, _) = engine.infer(&query).expect("Inference failed");
+
+        assert_eq!(2, best.len());
+        assert!(vars.iter().skip(1).all(|v| mpe.get(v) == Some(&1)));
+        for v in vars.iter() {
+            assert_eq!(best[0].0.get(v), mpe.get(v));
+        }
+        assert!(vars.iter().any(|v| best[1].0.get(v) != mpe.get(v)));
+    }
+
+    #[test]
+    fn top_k_subset() {
+        let (vars, model) = student();
+        let reduced = UndirectedModel::try_from(&model).unwrap();
+
+        // brute force the max-marginals of (I, G)
+        let scope = vec![vars[2], vars[3]];
+        let rest: Vec<Variable> = vars.iter().cloned().filter(|v| ! scope.contains(v)).collect();
+        let mut ranked: Vec<f64> = all_assignments(&scope).map(|y| {
+            all_assignments(&rest).map(|z| {
+                let mut a = z.clone();
+                for v in scope.iter() {
+                    a.set(v, *y.get(v).unwrap());
+                }
+
+                reduced.probability(&a).unwrap()
+            }).fold(0.0, f64::max)
+        }).collect();
+        ranked.sort_by(|a, b| b.partial_cmp(a).unwrap());
+
+        // asking for more assignments than exist returns all of them
+        let mut engine = MaxProductEngine::for_directed(&model, &Assignment::new()).unwrap();
+        let query: HashSet<Variable> = scope.iter().cloned().collect();
+        let best = engine.infer_top_k(&query, 10).expect("Inference failed");
+
+        assert_eq!(6, best.len());
+        for (&(ref assn, p), &expected) in best.iter().zip(ranked.iter()) {
+            assert!((p - expected).abs() < 1e-10);
+            assert!(assn.get(&vars[0]).is_none());
+        }
+
+        // every assignment is distinct
+        let distinct: HashSet<(usize, usize)> = best.iter()
+                                                    .map(|(a, _)| (*a.get(&vars[2]).unwrap(), *a.get(&vars[3]).unwrap()))
+                                                    .collect();
+        assert_eq!(6, distinct.len());
+    }
+
+    #[test]
+    /// Evidence with probability zero has no most probable explanation
+    fn impossible_evidence() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+        let y = Variable::binary();
+
+        let and = Deterministic::new(y, vec![a, b], |u: &[usize]| u[0] & u[1]).unwrap();
+        let model = DirectedModelBuilder::new()
+            .with_variable(&a, HashSet::new(), Initialization::Binomial(0.5))
+            .with_variable(&b, HashSet::new(), Initialization::Binomial(0.5))
+            .with_variable(&y, vec![a, b].into_iter().collect(), Initialization::Deterministic(and))
+            .build()
+            .unwrap();
+
+        let divide_by_zero = |e: JeromeError| matches!(e, JeromeError::DivideByZero);
+
+        // the zero is found by maximizing over B
+        let mut evidence = Assignment::new();
+        evidence.set(&a, 0);
+        evidence.set(&y, 1);
+
+        let query: HashSet<Variable> = vec![b].into_iter().collect();
+        let mut engine = MaxProductEngine::for_directed(&model, &evidence).unwrap();
+        assert!(divide_by_zero(engine.infer(&query).unwrap_err()));
+        assert!(divide_by_zero(engine.infer_top_k(&query, 2).unwrap_err()));
+
+        // the zero is in a factor reduced to a constant
+        evidence.set(&b, 0);
+        let mut engine = MaxProductEngine::for_directed(&model, &evidence).unwrap();
+        let query: HashSet<Variable> = HashSet::new();
+        assert!(divide_by_zero(engine.infer(&query).unwrap_err()));
+        assert!(divide_by_zero(engine.infer_top_k(&query, 2).unwrap_err()));
+    }
+
+    #[test]
+    fn invalid_scope() {
+        let (vars, model) = student();
+        let mut evidence = Assignment::new();
+        evidence.set(&vars[0], 1);
+
+        let mut engine = MaxProductEngine::for_directed(&model, &evidence).unwrap();
+        assert!(engine.infer(&HashSet::new()).is_err());
+
+        let query: HashSet<Variable> = vec![vars[0]].into_iter().collect();
+        assert!(engine.infer(&query).is_err());
+    }
+
+}
@@ -52,10 +52,8 @@ impl<'a, S: 'a + Sampler> ConditionalInferenceEngine for McmcEngine<'a, S> {
             let idx: Vec<Option<&usize>> = scope.iter().map(|v| a.get(v)).collect();
 
             // on the first iteration, verify the assignment matches the scope
-            if i == 0 {
-                if idx.iter().any(|v| v.is_none()) {
-                    return Err(JeromeError::InvalidScope);
-                }
+            if i == 0 && idx.iter().any(|v| v.is_none()) {
+                return Err(JeromeError::InvalidScope);
             }
 
             let idx: Vec<usize> = idx.iter().map(|v| v.unwrap()).cloned().collect();
@@ -64,7 +62,7 @@ impl<'a, S: 'a + Sampler> ConditionalInferenceEngine for McmcEngine<'a, S> {
         }
 
         let factor = Factor::new(scope, table)?;
-        Ok(factor.normalize())
+        factor.normalize()
     }
 
 }
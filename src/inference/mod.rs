@@ -6,13 +6,31 @@ use super::Result;
 
 use std::collections::HashSet;
 
+mod clique_tree;
+mod elimination_ordering;
+mod gaussian;
 mod importance_sampling;
+mod loopy_belief_propagation;
+mod marginal_map;
+mod max_product;
 mod mcmc;
+mod particle_filter;
 mod variable_elimination;
 
+pub use self::clique_tree::CliqueTreeEngine;
+pub use self::elimination_ordering::{
+    elimination_cost, EliminationCost, EliminationOrdering, MaxCardinality, MinFill, MinNeighbors, MinWeight,
+    WeightedMinFill
+};
+pub use self::gaussian::GaussianInferenceEngine;
 pub use self::importance_sampling::ImportanceSamplingEngine;
+pub use self::loopy_belief_propagation::{LoopyBeliefPropagationEngine, Schedule};
+pub use self::marginal_map::MarginalMapEngine;
+pub use self::max_product::MaxProductEngine;
 pub use self::mcmc::McmcEngine;
+pub use self::particle_filter::{ParticleFilter, Resampling};
 pub use self::variable_elimination::VariableEliminationEngine;
+pub(crate) use self::variable_elimination::{log_partition, partition};
 
 
 /// A `ConditionalInferenceEngine` is capable of answering Conditional Probability Queries of the form:
@@ -28,6 +46,27 @@ pub trait ConditionalInferenceEngine {
 }
 
 
+/// An `EvidenceProbabilityEngine` is capable of computing the probability of the evidence:
+///     ```P(E = e)```
+///
+/// This is discarded when answering normalized queries, but is the likelihood of the model given
+/// the evidence, which is useful for model comparison and anomaly scoring.
+///
+/// `EvidenceProbabilityEngine`s are stateful and must take the evidence `e` as an argument to
+/// whatever construction mechanism they employ.
+pub trait EvidenceProbabilityEngine {
+
+    /// Compute (or estimate) ```P(E = e)```
+    fn probability_of_evidence(&mut self) -> Result<f64>;
+
+    /// Compute (or estimate) ```log P(E = e)```
+    fn log_probability_of_evidence(&mut self) -> Result<f64> {
+        self.probability_of_evidence().map(f64::ln)
+    }
+
+}
+
+
 /// A `MapInferenceEngine` is cable of answering Maximum a posteiori queries:
 ///     ```MAP(Y | E = e) = argmax_y P(Y = y | E = e)```
 ///
@@ -35,8 +74,11 @@ pub trait ConditionalInferenceEngine {
 /// construction mechanism they employ.
 pub trait MapInferenceEngine {
 
-    /// Infer the most probable assignment `Y = y` given the 
-    fn infer(&self, variables: &HashSet<Variable>) -> Result<Assignment>;
+    /// Infer the most probable assignment `Y = y` given the evidence.
+    ///
+    /// # Returns
+    /// the most probable `Assignment` to `variables`, and its probability given the evidence
+    fn infer(&mut self, variables: &HashSet<Variable>) -> Result<(Assignment, f64)>;
 
 }
 
@@ -53,11 +95,19 @@ pub trait MapInferenceEngine {
 /// of the K&F Student example. We use that result here to test our implementation.
 ///
 /// [1] https://www.uni-oldenburg.de/en/lcs/probabilistic-programming/webchurch-and-openbugs/
+#[allow(clippy::nonminimal_bool)]
 mod tests {
     use super::*;
+    use model::Model;
     use model::directed::{DirectedModel, DirectedModelBuilder};
+    use model::undirected::{UndirectedModel, UndirectedModelBuilder};
+    use variable::all_assignments;
+    use cpd::{Deterministic, NoisyMax};
+    use gaussian::{ConditionalLinearGaussian, LinearGaussian};
     use init::Initialization;
-    use samplers::{GibbsSampler, LikelihoodWeightedSampler};
+    use samplers::{ForwardSampler, GibbsSampler, LikelihoodWeightedSampler, Sampler};
+    use util::JeromeError;
+    use std::convert::TryFrom;
 
     /// Utility function to build the student inference example
     fn build_student_example() -> (Variable, DirectedModel, Assignment) {
@@ -95,7 +145,7 @@ mod tests {
     }
     
     /// Utility method to test the actual inference task
-    fn test_inference(i: Variable, engine: &mut ConditionalInferenceEngine, precision: f64) {
+    fn test_inference(i: Variable, engine: &mut dyn ConditionalInferenceEngine, precision: f64) {
         let f = engine.infer(&vec![i].into_iter().collect());
 
         assert!(! f.is_err());
@@ -116,7 +166,65 @@ mod tests {
         let (i, model, evidence) = build_student_example();
 
         // note that this implicitly tests for_undirected as well!
-        let mut engine = VariableEliminationEngine::for_directed(&model, &evidence);
+        let mut engine = VariableEliminationEngine::for_directed(&model, &evidence).unwrap();
+
+        // the result should be the same on subsequent iterations
+        for _ in 0..10 {
+            test_inference(i, &mut engine, 0.00000001);
+        }
+    }
+    
+    #[test]
+    /// Test variable elimination with each elimination ordering heuristic and a user-supplied order
+    fn variable_elimination_orderings() {
+        let (i, model, evidence) = build_student_example();
+
+        let orderings: Vec<Box<dyn EliminationOrdering>> = vec![
+            Box::new(MaxCardinality), 
+            Box::new(MinNeighbors), 
+            Box::new(MinWeight), 
+            Box::new(MinFill), 
+            Box::new(WeightedMinFill)
+        ];
+
+        for ordering in orderings.iter() {
+            let mut engine = VariableEliminationEngine::for_directed(&model, &evidence).unwrap()
+                                .with_ordering(ordering.as_ref());
+            assert_eq!(1, engine.cost().induced_width);
+            test_inference(i, &mut engine, 0.00000001);
+        }
+
+        // the user-supplied order may include the observed variables
+        let order: Vec<Variable> = model.variables().into_iter().collect();
+        let mut engine = VariableEliminationEngine::for_directed(&model, &evidence).unwrap()
+                            .with_order(&order)
+                            .unwrap();
+        test_inference(i, &mut engine, 0.00000001);
+
+        let missing: Vec<Variable> = order.into_iter().filter(|&v| v != i).collect();
+        assert!(VariableEliminationEngine::for_directed(&model, &evidence).unwrap().with_order(&missing).is_err());
+    }
+    
+    #[test]
+    /// Test variable elimination over a model in log-space
+    fn variable_elimination_log_space() {
+        let (i, model, evidence) = build_student_example();
+
+        let log_model = UndirectedModel::try_from(&model).unwrap().to_log();
+        let mut engine = VariableEliminationEngine::for_undirected(&log_model, &evidence);
+        test_inference(i, &mut engine, 0.00000001);
+
+        let mut linear = VariableEliminationEngine::for_directed(&model, &evidence).unwrap();
+        let expected = linear.log_probability_of_evidence().unwrap();
+        assert!((engine.log_probability_of_evidence().unwrap() - expected).abs() < 1e-10);
+    }
+
+    #[test]
+    /// Test clique tree calibration
+    fn clique_tree() {
+        let (i, model, evidence) = build_student_example();
+
+        let mut engine = CliqueTreeEngine::for_directed(&model, &evidence).unwrap();
 
         // the result should be the same on subsequent iterations
         for _ in 0..10 {
@@ -124,6 +232,20 @@ mod tests {
         }
     }
     
+    #[test]
+    /// Test loopy belief propagation. The student network is not a tree, so the result is only
+    /// approximate.
+    fn loopy_belief_propagation() {
+        let (i, model, evidence) = build_student_example();
+
+        let mut engine = LoopyBeliefPropagationEngine::for_directed(&model, &evidence).unwrap();
+
+        // the result should be the same on subsequent iterations
+        for _ in 0..10 {
+            test_inference(i, &mut engine, 0.01);
+        }
+    }
+    
     #[test]
     /// Test importance sampling
     fn importance_sampling() {
@@ -139,13 +261,78 @@ mod tests {
         }
     }
     
+    #[test]
+    /// Test the probability of evidence by exact and approximate inference
+    fn probability_of_evidence() {
+        let (_, model, evidence) = build_student_example();
+
+        // brute force P(e) by summing the joint over the unobserved variables
+        let joint = UndirectedModel::try_from(&model).unwrap();
+        let unobserved: Vec<Variable> = model.variables()
+                                             .into_iter()
+                                             .filter(|v| evidence.get(v).is_none())
+                                             .collect();
+        let expected: f64 = all_assignments(&unobserved).map(|a| {
+            let mut full = a.clone();
+            for v in model.variables() {
+                if let Some(&val) = evidence.get(&v) {
+                    full.set(&v, val);
+                }
+            }
+
+            joint.probability(&full).unwrap()
+        }).sum();
+
+        let mut engine = VariableEliminationEngine::for_directed(&model, &evidence).unwrap();
+        assert!((engine.probability_of_evidence().unwrap() - expected).abs() < 1e-10);
+        assert!((engine.log_probability_of_evidence().unwrap() - expected.ln()).abs() < 1e-10);
+
+        let mut sampler = LikelihoodWeightedSampler::new(&model, &evidence);
+        let mut engine = ImportanceSamplingEngine::new(&mut sampler, 20000);
+        let estimate = engine.probability_of_evidence().unwrap();
+        assert!((estimate - expected).abs() < 0.1 * expected);
+    }
+
+    #[test]
+    /// Test the probability of evidence on a model that is not normalized
+    fn probability_of_evidence_undirected() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+        let c = Variable::binary();
+
+        let model = UndirectedModelBuilder::new()
+            .with_factor(vec![a, b].into_iter().collect(), Initialization::Random)
+            .with_factor(vec![b, c].into_iter().collect(), Initialization::Random)
+            .with_factor(vec![a].into_iter().collect(), Initialization::Random)
+            .build()
+            .unwrap();
+
+        // a is fully observed, so its singleton factor is dropped when conditioning
+        let mut evidence = Assignment::new();
+        evidence.set(&a, 1);
+        evidence.set(&c, 0);
+
+        let expected: f64 = (0..2).map(|val| {
+            let mut full = evidence.clone();
+            full.set(&b, val);
+            model.probability(&full).unwrap()
+        }).sum();
+
+        let mut engine = VariableEliminationEngine::for_undirected(&model, &evidence);
+        assert!((engine.probability_of_evidence().unwrap() - expected).abs() < 1e-10);
+
+        // with no evidence, P(e) is one
+        let mut engine = VariableEliminationEngine::for_undirected(&model, &Assignment::new());
+        assert!((engine.probability_of_evidence().unwrap() - 1.0).abs() < 1e-10);
+    }
+
     #[test]
     /// Test importance sampling
     fn mcmc() {
         let (i, model, evidence) = build_student_example();
 
         // note that this implicitly tests for_undirected as well!
-        let mut sampler = GibbsSampler::for_directed(&model, &evidence);
+        let mut sampler = GibbsSampler::for_directed(&model, &evidence).unwrap();
         let mut engine = McmcEngine::new(&mut sampler, 10000, 2000);
 
         // the result should be the same on subsequent iterations
@@ -154,4 +341,270 @@ mod tests {
         }
     }
 
+
+    #[test]
+    /// X ~ N(1, 4), Y | X ~ N(2x + 3, 1), Z | Y ~ N(1 - y, 2), which has mean (1, 5, -4) and
+    /// covariance [[4, 8, -8], [8, 17, -17], [-8, -17, 19]]
+    fn gaussian() {
+        let x = Variable::continuous();
+        let y = Variable::continuous();
+        let z = Variable::continuous();
+
+        let model = DirectedModelBuilder::new()
+            .with_variable(&x, HashSet::new(), Initialization::Gaussian(1.0, 4.0))
+            .with_variable(
+                &y,
+                vec![x].into_iter().collect(),
+                Initialization::LinearGaussian(LinearGaussian::new(y, vec![x], vec![2.0], 3.0, 1.0).unwrap())
+            ).with_variable(
+                &z,
+                vec![y].into_iter().collect(),
+                Initialization::LinearGaussian(LinearGaussian::new(z, vec![y], vec![-1.0], 1.0, 2.0).unwrap())
+            ).build()
+            .unwrap();
+
+        let close = |expected: f64, actual: f64| (expected - actual).abs() < 1e-9;
+
+        // the prior marginal of Z
+        let mut engine = GaussianInferenceEngine::for_directed(&model, &Assignment::new()).unwrap();
+        let p_z = engine.infer(&[z]).unwrap();
+        assert!(close(-4.0, p_z.mean().unwrap()[0]));
+        assert!(close(19.0, p_z.covariance().unwrap()[[0, 0]]));
+        assert!(close(0.0, engine.log_probability_of_evidence().unwrap()));
+
+        // condition on Z = -2
+        let mut evidence = Assignment::new();
+        evidence.set_continuous(&z, -2.0);
+        let mut engine = GaussianInferenceEngine::for_directed(&model, &evidence).unwrap();
+
+        let posterior = engine.infer(&[y, x]).unwrap();
+        assert_eq!(vec![y, x], posterior.scope());
+
+        let mean = posterior.mean().unwrap();
+        let cov = posterior.covariance().unwrap();
+        assert!(close(5.0 - 17.0 / 19.0 * 2.0, mean[0]));
+        assert!(close(1.0 - 8.0 / 19.0 * 2.0, mean[1]));
+        assert!(close(17.0 - 289.0 / 19.0, cov[[0, 0]]));
+        assert!(close(4.0 - 64.0 / 19.0, cov[[1, 1]]));
+        assert!(close(8.0 - 136.0 / 19.0, cov[[0, 1]]));
+
+        // the posterior is normalized
+        assert!(close(0.0, posterior.marginalize(x).unwrap().marginalize(y).unwrap().g()));
+
+        // and the density of the evidence is N(-2; -4, 19)
+        let expected = (-4.0f64 / 38.0).exp() / (38.0 * std::f64::consts::PI).sqrt();
+        assert!(close(expected, engine.probability_of_evidence().unwrap()));
+
+        assert!(engine.infer(&[z]).is_err());
+        assert!(engine.infer(&[]).is_err());
+
+        // discrete models are not supported
+        let (_, discrete, _) = build_student_example();
+        assert!(GaussianInferenceEngine::for_directed(&discrete, &Assignment::new()).is_err());
+    }
+
+    #[test]
+    /// The discrete engines reject a hybrid model, rather than panicking on its continuous variable.
+    fn hybrid_models() {
+        let m = Variable::binary();
+        let x = Variable::continuous();
+
+        let cpd_x = ConditionalLinearGaussian::new(x, vec![m], vec![
+            LinearGaussian::normal(x, -1.0, 1.0).unwrap(),
+            LinearGaussian::normal(x, 1.0, 1.0).unwrap()
+        ]).unwrap();
+
+        let model = DirectedModelBuilder::new()
+                        .with_variable(&m, HashSet::new(), Initialization::Binomial(0.3))
+                        .with_variable(&x, vec![m].into_iter().collect(), Initialization::ConditionalLinearGaussian(cpd_x))
+                        .build()
+                        .unwrap();
+
+        let evidence = Assignment::new();
+        assert!(matches!(UndirectedModel::try_from(&model), Err(JeromeError::InvalidScope)));
+        assert!(matches!(UndirectedModel::decomposed(&model), Err(JeromeError::InvalidScope)));
+        assert!(matches!(VariableEliminationEngine::for_directed(&model, &evidence), Err(JeromeError::InvalidScope)));
+        assert!(matches!(CliqueTreeEngine::for_directed(&model, &evidence), Err(JeromeError::InvalidScope)));
+        assert!(matches!(LoopyBeliefPropagationEngine::for_directed(&model, &evidence), Err(JeromeError::InvalidScope)));
+        assert!(matches!(MaxProductEngine::for_directed(&model, &evidence), Err(JeromeError::InvalidScope)));
+        assert!(matches!(MarginalMapEngine::for_directed(&model, &evidence), Err(JeromeError::InvalidScope)));
+        assert!(matches!(GibbsSampler::for_directed(&model, &evidence), Err(JeromeError::InvalidScope)));
+    }
+
+
+    #[test]
+    /// Four binary causes of a noisy-OR symptom, which has a child of its own. Inference over the
+    /// causal independence decomposition must agree with inference over the expanded table.
+    fn noisy_or() {
+        let causes: Vec<Variable> = (0..4).map(|_| Variable::binary()).collect();
+        let y = Variable::binary();
+        let z = Variable::binary();
+
+        let cpd_y = NoisyMax::noisy_or(y, causes.clone(), vec![0.2, 0.4, 0.6, 0.8], 0.05).unwrap();
+        let cpd_z = Factor::cpd(z, vec![y], array![[0.9, 0.1], [0.3, 0.7]].into_dyn()).unwrap();
+
+        let build = |init: Initialization| {
+            let builder = causes.iter().fold(DirectedModelBuilder::new(), |b, c| {
+                b.with_variable(c, HashSet::new(), Initialization::Binomial(0.7))
+            });
+
+            builder.with_variable(&y, causes.iter().cloned().collect(), init)
+                   .with_variable(&z, vec![y].into_iter().collect(), Initialization::Table(cpd_z.clone()))
+                   .build()
+                   .unwrap()
+        };
+
+        let compact = build(Initialization::NoisyMax(cpd_y.clone()));
+        let table = build(Initialization::Table(cpd_y.as_factor().clone()));
+
+        // the decomposition adds auxiliary variables, but no factor over more than three
+        let decomposed = UndirectedModel::decomposed(&compact).unwrap();
+        assert_eq!(compact.num_variables() + 3, decomposed.num_variables());
+        assert!(decomposed.factors().iter().all(|f| f.scope().len() <= 3));
+
+        let mut evidence = Assignment::new();
+        evidence.set(&z, 1);
+        evidence.set(&causes[3], 1);
+
+        let query: HashSet<Variable> = vec![causes[0]].into_iter().collect();
+        let expected = VariableEliminationEngine::for_directed(&table, &evidence).unwrap().infer(&query).unwrap();
+
+        let mut a = Assignment::new();
+        a.set(&causes[0], 1);
+        let expected = expected.value(&a).unwrap();
+
+        let mut ve = VariableEliminationEngine::for_directed(&compact, &evidence).unwrap();
+        assert!((ve.infer(&query).unwrap().value(&a).unwrap() - expected).abs() < 1e-10);
+
+        let mut ct = CliqueTreeEngine::for_directed(&compact, &evidence).unwrap();
+        assert!((ct.infer(&query).unwrap().value(&a).unwrap() - expected).abs() < 1e-10);
+
+        let p_e = VariableEliminationEngine::for_directed(&table, &evidence).unwrap().probability_of_evidence().unwrap();
+        assert!((ve.probability_of_evidence().unwrap() - p_e).abs() < 1e-10);
+
+        // samplers work with the compact CPD directly
+        let mut sampler = LikelihoodWeightedSampler::new(&compact, &evidence);
+        let mut engine = ImportanceSamplingEngine::new(&mut sampler, 20000);
+        assert!((engine.infer(&query).unwrap().value(&a).unwrap() - expected).abs() < 0.02);
+    }
+
+
+    #[test]
+    /// A and B are fair coins, Y = A XOR B, and Z is a noisy sensor of Y. The zeros of the
+    /// deterministic CPD must be handled exactly.
+    fn deterministic() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+        let y = Variable::binary();
+        let z = Variable::binary();
+
+        let xor = Deterministic::new(y, vec![a, b], |u: &[usize]| u[0] ^ u[1]).unwrap();
+        let cpd_z = Factor::cpd(z, vec![y], array![[0.8, 0.2], [0.1, 0.9]].into_dyn()).unwrap();
+
+        let model = DirectedModelBuilder::new()
+            .with_variable(&a, HashSet::new(), Initialization::Binomial(0.5))
+            .with_variable(&b, HashSet::new(), Initialization::Binomial(0.5))
+            .with_variable(&y, vec![a, b].into_iter().collect(), Initialization::Deterministic(xor))
+            .with_variable(&z, vec![y].into_iter().collect(), Initialization::Table(cpd_z))
+            .build()
+            .unwrap();
+
+        // samples always satisfy the function
+        let mut sampler = ForwardSampler::new(&model);
+        for _ in 0..100 {
+            let s = sampler.sample();
+            assert_eq!(s.get(&a).unwrap() ^ s.get(&b).unwrap(), *s.get(&y).unwrap());
+        }
+
+        // observing Y and B determines A
+        let mut evidence = Assignment::new();
+        evidence.set(&y, 1);
+        evidence.set(&b, 0);
+
+        let query: HashSet<Variable> = vec![a].into_iter().collect();
+        let mut assn = Assignment::new();
+        assn.set(&a, 0);
+
+        let mut ve = VariableEliminationEngine::for_directed(&model, &evidence).unwrap();
+        assert_eq!(0.0, ve.infer(&query).unwrap().value(&assn).unwrap());
+        assert!((ve.probability_of_evidence().unwrap() - 0.25).abs() < 1e-12);
+
+        let mut ct = CliqueTreeEngine::for_directed(&model, &evidence).unwrap();
+        assert_eq!(0.0, ct.infer(&query).unwrap().value(&assn).unwrap());
+
+        let mut sampler = LikelihoodWeightedSampler::new(&model, &evidence);
+        let mut engine = ImportanceSamplingEngine::new(&mut sampler, 1000);
+        assert_eq!(0.0, engine.infer(&query).unwrap().value(&assn).unwrap());
+
+        // P(Y = 1 | Z = 1) = 0.5 * 0.9 / (0.5 * 0.9 + 0.5 * 0.2)
+        let mut evidence = Assignment::new();
+        evidence.set(&z, 1);
+        let mut assn = Assignment::new();
+        assn.set(&y, 1);
+
+        let query: HashSet<Variable> = vec![y].into_iter().collect();
+        let mut ve = VariableEliminationEngine::for_directed(&model, &evidence).unwrap();
+        assert!((ve.infer(&query).unwrap().value(&assn).unwrap() - 0.45 / 0.55).abs() < 1e-12);
+    }
+
+
+    #[test]
+    /// Evidence with probability zero has no conditional distribution. Every engine must report
+    /// it as an error.
+    fn impossible_evidence() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+        let y = Variable::binary();
+        let w = Variable::binary();
+        let z = Variable::binary();
+
+        let xor = Deterministic::new(y, vec![a, b], |u: &[usize]| u[0] ^ u[1]).unwrap();
+        let copy = Deterministic::new(w, vec![b], |u: &[usize]| u[0]).unwrap();
+        let cpd_z = Factor::cpd(z, vec![y], array![[0.8, 0.2], [0.1, 0.9]].into_dyn()).unwrap();
+
+        let model = DirectedModelBuilder::new()
+            .with_variable(&a, HashSet::new(), Initialization::Binomial(0.5))
+            .with_variable(&b, HashSet::new(), Initialization::Binomial(0.5))
+            .with_variable(&y, vec![a, b].into_iter().collect(), Initialization::Deterministic(xor))
+            .with_variable(&w, vec![b].into_iter().collect(), Initialization::Deterministic(copy))
+            .with_variable(&z, vec![y].into_iter().collect(), Initialization::Table(cpd_z))
+            .build()
+            .unwrap();
+        let log_model = UndirectedModel::try_from(&model).unwrap().to_log();
+
+        let divide_by_zero = |r: Result<Factor>| matches!(r, Err(JeromeError::DivideByZero));
+
+        // 0 XOR 0 is never 1, whether the factor is reduced to a constant or the zero is found by
+        // summing out B, in the component of the query or another one
+        let mut fully_observed = Assignment::new();
+        fully_observed.set(&a, 0);
+        fully_observed.set(&b, 0);
+        fully_observed.set(&y, 1);
+
+        let mut summed = Assignment::new();
+        summed.set(&a, 0);
+        summed.set(&w, 0);
+        summed.set(&y, 1);
+
+        for &(evidence, query) in [(&fully_observed, z), (&summed, z), (&summed, b)].iter() {
+            let query: HashSet<Variable> = vec![query].into_iter().collect();
+
+            let mut ve = VariableEliminationEngine::for_directed(&model, evidence).unwrap();
+            assert!(divide_by_zero(ve.infer(&query)));
+
+            let mut ve = VariableEliminationEngine::for_undirected(&log_model, evidence);
+            assert!(divide_by_zero(ve.infer(&query)));
+
+            let mut ct = CliqueTreeEngine::for_directed(&model, evidence).unwrap();
+            assert!(divide_by_zero(ct.infer(&query)));
+
+            let mut lbp = LoopyBeliefPropagationEngine::for_directed(&model, evidence).unwrap();
+            assert!(divide_by_zero(lbp.infer(&query)));
+
+            let mut sampler = LikelihoodWeightedSampler::new(&model, evidence);
+            let mut engine = ImportanceSamplingEngine::new(&mut sampler, 100);
+            assert!(divide_by_zero(engine.infer(&query)));
+        }
+    }
+
 }
@@ -0,0 +1,378 @@
+//! Defines a `ParticleFilter` for online approximate filtering in a `DynamicModel`.
+//!
+//! Unrolling a `DynamicModel` is infeasible for long sequences of observations. A particle filter
+//! instead keeps a set of weighted samples of the current state, which it propagates through the
+//! transition network and weights by the evidence of each time slice, as in likelihood weighting.
+//! Resampling whenever the effective sample size drops keeps the particles from degenerating.
+//!
+//! Implementation of Sequential Importance Resampling, Koller & Friedman Section 15.3.3
+//! (Algorithm 15.2).
+
+use factor::{Factor, Table};
+use model::dynamic::DynamicModel;
+use util::{JeromeError, Result};
+use variable::{Assignment, DenseAssignment, Variable, VariableIndex};
+
+use ndarray::prelude as nd;
+use rand;
+use rand::distributions::{IndependentSample, Range};
+
+use std::sync::Arc;
+
+
+/// The scheme used to resample the particles
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resampling {
+    /// Draw each particle independently, in proportion to its weight
+    Multinomial,
+
+    /// Draw every particle with a single uniform offset on an evenly spaced grid
+    Systematic,
+
+    /// Copy each particle ```floor(N w)``` times, and draw the rest by multinomial resampling on
+    /// the remaining weight
+    Residual
+}
+
+
+/// A Sequential Importance Resampling particle filter over a `DynamicModel`.
+///
+/// The filter advances one time slice per call to `ParticleFilter::step`. A `HiddenMarkovModel`
+/// can be filtered by first converting it with `HiddenMarkovModel::to_dynamic`.
+pub struct ParticleFilter<'a> {
+
+    /// The model to filter
+    model: &'a DynamicModel,
+
+    /// The state `Variable`s followed by their copies in the next time slice
+    index: Arc<VariableIndex>,
+
+    /// The value of each state `Variable`, in the order of `DynamicModel::states`, for each particle
+    particles: Vec<Vec<usize>>,
+
+    /// The normalized weight of each particle
+    weights: Vec<f64>,
+
+    /// The number of particles
+    size: usize,
+
+    /// The resampling scheme
+    resampling: Resampling,
+
+    /// The particles are resampled when the effective sample size falls below this fraction of
+    /// the number of particles
+    threshold: f64,
+
+    /// The number of time slices filtered so far
+    steps: usize,
+
+    /// The estimate of the log probability of the evidence so far
+    log_likelihood: f64
+
+}
+
+
+impl<'a> ParticleFilter<'a> {
+
+    /// Construct a new `ParticleFilter` with the given number of particles, using systematic
+    /// resampling when the effective sample size falls below half of the particles.
+    pub fn new(model: &'a DynamicModel, particles: usize) -> Self {
+        let states = model.states();
+        let variables: Vec<Variable> = states.iter().cloned().chain(states.iter().map(|x| model.next(x).unwrap())).collect();
+
+        ParticleFilter {
+            model,
+            index: Arc::new(VariableIndex::new(&variables)),
+            particles: Vec::new(),
+            weights: Vec::new(),
+            size: particles,
+            resampling: Resampling::Systematic,
+            threshold: 0.5,
+            steps: 0,
+            log_likelihood: 0.0
+        }
+    }
+
+    /// Set the resampling scheme
+    pub fn with_resampling(mut self, resampling: Resampling) -> Self {
+        self.resampling = resampling;
+        self
+    }
+
+    /// Resample when the effective sample size falls below ```threshold * particles```. A
+    /// threshold of 0 never resamples, and one greater than 1 resamples at every step.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Get the number of time slices filtered so far
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Get the effective sample size of the current particles, ```1 / sum w^2```
+    pub fn effective_sample_size(&self) -> f64 {
+        1.0 / self.weights.iter().map(|w| w * w).sum::<f64>()
+    }
+
+    /// Get the estimate of the natural logarithm of the probability of the evidence of every time
+    /// slice so far
+    pub fn log_likelihood(&self) -> f64 {
+        self.log_likelihood
+    }
+
+    /// Advance the filter by one time slice. The first call samples the initial network, and each
+    /// call after samples the transition network given the current particles.
+    ///
+    /// # Args
+    /// * `evidence`: the observed values of the state `Variable`s in this time slice. Evidence on
+    ///   any other `Variable` is ignored.
+    ///
+    /// # Returns
+    /// the filtered distribution of each state `Variable`, in the order of
+    /// `DynamicModel::states`
+    ///
+    /// # Errors
+    /// * `JeromeError::NotEnoughData`, if the filter has no particles
+    /// * `JeromeError::DivideByZero`, if every particle is inconsistent with the evidence
+    pub fn step(&mut self, evidence: &Assignment) -> Result<Vec<Factor>> {
+        if self.size == 0 {
+            return Err(JeromeError::NotEnoughData);
+        }
+
+        let states = self.model.states();
+        let n = states.len();
+
+        // the first time slice is sampled from the initial network, and every other from the
+        // transition network, into the copies of the state variables
+        let (cpds, offset) = if self.steps == 0 {
+            let initial = self.model.initial();
+            (initial.topological_order().into_iter().map(|x| (x, initial.discrete_cpd(&x).unwrap())).collect::<Vec<_>>(), 0)
+        } else {
+            let order = self.model.transition_order();
+            (order.into_iter().map(|x| (x, self.model.transition_cpd(&x).unwrap())).collect::<Vec<_>>(), n)
+        };
+
+        // 1) propagate each particle, weighting it by the likelihood of the evidence
+        let (particles, likelihoods): (Vec<Vec<usize>>, Vec<f64>) = (0..self.size).map(|i| {
+            let mut a = DenseAssignment::new(&self.index);
+            let mut w = 1.0;
+
+            if self.steps > 0 {
+                for (pos, &u) in self.particles[i].iter().enumerate() {
+                    a.set_at(pos, u);
+                }
+            }
+
+            for &(var, cpd) in cpds.iter() {
+                let pos = self.index.position(&var).unwrap();
+                match evidence.get(&states[pos - offset]) {
+                    Some(&u) => {
+                        a.set_at(pos, u);
+                        w *= cpd.value(&a).unwrap();
+                    },
+                    None => {
+                        // the particle is complete over the parents, as the CPDs are sampled in
+                        // topological order
+                        let u = cpd.sample_cpd(&a).unwrap();
+                        a.set_at(pos, u);
+                    }
+                }
+            }
+
+            ((offset..offset + n).map(|pos| a.get_at(pos).unwrap()).collect(), w)
+        }).unzip();
+
+        // 2) update the weights. The particles start out evenly weighted.
+        let prior = if self.steps == 0 { vec![1.0 / self.size as f64; self.size] } else { self.weights.clone() };
+        let weights: Vec<f64> = prior.iter().zip(likelihoods.iter()).map(|(p, l)| p * l).collect();
+
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return Err(JeromeError::DivideByZero);
+        }
+
+        self.particles = particles;
+        self.weights = weights.into_iter().map(|w| w / total).collect();
+        self.log_likelihood += total.ln();
+        self.steps += 1;
+
+        // 3) estimate the filtered distributions from the weighted particles
+        let marginals = states.iter().enumerate().map(|(pos, x)| {
+            let mut table = Table::zeros(vec![x.cardinality()]);
+            for (particle, w) in self.particles.iter().zip(self.weights.iter()) {
+                table[nd::IxDyn(&[particle[pos]])] += *w;
+            }
+
+            Factor::new(vec![*x], table).and_then(|f| f.normalize())
+        }).collect();
+
+        // 4) resample if the particles have degenerated
+        if self.effective_sample_size() < self.threshold * self.size as f64 {
+            let chosen = resample(self.resampling, &self.weights, self.size);
+            self.particles = chosen.into_iter().map(|i| self.particles[i].clone()).collect();
+            self.weights = vec![1.0 / self.size as f64; self.size];
+        }
+
+        marginals
+    }
+
+    /// Filter a sequence of evidence, one time slice at a time, continuing from the current state
+    /// of the filter.
+    ///
+    /// # Returns
+    /// the filtered distributions of each time slice; see `ParticleFilter::step`
+    pub fn filter(&mut self, evidence: &[Assignment]) -> Result<Vec<Vec<Factor>>> {
+        evidence.iter().map(|e| self.step(e)).collect()
+    }
+}
+
+
+/// Draw `n` indices in proportion to the normalized `weights`
+fn resample(resampling: Resampling, weights: &[f64], n: usize) -> Vec<usize> {
+    let mut rng = rand::thread_rng();
+    let uniform = Range::new(0.0, 1.0);
+
+    match resampling {
+        Resampling::Multinomial => {
+            let cdf = cumulative(weights);
+            (0..n).map(|_| inverse(&cdf, uniform.ind_sample(&mut rng))).collect()
+        },
+        Resampling::Systematic => {
+            let cdf = cumulative(weights);
+            let offset = uniform.ind_sample(&mut rng);
+            (0..n).map(|i| inverse(&cdf, (i as f64 + offset) / n as f64)).collect()
+        },
+        Resampling::Residual => {
+            let expected: Vec<f64> = weights.iter().map(|w| w * n as f64).collect();
+            let mut chosen: Vec<usize> = expected.iter()
+                                                 .enumerate()
+                                                 .flat_map(|(i, e)| vec![i; e.floor() as usize])
+                                                 .collect();
+
+            // the remaining particles are drawn from the fractional parts of the expected counts
+            let remaining = n - chosen.len().min(n);
+            if remaining > 0 {
+                let cdf = cumulative(&expected.iter().map(|e| e - e.floor()).collect::<Vec<f64>>());
+                chosen.extend((0..remaining).map(|_| inverse(&cdf, uniform.ind_sample(&mut rng))));
+            }
+
+            chosen.truncate(n);
+            chosen
+        }
+    }
+}
+
+
+/// The cumulative sums of `weights`, normalized so that the last is 1
+fn cumulative(weights: &[f64]) -> Vec<f64> {
+    let total: f64 = weights.iter().sum();
+    weights.iter()
+           .scan(0.0, |acc, w| {
+               *acc += w / total;
+               Some(*acc)
+           })
+           .collect()
+}
+
+
+/// The first index whose cumulative weight exceeds `u`
+fn inverse(cdf: &[f64], u: f64) -> usize {
+    cdf.iter().position(|&c| u < c).unwrap_or(cdf.len() - 1)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use init::Initialization;
+    use model::dynamic::DynamicModelBuilder;
+    use model::hmm::tests::{umbrella, value};
+
+    #[test]
+    /// The filtered distributions approach those of the forward algorithm
+    fn umbrella_filter() {
+        let hmm = umbrella();
+        let model = hmm.to_dynamic();
+        let observations = [1, 1, 0, 1, 1, 0, 0, 1];
+        let exact = hmm.filter(&observations).unwrap();
+
+        let evidence: Vec<Assignment> = observations.iter().map(|&o| {
+            let mut a = Assignment::new();
+            a.set(&hmm.observed(), o);
+            a
+        }).collect();
+
+        for &resampling in [Resampling::Multinomial, Resampling::Systematic, Resampling::Residual].iter() {
+            let mut filter = ParticleFilter::new(&model, 5000).with_resampling(resampling);
+            let marginals = filter.filter(&evidence).unwrap();
+
+            assert_eq!(observations.len(), filter.steps());
+            for (t, m) in marginals.iter().enumerate() {
+                // the observed state is certain
+                assert_eq!(2, m.len());
+                assert_eq!(1.0, value(&m[1], hmm.observed(), observations[t]));
+                assert!((value(&m[0], hmm.hidden(), 1) - value(&exact[t], hmm.hidden(), 1)).abs() < 0.05);
+            }
+
+            let expected = hmm.log_likelihood(&observations).unwrap();
+            assert!((filter.log_likelihood() - expected).abs() < 0.2);
+        }
+    }
+
+    #[test]
+    fn effective_sample_size() {
+        let hmm = umbrella();
+        let model = hmm.to_dynamic();
+
+        let mut evidence = Assignment::new();
+        evidence.set(&hmm.observed(), 1);
+
+        // without resampling, the weights are uneven after observing the umbrella
+        let mut filter = ParticleFilter::new(&model, 1000).with_threshold(0.0);
+        filter.step(&evidence).unwrap();
+        filter.step(&evidence).unwrap();
+        assert!(filter.effective_sample_size() < 1000.0 - 1e-6);
+
+        // resampling at every step leaves the particles evenly weighted
+        let mut filter = ParticleFilter::new(&model, 1000).with_threshold(2.0);
+        filter.step(&evidence).unwrap();
+        assert!((filter.effective_sample_size() - 1000.0).abs() < 1e-6);
+
+        // evidence that no particle can explain is an error
+        let x = Variable::binary();
+        let x_next = Variable::binary();
+        let initial = Factor::cpd(x, vec![], array![1.0, 0.0].into_dyn()).unwrap();
+        let cpd = Factor::cpd(x_next, vec![x], array![[1.0, 0.0], [0.0, 1.0]].into_dyn()).unwrap();
+        let stuck = DynamicModelBuilder::new()
+                        .with_state(&x, &x_next, "X")
+                        .with_initial(&x, Default::default(), Initialization::Table(initial))
+                        .with_transition(&x_next, vec![x].into_iter().collect(), Initialization::Table(cpd))
+                        .build()
+                        .unwrap();
+
+        let mut filter = ParticleFilter::new(&stuck, 100);
+        let mut evidence = Assignment::new();
+        evidence.set(&x, 1);
+        assert!(matches!(filter.step(&evidence), Err(JeromeError::DivideByZero)));
+
+        assert!(matches!(ParticleFilter::new(&stuck, 0).step(&Assignment::new()), Err(JeromeError::NotEnoughData)));
+    }
+
+    #[test]
+    fn resampling() {
+        // the deterministic part of systematic and residual resampling is exact
+        let counts = |chosen: Vec<usize>| (0..3).map(|i| chosen.iter().filter(|&&c| c == i).count()).collect::<Vec<usize>>();
+
+        assert_eq!(vec![1, 0, 3], counts(resample(Resampling::Residual, &[0.25, 0.0, 0.75], 4)));
+        assert_eq!(vec![2, 0, 2], counts(resample(Resampling::Systematic, &[0.5, 0.0, 0.5], 4)));
+
+        for &method in [Resampling::Multinomial, Resampling::Systematic, Resampling::Residual].iter() {
+            let chosen = resample(method, &[0.3, 0.0, 0.7], 100);
+            assert_eq!(100, chosen.len());
+            assert_eq!(0, counts(chosen)[1]);
+        }
+    }
+}
@@ -3,16 +3,16 @@
 //!
 //! Implementation of Koller & Friedman Algorithm 9.1 - Sum-Product-VE
 
-use factor::Factor;
+use factor::{log_sum_exp, Factor};
 use model::Model;
 use model::directed::DirectedModel;
 use model::undirected::UndirectedModel;
-use super::ConditionalInferenceEngine;
+use super::elimination_ordering::{elimination_cost, restrict_order, EliminationCost, EliminationOrdering, MaxCardinality};
+use super::{ConditionalInferenceEngine, EvidenceProbabilityEngine};
 use util::{JeromeError, Result};
-use variable::{Assignment, Variable};
+use variable::{all_assignments, Assignment, Variable};
 
 use std::collections::HashSet;
-use std::collections::HashMap;
 
 pub struct VariableEliminationEngine {
     
@@ -21,104 +21,74 @@ pub struct VariableEliminationEngine {
     model: UndirectedModel,
 
     /// precomputed preferred elimination order based on max-cardinality heuristic
-    order: Vec<Variable>
+    order: Vec<Variable>,
+
+    /// the original `UndirectedModel`, whose partition function is computed (and cached) only if
+    /// the probability of the evidence is requested
+    original: UndirectedModel,
+
+    /// the log of the product of the `Factor`s that are fully observed, and so dropped from the
+    /// reduced model
+    log_observed: f64
 
 }
 
 
 impl VariableEliminationEngine {
 
-    pub fn for_directed(model: &DirectedModel, evidence: &Assignment) -> Self {
-        VariableEliminationEngine::for_undirected(
-            &UndirectedModel::from(model),
-            evidence
-        )
+    /// Construct the engine for a `DirectedModel`, by converting it to an `UndirectedModel`.
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if the model has a continuous `Variable`
+    pub fn for_directed(model: &DirectedModel, evidence: &Assignment) -> Result<Self> {
+        UndirectedModel::decomposed(model).map(|m| VariableEliminationEngine::new(m, evidence))
     }
 
     pub fn for_undirected(model: &UndirectedModel, evidence: &Assignment) -> Self {
+        VariableEliminationEngine::new(model.clone(), evidence)
+    }
+
+    fn new(model: UndirectedModel, evidence: &Assignment) -> Self {
         // reduce the provided model with the evidence - this is the model we will use for variable
         // elimination
         let reduced = model.condition(evidence);
         // precompute the preferred elimination order using the max-cardinatlity heuristic.
-        let order = max_cardinality_elimination_order(&reduced);
+        let order = MaxCardinality.order(&reduced);
 
         VariableEliminationEngine {
             model: reduced,
-            order: order
+            order,
+            log_observed: log_observed(&model, evidence),
+            original: model
         }
     }
 
-}
-
-/// Compute the preferred elimination order by the max-cardinality heuristic
-fn max_cardinality_elimination_order(model: &UndirectedModel) -> Vec<Variable> {
-    // since we do not explictly hold the graph structure, we need to determine the neighbors of
-    // each variable.
-    let mut neighbors: HashMap<Variable, HashSet<Variable>> = model.variables()
-                                                                   .iter()
-                                                                   .map(|v| (*v, HashSet::new()))
-                                                                   .collect();
-
-    for f in model.factors().iter() {
-        let scope = f.scope();
-        for i in 0..(scope.len() - 1) {
-            let vi = scope[i];
-            for j in i..scope.len() {
-                let vj = scope[j];
-                neighbors.get_mut(&vi).unwrap().insert(vj);
-                neighbors.get_mut(&vj).unwrap().insert(vi);
-            }
-        }
+    /// Choose the elimination order with the given `EliminationOrdering` heuristic, rather than
+    /// the default max-cardinality heuristic.
+    pub fn with_ordering(mut self, ordering: &dyn EliminationOrdering) -> Self {
+        self.order = ordering.order(&self.model);
+        self
     }
 
-    // set of marked variables
-    let mut marked = HashSet::new();
-    // the (reverse) elimination order
-    let mut elimination = Vec::new();
-    // the variables in the model
-    let vars: Vec<Variable> = model.variables().into_iter().collect();
-
-    // for |vars| iterations
-    for _ in 0..vars.len() {
-        let mut idx = None;
-
-        // loop over all variables
-        for (vidx, v) in vars.iter().enumerate() {
-            // if we have already marked this variable, it is already in the elimination order so
-            // we don't process it again
-            if marked.contains(v) {
-                continue;
-            }
-
-            // otherwise, count the number of marked neighbors
-            let ct = neighbors[v].iter().filter(|&n| marked.contains(n)).count();
-
-            // if there are more neighbors, update the max index to this variable's index
-            if let Some((_, max)) = idx {
-                if ct > max {
-                    idx = Some((vidx, ct));
-                }
-            } else {
-                idx = Some((vidx, ct));
-            }
-        }
+    /// Use a user-supplied elimination order. Observed `Variable`s may be included; they are
+    /// ignored.
+    ///
+    /// # Errors
+    /// `JeromeError::InvalidScope` if `order` does not contain every unobserved `Variable` exactly
+    /// once
+    pub fn with_order(mut self, order: &[Variable]) -> Result<Self> {
+        self.order = restrict_order(&self.model, order)?;
+        Ok(self)
+    }
 
-        // invariant: this will *always* be Some
-        // add the selected variable to the elimination order and marked variable list
-        if let Some((i, _)) = idx {
-            elimination.push(vars[i]);
-            marked.insert(vars[i]);
-        } else {
-            panic!("This should be unreachable");
-        }
+    /// The cost of running variable elimination with the current elimination order
+    pub fn cost(&self) -> EliminationCost {
+        // invariant: the order always contains every variable in the model
+        elimination_cost(&self.model, &self.order).unwrap()
     }
 
-    // we need to reverse the elimination order before returning
-    elimination.reverse();
-    elimination
 }
 
-
 impl ConditionalInferenceEngine for VariableEliminationEngine {
 
     fn infer(&mut self, variables: &HashSet<Variable>) -> Result<Factor> {
@@ -128,84 +98,182 @@ impl ConditionalInferenceEngine for VariableEliminationEngine {
             return Err(JeromeError::InvalidScope);
         }
 
-        let mut phis = self.model.factors().clone();
-        for &var in self.order.iter() {
-            if variables.contains(&var) {
-                // we are computing P(var | e), so do not eliminate the variable
-                continue;
-            }
-
-            // Otherwise, time to get rid of var
-            let (phi_1prime, phi_2prime): (Vec<Factor>, Vec<Factor>) = phis
-                                           .into_iter()
-                                           .partition(|f| f.scope().contains(&var));
-
-            // product step - multiply factors with var
-            // Safe to unwrap the result of product here. 
-            // We know the inputs are correct, so it will not fail.
-            let psi = phi_1prime.into_iter()
-                                .fold(Factor::Identity, |acc, phi| acc.product(&phi).unwrap());
-                          
-            // sum step - marginalize psi over var
-            let tau = psi.marginalize(var);
-
-            phis = phi_2prime;
-            phis.push(tau);
+        // a fully observed `Factor` with value zero means the evidence has probability zero
+        if self.log_observed == f64::NEG_INFINITY {
+            return Err(JeromeError::DivideByZero);
         }
 
-        // multiply together remaining phis
-        let phi_star = phis.into_iter()
-                           .fold(Factor::Identity, |acc, phi| acc.product(&phi).unwrap());
+        let phi_star = sum_product_ve(self.model.factors().clone(), &self.order, variables)?;
 
         // now we have an unnormalized distribution. We need the partition function to return a
         // conditional probability.
-        return Ok(phi_star.normalize());
+        phi_star.normalize()
     }
 
 }
 
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use model::directed::DirectedModelBuilder;
-    use init::Initialization;
-    
-    /// This example is taken from Koller & Friedman Example 9.3 and Figure 9.11.
-    /// Verified by manual selection because the order is not deterministic. The annotation below can
-    /// be uncommented to run this test - it will fail (by design), but will print out the
-    /// calculated order, which can be verified against the graph constructed below.
-    // #[test]
-    #[allow(dead_code)]
-    fn max_cardinality() {
-        let c = Variable::binary();
-        let d = Variable::binary();
-        let i = Variable::binary();
-        let g = Variable::binary();
-        let s = Variable::binary();
-        let l = Variable::binary();
-        let j = Variable::binary();
-        let h = Variable::binary();
-
-        let builder = DirectedModelBuilder::new();
-        let model = builder.with_variable(&c, HashSet::new(), Initialization::Uniform)
-                           .with_variable(&d, vec![c].into_iter().collect(), Initialization::Uniform)
-                           .with_variable(&i, HashSet::new(), Initialization::Uniform)
-                           .with_variable(&g, vec![d, i].into_iter().collect(), Initialization::Uniform)
-                           .with_variable(&s, vec![i].into_iter().collect(), Initialization::Uniform)
-                           .with_variable(&l, vec![g].into_iter().collect(), Initialization::Uniform)
-                           .with_variable(&j, vec![l, s].into_iter().collect(), Initialization::Uniform)
-                           .with_variable(&h, vec![g, j].into_iter().collect(), Initialization::Uniform)
-                           .build()
-                           .unwrap();
-
-        let model = UndirectedModel::from(&model);
-        let order = max_cardinality_elimination_order(&model);
-
-        println!("{:?}", order);
-
-        // by failing on purpose, the print out above will be displayed
-        assert!(false); 
+impl EvidenceProbabilityEngine for VariableEliminationEngine {
+
+    fn probability_of_evidence(&mut self) -> Result<f64> {
+        self.log_probability_of_evidence().map(f64::exp)
+    }
+
+    fn log_probability_of_evidence(&mut self) -> Result<f64> {
+        // the partition function of the reduced model, times the fully observed `Factor`s, is the
+        // unnormalized measure of the evidence
+        Ok(self.log_observed + self.model.log_partition() - self.original.log_partition())
+    }
+
+}
+
+
+/// The operation used to eliminate a `Variable` from a `Factor`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum Elimination {
+    /// Sum over the values of the `Variable` (Sum-Product-VE)
+    Sum,
+
+    /// Maximize over the values of the `Variable` (Max-Product-VE)
+    Max
+}
+
+
+/// Eliminate a single `Variable` from a set of `Factor`s.
+///
+/// # Returns
+/// * the remaining `Factor`s
+/// * the product ```psi``` of the `Factor`s that contained `var` (before elimination)
+/// * the log of the constant produced if `var` was the only `Variable` in the scope of ```psi```,
+///   or 0. The constant is kept in log-space, since the product of these constants over a large
+///   model is prone to underflow.
+pub(super) fn eliminate(phis: Vec<Factor>, var: Variable, op: Elimination) -> (Vec<Factor>, Factor, f64) {
+    let (phi_1prime, mut phi_2prime): (Vec<Factor>, Vec<Factor>) = phis
+                                   .into_iter()
+                                   .partition(|f| f.scope().contains(&var));
+
+    // product step - multiply factors with var
+    // Safe to unwrap the result of product here. 
+    // We know the inputs are correct, so it will not fail.
+    let psi = phi_1prime.into_iter()
+                        .fold(Factor::Identity, |acc, phi| acc.product(&phi).unwrap());
+
+    // elimination step - sum or maximize psi over var. If var is the only variable in the scope of
+    // psi, the result is a constant rather than a factor.
+    let mut log_constant = 0.0;
+    if psi.scope().len() > 1 {
+        let tau = match op {
+            Elimination::Sum => psi.marginalize(var),
+            Elimination::Max => psi.max_marginalize(var)
+        };
+
+        phi_2prime.push(tau);
+    } else if ! psi.is_identity() {
+        let scope = vec![var];
+        let vals: Vec<f64> = all_assignments(&scope).map(|a| psi.log_value(&a).unwrap()).collect();
+        log_constant = match op {
+            Elimination::Sum => log_sum_exp(vals.iter()),
+            Elimination::Max => vals.into_iter().fold(f64::NEG_INFINITY, f64::max)
+        };
     }
 
+    (phi_2prime, psi, log_constant)
+}
+
+
+/// Compute the partition function of a set of `Factor`s by eliminating every `Variable`.
+///
+/// Unlike `log_partition`, the computation is carried out directly on the values of the `Factor`s,
+/// so it is exact (up to floating point error) but may underflow for large models.
+///
+/// # Args
+/// * `factors`: the `Factor`s
+/// * `order`: an elimination order that contains every `Variable` in the scope of `factors`
+pub(crate) fn partition(factors: Vec<Factor>, order: &[Variable]) -> f64 {
+    let mut phis = factors;
+    let mut z = 1.0;
+    for &var in order.iter() {
+        let (remaining, psi, _) = eliminate(phis, var, Elimination::Sum);
+        phis = remaining;
+
+        if psi.scope() == [var] {
+            z *= all_assignments(&vec![var]).map(|a| psi.value(&a).unwrap()).sum::<f64>();
+        }
+    }
+
+    z
+}
+
+
+/// Compute the log of the partition function of a set of `Factor`s by eliminating every
+/// `Variable`.
+///
+/// # Args
+/// * `factors`: the `Factor`s
+/// * `order`: an elimination order that contains every `Variable` in the scope of `factors`
+pub(crate) fn log_partition(factors: Vec<Factor>, order: &[Variable]) -> f64 {
+    let mut phis = factors;
+    let mut log_z = 0.0;
+    for &var in order.iter() {
+        let (remaining, _, log_constant) = eliminate(phis, var, Elimination::Sum);
+        phis = remaining;
+        log_z += log_constant;
+    }
+
+    log_z
+}
+
+
+/// Compute the log of the product of the `Factor`s of `model` whose scope is entirely observed.
+/// Conditioning on the evidence reduces each of these `Factor`s to a constant, which is dropped
+/// from the reduced model.
+///
+/// # Returns
+/// the log of the product, which is ```-inf``` if the evidence has probability zero
+pub(super) fn log_observed(model: &UndirectedModel, evidence: &Assignment) -> f64 {
+    model.factors()
+         .iter()
+         .filter(|f| f.scope().iter().all(|v| evidence.get(v).is_some()))
+         .map(|f| f.log_value(evidence).unwrap())
+         .sum()
+}
+
+
+/// Run Sum-Product-VE over a set of `Factor`s, eliminating every `Variable` in `order` that is not
+/// in `variables`.
+///
+/// # Returns
+/// the (unnormalized) product of the remaining `Factor`s, which has scope `variables`
+///
+/// # Errors
+/// * `JeromeError::DivideByZero`, if the `Factor`s not connected to `variables` sum to zero, so
+///   that the product is zero everywhere
+pub(super) fn sum_product_ve(
+    factors: Vec<Factor>, 
+    order: &[Variable], 
+    variables: &HashSet<Variable>
+) -> Result<Factor> {
+    let mut phis = factors;
+    for &var in order.iter() {
+        if variables.contains(&var) {
+            // we are computing P(var | e), so do not eliminate the variable
+            continue;
+        }
+
+        // Otherwise, time to get rid of var. Any constant is absorbed by normalization, unless it
+        // is zero.
+        let (remaining, _, log_constant) = eliminate(phis, var, Elimination::Sum);
+        if log_constant == f64::NEG_INFINITY {
+            return Err(JeromeError::DivideByZero);
+        }
+
+        phis = remaining;
+    }
+
+    // multiply together remaining phis. Starting from a unit factor over the query ensures the
+    // product is defined even if the remaining phis do not share any variables.
+    let scope: Vec<Variable> = variables.iter().cloned().collect();
+    let unit = if scope.is_empty() { Factor::Identity } else { Factor::ones(scope).unwrap() };
+
+    Ok(phis.into_iter().fold(unit, |acc, phi| acc.product(&phi).unwrap()))
 }
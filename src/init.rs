@@ -1,6 +1,8 @@
 //! Module containing initialization routines for the parameters of a model.
 
+use cpd::{Deterministic, DiscreteCpd, NoisyMax, TreeCpd};
 use factor::Factor;
+use gaussian::{ConditionalLinearGaussian, LinearGaussian};
 use util::{JeromeError, Result};
 use variable::Variable;
 
@@ -27,7 +29,28 @@ pub enum Initialization<'a> {
     Multinomial(&'a [f64]),
 
     /// User defined CPD
-    Table(Factor)
+    Table(Factor),
+
+    /// User defined noisy-MAX (or noisy-OR) CPD. A `DirectedModel` keeps it in its compact form.
+    NoisyMax(NoisyMax),
+
+    /// User defined tree-structured CPD. A `DirectedModel` keeps it in its compact form.
+    Tree(TreeCpd),
+
+    /// User defined deterministic CPD, in which the `Variable` is a function of its parents
+    Deterministic(Deterministic),
+
+    /// Initialize the CPD of a continuous `Variable` as a normal distribution with parameters
+    /// ```mean, variance```.
+    /// Note that this `Initialization` is valid only to a `Variable` with no parents.
+    Gaussian(f64, f64),
+
+    /// User defined linear-Gaussian CPD for a continuous `Variable`
+    LinearGaussian(LinearGaussian),
+
+    /// User defined conditional linear-Gaussian CPD for a continuous `Variable` with discrete
+    /// parents
+    ConditionalLinearGaussian(ConditionalLinearGaussian)
 }
 
 
@@ -45,6 +68,16 @@ impl<'a> Initialization<'a> {
         ///////////////////////////////////////////////////////////////////////////////
         // Trivial cases
 
+        // continuous CPDs are built by build_gaussian
+        if self.is_continuous() || var.is_continuous() {
+            return Err(JeromeError::InvalidInitialization);
+        }
+
+        // a structured CPD is verified, then expanded to a table
+        if self.is_structured() {
+            return self.build_discrete(var, parents).map(|cpd| cpd.as_factor().clone());
+        }
+
         // if this is a user defined factor, it just needs to be verified and returned
         if let Initialization::Table(f) = self {
             if ! f.is_cpd() {
@@ -61,7 +94,7 @@ impl<'a> Initialization<'a> {
         
         ///////////////////////////////////////////////////////////////////////////////
         // Check for errors
-        if parents.len() == 0 {
+        if parents.is_empty() {
 
             match self {
 
@@ -102,22 +135,105 @@ impl<'a> Initialization<'a> {
             },
             Initialization::Random => {
                 let ax = nd::Axis(shape.len() - 1);
-                let mut tbl = nd::Array::random(shape, Range::new(1.0, 100.0));
-                let z = tbl.sum_axis(ax);
+                let tbl = nd::Array::random(shape, Range::new(1.0, 100.0));
+                // keep the summed axis so that z broadcasts along the axis of var
+                let z = tbl.sum_axis(ax).insert_axis(ax);
                 (tbl / z).into_dyn()
             },
             Initialization::Binomial(p) => {
                 array![p, (1.0 - p)].into_dyn()
             },
             Initialization::Multinomial(p) => {
-                nd::Array::from_iter(p.iter().map(|&x| x)).into_dyn()
+                nd::Array::from_iter(p.iter().copied()).into_dyn()
             },
-            Initialization::Table(_) => panic!("unreachable")
+            _ => panic!("unreachable")
         };
 
         Factor::cpd(var, parents.into_iter().collect(), tbl)
     }
 
+    /// Construct the CPD of a discrete `Variable`, initialized based on ```self```. Unlike
+    /// `Initialization::build_cpd`, structured CPDs are not expanded to a table.
+    ///
+    /// # Args
+    /// * `var`: the discrete `Variable` described by the CPD
+    /// * `parents`: the parents of `var`
+    ///
+    /// # Returns
+    /// a `DiscreteCpd`, initialized according to ```self```.
+    pub fn build_discrete(self, var: Variable, parents: HashSet<Variable>) -> Result<DiscreteCpd> {
+        match self {
+            Initialization::NoisyMax(cpd) => {
+                let s = cpd.scope();
+                if cpd.variable() == var && s.len() == parents.len() + 1 && parents.iter().all(|v| s.contains(v)) {
+                    Ok(DiscreteCpd::NoisyMax(cpd))
+                } else {
+                    Err(JeromeError::InvalidScope)
+                }
+            },
+            Initialization::Tree(cpd) => {
+                let s = cpd.scope();
+                if cpd.variable() == var && s.len() == parents.len() + 1 && parents.iter().all(|v| s.contains(v)) {
+                    Ok(DiscreteCpd::Tree(cpd))
+                } else {
+                    Err(JeromeError::InvalidScope)
+                }
+            },
+            Initialization::Deterministic(cpd) => {
+                let s = cpd.scope();
+                if cpd.variable() == var && s.len() == parents.len() + 1 && parents.iter().all(|v| s.contains(v)) {
+                    Ok(DiscreteCpd::Deterministic(cpd))
+                } else {
+                    Err(JeromeError::InvalidScope)
+                }
+            },
+            init => init.build_cpd(var, parents).map(DiscreteCpd::Table)
+        }
+    }
+
+    /// Construct a conditional linear-Gaussian CPD for a continuous `Variable`, initialized based
+    /// on ```self```
+    ///
+    /// # Args
+    /// * `var`: the continuous `Variable` described by the CPD
+    /// * `parents`: the discrete and continuous parents of `var`
+    ///
+    /// # Returns
+    /// a `ConditionalLinearGaussian`, initialized according to ```self```.
+    pub fn build_gaussian(self, var: Variable, parents: HashSet<Variable>) -> Result<ConditionalLinearGaussian> {
+        let cpd = match self {
+            Initialization::Gaussian(mean, variance) if parents.is_empty() => {
+                return LinearGaussian::normal(var, mean, variance).map(ConditionalLinearGaussian::from);
+            },
+            Initialization::LinearGaussian(cpd) => ConditionalLinearGaussian::from(cpd),
+            Initialization::ConditionalLinearGaussian(cpd) => cpd,
+            _ => return Err(JeromeError::InvalidInitialization)
+        };
+
+        let s = cpd.scope();
+        if cpd.variable() == var && s.len() == parents.len() + 1 && parents.iter().all(|v| s.contains(v)) {
+            Ok(cpd)
+        } else {
+            Err(JeromeError::InvalidScope)
+        }
+    }
+
+    /// `true`, if this `Initialization` is a structured CPD of a discrete `Variable`
+    fn is_structured(&self) -> bool {
+        matches!(
+            *self,
+            Initialization::NoisyMax(_) | Initialization::Tree(_) | Initialization::Deterministic(_)
+        )
+    }
+
+    /// `true`, if this `Initialization` is for the CPD of a continuous `Variable`
+    fn is_continuous(&self) -> bool {
+        matches!(
+            *self,
+            Initialization::Gaussian(..) | Initialization::LinearGaussian(_) | Initialization::ConditionalLinearGaussian(_)
+        )
+    }
+
     /// Construct a factor, initialized based on ```self```
     ///
     /// # Args
@@ -134,6 +250,11 @@ impl<'a> Initialization<'a> {
             return Err(JeromeError::InvalidScope);
         }
 
+        // continuous and structured CPDs are only meaningful in a `DirectedModel`
+        if self.is_continuous() || self.is_structured() {
+            return Err(JeromeError::InvalidInitialization);
+        }
+
         // if this is a user defined factor, it just needs to be verified and returned
         if let Initialization::Table(f) = self {
             let s = f.scope();
@@ -196,16 +317,31 @@ impl<'a> Initialization<'a> {
                 array![p, (1.0 - p)].into_dyn()
             },
             Initialization::Multinomial(p) => {
-                nd::Array::from_iter(p.iter().map(|&x| x)).into_dyn()
+                nd::Array::from_iter(p.iter().copied()).into_dyn()
             },
-            Initialization::Table(_) => panic!("unreachable")
+            _ => panic!("unreachable")
         };
 
         Factor::new(scope.into_iter().collect(), tbl)
     }
 }
 
+impl<'a> From<DiscreteCpd> for Initialization<'a> {
+
+    /// Initialize a CPD as a copy of an existing one, keeping its structure
+    fn from(cpd: DiscreteCpd) -> Self {
+        match cpd {
+            DiscreteCpd::Table(f) => Initialization::Table(f),
+            DiscreteCpd::NoisyMax(cpd) => Initialization::NoisyMax(cpd),
+            DiscreteCpd::Tree(cpd) => Initialization::Tree(cpd),
+            DiscreteCpd::Deterministic(cpd) => Initialization::Deterministic(cpd)
+        }
+    }
+
+}
+
 #[cfg(test)]
+#[allow(clippy::nonminimal_bool, clippy::legacy_numeric_constants)]
 mod tests {
     use super::*;
     use variable::{all_assignments, Assignment};
@@ -282,6 +418,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn random_cpd_init() {
+        let a = Variable::binary();
+        let b = Variable::discrete(10);
+        let c = Variable::discrete(3);
+
+        let factor = Initialization::Random.build_cpd(c, vec![a, b].into_iter().collect()).unwrap();
+        assert!(factor.is_cpd());
+
+        // each distribution over c, given its parents, is normalized
+        for parents in all_assignments(&vec![a, b]) {
+            let sum: f64 = (0..c.cardinality()).map(|i| {
+                let mut assn = parents.clone();
+                assn.set(&c, i);
+                factor.value(&assn).unwrap()
+            }).sum();
+
+            assert!((1.0 - sum).abs() < 1e-9);
+        }
+    }
+
     #[test]
     fn uniform_init() {
         let a = Variable::binary();
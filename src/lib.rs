@@ -13,8 +13,10 @@ extern crate ndarray;
 extern crate ndarray_rand;
 extern crate rand;
 
+pub mod cpd;
 pub mod estimators;
 pub mod factor;
+pub mod gaussian;
 pub mod inference;
 pub mod init;
 pub mod model;
@@ -23,14 +25,19 @@ pub mod util;
 pub use util::{Result, JeromeError};
 pub mod variable;
 
+pub use cpd::*;
 pub use estimators::*;
 pub use factor::*;
+pub use gaussian::*;
 pub use inference::*;
 pub use init::*;
 pub use model::*;
 pub use model::directed::*;
+pub use model::dynamic::*;
+pub use model::graph::*;
+pub use model::hmm::*;
+pub use model::independence::*;
 pub use model::undirected::*;
 pub use samplers::*;
-pub use util::*;
 pub use variable::*;
 
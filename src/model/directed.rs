@@ -1,16 +1,19 @@
 //! Defines a `DirectedModel`, which is a Bayesian model that represents the factorization of 
 //! a probability distribution P
 
+use cpd::{Deterministic, DiscreteCpd, NoisyMax, TreeCpd};
 use factor::Factor;
+use gaussian::{ConditionalLinearGaussian, LinearGaussian};
 use init::Initialization;
 use util::{Result, JeromeError};
-use variable::{Assignment, Variable};
+use variable::{Assignment, Labels, Variable};
 use super::Model;
 
 use bidir_map::BidirMap;
 use indexmap::IndexMap;
 
 use std::collections::HashSet;
+use std::result;
 
 
 /// Represents a Bayesian Network - a Directed Probabilistic Graphical Model.
@@ -21,33 +24,180 @@ use std::collections::HashSet;
 /// Conditional Probability Distribution (CPD) of each `Variable` implicitly defines the edges of
 /// the graph. The `Variable`s are held in their topological order to faciliate efficient
 /// computations over the graph.
+///
+/// The CPD of a discrete `Variable` may be a table `Factor`, or a structured CPD such as a
+/// `NoisyMax`, which is kept in its compact form; see `DiscreteCpd`.
+///
+/// Continuous `Variable`s are described by `ConditionalLinearGaussian` CPDs: their discrete
+/// parents select among linear-Gaussian regressions on their continuous parents. Discrete
+/// `Variable`s may only have discrete parents, so the discrete `Variable`s followed by the
+/// continuous `Variable`s is a topological order.
 pub struct DirectedModel {
 
     /// The `Variable`s comprising the scope of the `DirectedModel` and their associated CPDs. Note
-    /// that the `Factor` associated with a `Variable` ```X``` has scope ```X U Pa(X)````, where
+    /// that the CPD associated with a `Variable` ```X``` has scope ```X U Pa(X)````, where
     /// ```Pa(X)``` are the parent's of ```X```. Therefore, in the DAG represented by this map,
     /// there are edges ```P -> X forall P in X.scope() where P != X```
-    graph: IndexMap<Variable, Factor>,
+    graph: IndexMap<Variable, DiscreteCpd>,
+
+    /// The continuous `Variable`s of the `DirectedModel` and their associated CPDs, in
+    /// topological order.
+    continuous: IndexMap<Variable, ConditionalLinearGaussian>,
 
     /// The user-defined names of each `Variable`. This is a two way lookup ```(`Variable`->Name)```
     /// and ```(Name->`Variable`)```
-    names: BidirMap<Variable, String>
+    names: BidirMap<Variable, String>,
+
+    /// The state labels of the `Variable`s
+    labels: Labels,
+
+    /// The children of each `Variable`, in topological order. This is the reverse of the edges
+    /// implied by the CPDs, computed once when the model is built.
+    children: IndexMap<Variable, Vec<Variable>>
 
 }
 
 impl DirectedModel {
    
-    /// Get the `Factor` for the given variable in this model.
+    /// Get the `Factor` for the given variable in this model. Continuous `Variable`s have no
+    /// `Factor`; see `DirectedModel::gaussian_cpd`.
+    ///
+    /// A structured CPD is expanded to a table the first time it is requested; see
+    /// `DirectedModel::discrete_cpd` to work with it directly.
     pub fn cpd(&self, v: &Variable) -> Option<&Factor> {
+        self.graph.get(v).map(DiscreteCpd::as_factor)
+    }
+
+    /// Get the CPD for the given discrete variable in this model, in its compact form.
+    pub fn discrete_cpd(&self, v: &Variable) -> Option<&DiscreteCpd> {
         self.graph.get(v)
     }
 
+    /// Get the `NoisyMax` CPD for the given discrete variable in this model. `None` if the
+    /// variable has some other CPD.
+    pub fn noisy_max_cpd(&self, v: &Variable) -> Option<&NoisyMax> {
+        match self.graph.get(v) {
+            Some(DiscreteCpd::NoisyMax(cpd)) => Some(cpd),
+            _ => None
+        }
+    }
+
+    /// Get the `TreeCpd` for the given discrete variable in this model. `None` if the variable
+    /// has some other CPD.
+    pub fn tree_cpd(&self, v: &Variable) -> Option<&TreeCpd> {
+        match self.graph.get(v) {
+            Some(DiscreteCpd::Tree(cpd)) => Some(cpd),
+            _ => None
+        }
+    }
+
+    /// Get the `Deterministic` CPD for the given discrete variable in this model. `None` if the
+    /// variable has some other CPD.
+    pub fn deterministic_cpd(&self, v: &Variable) -> Option<&Deterministic> {
+        match self.graph.get(v) {
+            Some(DiscreteCpd::Deterministic(cpd)) => Some(cpd),
+            _ => None
+        }
+    }
+
+    /// Get the `LinearGaussian` CPD for the given continuous variable in this model. `None` if the
+    /// variable has discrete parents; see `DirectedModel::clg_cpd`.
+    pub fn gaussian_cpd(&self, v: &Variable) -> Option<&LinearGaussian> {
+        self.continuous.get(v).and_then(|cpd| cpd.as_linear_gaussian())
+    }
+
+    /// Get the `ConditionalLinearGaussian` CPD for the given continuous variable in this model.
+    pub fn clg_cpd(&self, v: &Variable) -> Option<&ConditionalLinearGaussian> {
+        self.continuous.get(v)
+    }
+
+    /// Determine the log likelihood of the `DirectedModel` given a dataset of full `Assignment`s,
+    /// i.e. the sum of the log probability (or log density) of each `Assignment`.
+    pub fn log_likelihood<'a>(&self, dataset: impl Iterator<Item = &'a Assignment>) -> Result<f64> {
+        dataset.map(|a| self.log_probability(a)).sum()
+    }
+
     /// Get a topological order of the `DirectedModel`
     pub fn topological_order(&self) -> Vec<Variable> {
-        self.graph.keys().cloned().collect() 
+        self.graph.keys().chain(self.continuous.keys()).cloned().collect() 
+    }
+
+    /// Get the parents of a `Variable`, in the order of the scope of its CPD. Empty if the
+    /// `Variable` is not in the model.
+    pub fn parents(&self, v: &Variable) -> Vec<Variable> {
+        let scope = match (self.graph.get(v), self.continuous.get(v)) {
+            (Some(cpd), _) => cpd.scope(),
+            (_, Some(cpd)) => cpd.scope(),
+            _ => Vec::new()
+        };
+
+        scope.into_iter().filter(|p| p != v).collect()
+    }
+
+    /// Get the children of a `Variable`, in topological order. Empty if the `Variable` is not in
+    /// the model.
+    pub fn children(&self, v: &Variable) -> Vec<Variable> {
+        self.children.get(v).cloned().unwrap_or_default()
+    }
+
+    /// Get the ancestors of a `Variable` - every `Variable` with a directed path to it
+    pub fn ancestors(&self, v: &Variable) -> HashSet<Variable> {
+        reachable(v, |u| self.parents(u))
+    }
+
+    /// Get the descendants of a `Variable` - every `Variable` with a directed path from it
+    pub fn descendants(&self, v: &Variable) -> HashSet<Variable> {
+        reachable(v, |u| self.children(u))
+    }
+
+    /// Get the Markov blanket of a `Variable`: its parents, its children, and the other parents
+    /// of its children. Given its Markov blanket, a `Variable` is independent of the rest of the
+    /// model (Koller & Friedman Definition 4.10).
+    pub fn markov_blanket(&self, v: &Variable) -> HashSet<Variable> {
+        let mut blanket: HashSet<Variable> = self.parents(v).into_iter().collect();
+
+        for c in self.children(v) {
+            blanket.extend(self.parents(&c));
+            blanket.insert(c);
+        }
+
+        blanket.remove(v);
+        blanket
+    }
+
+    /// Get the `Variable`s with no parents, in topological order
+    pub fn roots(&self) -> Vec<Variable> {
+        self.topological_order().into_iter().filter(|v| self.parents(v).is_empty()).collect()
+    }
+
+    /// Get the `Variable`s with no children, in topological order
+    pub fn leaves(&self) -> Vec<Variable> {
+        self.children.iter().filter(|&(_, cs)| cs.is_empty()).map(|(&v, _)| v).collect()
+    }
+
+    /// Get the adjacency list of the DAG: each `Variable`, in topological order, with its
+    /// children
+    pub fn adjacency(&self) -> &IndexMap<Variable, Vec<Variable>> {
+        &self.children
     }
 }
 
+
+/// Collect every `Variable` reachable from `v` by repeatedly following `next`, excluding `v`
+/// itself
+fn reachable<F: Fn(&Variable) -> Vec<Variable>>(v: &Variable, next: F) -> HashSet<Variable> {
+    let mut visited = HashSet::new();
+    let mut frontier = next(v);
+
+    while let Some(u) = frontier.pop() {
+        if visited.insert(u) {
+            frontier.extend(next(&u));
+        }
+    }
+
+    visited
+}
+
 impl Model for DirectedModel {
 
     type Model_Type = DirectedModel;
@@ -64,21 +214,26 @@ impl Model for DirectedModel {
 
     /// Get all `Variable`s in the model.
     fn variables(&self) -> HashSet<Variable> {
-        self.graph.keys().map(|&v| v).collect()
+        self.graph.keys().chain(self.continuous.keys()).copied().collect()
     }
 
     /// Get the number of `Variable`s in the the `DirectedModel`
     fn num_variables(&self) -> usize {
-        self.graph.len()
+        self.graph.len() + self.continuous.len()
+    }
+
+    /// Get the state labels of the `Variable`s in the `DirectedModel`
+    fn labels(&self) -> &Labels {
+        &self.labels
     }
 
     /// Condition the `DirectedModel` given the evidence.
     fn condition(&self, evidence: &Assignment) -> Self {
-        let mut builder = DirectedModelBuilder::new();
+        let mut builder = DirectedModelBuilder::new().with_labels_of(&self.labels);
 
         // For each variable in the graph
-        for (var, ref cpt) in self.graph.iter() {
-            if let None = evidence.get(var) {
+        for (var, cpt) in self.graph.iter() {
+            if evidence.get(var).is_none() {
                 // if the variable is *not* in the evidence, then it belongs in the new graph with
                 // a CPT reduced by the evidence
                 let new_cpt = cpt.reduce(evidence);
@@ -86,7 +241,21 @@ impl Model for DirectedModel {
                 // safe to unwrap, we *know* var is in this model
                 let name = self.lookup_name(var).unwrap();
                 
-                builder = builder.with_named_variable(var, name.as_str(), parents, Initialization::Table(new_cpt));
+                builder = builder.with_named_variable(var, name.as_str(), parents, Initialization::from(new_cpt));
+            }
+        }
+
+        // continuous variables are handled the same way: observed discrete parents select among
+        // the regressions, and observed continuous parents are folded into their means
+        for (var, cpd) in self.continuous.iter() {
+            if evidence.get_continuous(var).is_none() {
+                let new_cpd = cpd.reduce(evidence);
+                let parents: HashSet<Variable> = new_cpd.scope().into_iter().filter(|v| v != var).collect();
+                let name = self.lookup_name(var).unwrap();
+
+                builder = builder.with_named_variable(
+                    var, name.as_str(), parents, Initialization::ConditionalLinearGaussian(new_cpd)
+                );
             }
         }
 
@@ -94,32 +263,54 @@ impl Model for DirectedModel {
     }
 
     /// Determine the probability of a full `Assignment` to the `Variable`s in the `DirectedModel`.
+    ///
+    /// If the `DirectedModel` has continuous `Variable`s, this is a density.
     fn probability(&self, assignment: &Assignment) -> Result<f64> {
         // for every variable in the graph
         self.graph.values()
                   // get the probability of the assignment
-                  .map(|ref cpt| cpt.value(assignment)) 
+                  .map(|cpt| cpt.value(assignment)) 
+                  .chain(self.continuous.values().map(|cpd| cpd.density(assignment)))
                   // and multiply those probability by the chain rule
                   // but if there are any errors, just return the error
-                  .fold(Ok(1.0), |acc, val| acc.and_then(|p| val.map(|v| p * v)))
+                  .try_fold(1.0, |p, val| val.map(|v| p * v))
+    }
+
+    /// Determine the log probability of a full `Assignment` to the `Variable`s in the
+    /// `DirectedModel`.
+    fn log_probability(&self, assignment: &Assignment) -> Result<f64> {
+        self.graph.values()
+                  .map(|cpt| cpt.log_value(assignment))
+                  .chain(self.continuous.values().map(|cpd| cpd.log_density(assignment)))
+                  .try_fold(0.0, |p, val| val.map(|v| p + v))
     }
 }
 
 
 /// An implementation of the [builder pattern] for creating a `DirectedModel`.
 ///
-/// At the moment, models must be assembled in topological order. A more flexible API, while
-/// desireable, would introduce a lot of complexity I don't currently have time for.
+/// By default, models must be assembled in topological order, so that a missing parent is
+/// reported as soon as the `Variable` is added. After `DirectedModelBuilder::in_any_order`,
+/// `Variable`s may be added before their parents, and are sorted topologically by `build`.
 ///
 /// [builder pattern]: https://en.wikipedia.org/wiki/Builder_pattern
 pub struct DirectedModelBuilder {
 
     /// The `Variable`s and their associated CPDs
-    factors: IndexMap<Variable, Factor>,
+    factors: IndexMap<Variable, DiscreteCpd>,
+
+    /// The continuous `Variable`s and their associated CPDs
+    continuous: IndexMap<Variable, ConditionalLinearGaussian>,
 
     /// The names of each `Variable`
     names: BidirMap<Variable, String>,
 
+    /// The state labels of the `Variable`s
+    labels: Labels,
+
+    /// If `true`, `Variable`s may be added before their parents
+    any_order: bool,
+
     /// The error state of the builder
     err: Option<JeromeError>
 
@@ -132,12 +323,23 @@ impl DirectedModelBuilder {
     pub fn new() -> Self {
         DirectedModelBuilder { 
             factors: IndexMap::new(),
+            continuous: IndexMap::new(),
             names: BidirMap::new(),
+            labels: Labels::new(),
+            any_order: false,
             err: None
         }
     }
 
 
+    /// Allow `Variable`s to be added in any order. Parents are resolved, and the `Variable`s
+    /// sorted topologically, when the model is built; see `DirectedModelBuilder::build`.
+    pub fn in_any_order(mut self) -> Self {
+        self.any_order = true;
+        self
+    }
+
+
     /// Add an anonymous `Variable` to the `DirectedModel`.
     ///
     /// # Args
@@ -172,24 +374,98 @@ impl DirectedModelBuilder {
     }
 
 
+    /// Label the values of a `Variable` in the `DirectedModel`, in order; see `Labels::set`.
+    pub fn with_labels(mut self, var: &Variable, labels: &[&str]) -> Self {
+        if self.err.is_some() {
+            return self;
+        }
+
+        if let Err(e) = self.labels.set(var, labels) {
+            self.err = Some(e);
+        }
+
+        self
+    }
+
+
+    /// Label the values of every `Variable` labeled in `labels`, e.g. those of another model or of
+    /// a `VariableRegistry`.
+    pub fn with_labels_of(mut self, labels: &Labels) -> Self {
+        self.labels.extend(labels);
+        self
+    }
+
+
     /// Complete building the model.
     ///
     /// # Returns
-    /// the `DirectedModel`, or an error if one was generated during the building process
+    /// the `DirectedModel`, or an error if one was generated during the building process. If
+    /// `Variable`s were added in any order, this is also where `JeromeError::MissingParent` is
+    /// reported, as is `JeromeError::Cycle` if the parents do not form a DAG.
     ///
     /// # Postcondition
     /// This call consumes the `DirectedModelBuilder`
-    pub fn build(self) -> Result<DirectedModel> {
+    pub fn build(mut self) -> Result<DirectedModel> {
         if let Some(e) = self.err {
-            Err(e)
-        } else {
-            Ok(self.to_model())
+            return Err(e);
+        }
+
+        if self.any_order {
+            self.sort()?;
+        }
+
+        Ok(self.into_model())
+    }
+
+    /// Internal function that sorts the `Variable`s topologically, once all have been added
+    fn sort(&mut self) -> Result<()> {
+        let factors = &self.factors;
+        let continuous = &self.continuous;
+        let parents = |v: &Variable| -> Vec<Variable> {
+            let scope = factors.get(v).map(DiscreteCpd::scope).unwrap_or_else(|| continuous[v].scope());
+            scope.into_iter().filter(|p| p != v).collect()
+        };
+
+        let all: HashSet<Variable> = factors.keys().chain(continuous.keys()).cloned().collect();
+        if all.iter().any(|v| parents(v).iter().any(|p| ! all.contains(p))) {
+            return Err(JeromeError::MissingParent);
+        }
+
+        // discrete variables never have continuous parents, so each kind is sorted on its own
+        let discrete_order = topological_sort(factors.keys().map(|&v| (v, parents(&v))).collect());
+        let continuous_order = topological_sort(continuous.keys().map(|&v| (v, parents(&v))).collect());
+
+        match (discrete_order, continuous_order) {
+            (Ok(d), Ok(c)) => {
+                self.factors = d.into_iter().map(|v| (v, self.factors.swap_remove(&v).unwrap())).collect();
+                self.continuous = c.into_iter().map(|v| (v, self.continuous.swap_remove(&v).unwrap())).collect();
+                Ok(())
+            },
+            (Err(cycle), _) | (_, Err(cycle)) => {
+                Err(JeromeError::Cycle(cycle.iter().map(|v| self.names.get_by_first(v).unwrap().clone()).collect()))
+            }
         }
     }
 
     /// Internal function that constructs the model
-    fn to_model(self) -> DirectedModel {
-        DirectedModel { graph: self.factors, names: self.names }
+    fn into_model(self) -> DirectedModel {
+        let mut model = DirectedModel {
+            graph: self.factors,
+            continuous: self.continuous,
+            names: self.names,
+            labels: self.labels,
+            children: IndexMap::new()
+        };
+
+        let order = model.topological_order();
+        model.children = order.iter().map(|&v| (v, Vec::new())).collect();
+        for v in order {
+            for p in model.parents(&v) {
+                model.children[&p].push(v);
+            }
+        }
+
+        model
     }
 
     /// Internal function that acutally does the variable addition to the model
@@ -208,40 +484,114 @@ impl DirectedModelBuilder {
 
         ///////////////////////////////////////////////////////////////////////
         // 2) Check for error conditions
-        if parents.iter().any(|v| ! self.factors.contains_key(v)) {
+        // parents added out of order are checked when the model is built
+        if ! self.any_order && parents.iter().any(|v| ! self.factors.contains_key(v) && ! self.continuous.contains_key(v)) {
             self.err = Some(JeromeError::MissingParent);
             return self;
         }
 
-        if self.factors.contains_key(var) {
+        if self.factors.contains_key(var) || self.continuous.contains_key(var) {
             self.err = Some(JeromeError::DuplicateVariable);
         }
 
-        ///////////////////////////////////////////////////////////////////////
-        // 3) Build the factor based on the initialization
-        let factor = init.build_cpd(*var, parents.clone());
-        
-        if let Err(e) = factor {
-            self.err = Some(e);
+        // discrete variables with continuous parents are not supported
+        if ! var.is_continuous() && parents.iter().any(|v| v.is_continuous()) {
+            self.err = Some(JeromeError::InvalidScope);
             return self;
         }
 
-        let factor = factor.unwrap();
-
         ///////////////////////////////////////////////////////////////////////
-        // 4) Add to current model
-        self.factors.insert(*var, factor);
+        // 3) Build the CPD based on the initialization, and 4) add it to the current model
+        if var.is_continuous() {
+            match init.build_gaussian(*var, parents) {
+                Ok(cpd) => { self.continuous.insert(*var, cpd); },
+                Err(e) => {
+                    self.err = Some(e);
+                    return self;
+                }
+            }
+        } else {
+            match init.build_discrete(*var, parents) {
+                Ok(cpd) => { self.factors.insert(*var, cpd); },
+                Err(e) => {
+                    self.err = Some(e);
+                    return self;
+                }
+            }
+        }
+
         self.names.insert(*var, name);
 
         self
     }
 }
 
+/// Sort the `Variable`s so that each follows its parents, keeping the given order where possible.
+/// Parents that are not among the `Variable`s are ignored.
+///
+/// # Returns
+/// the sorted `Variable`s, or the `Variable`s along a directed cycle (in the direction of the
+/// edges) if there is one
+fn topological_sort(parents: IndexMap<Variable, Vec<Variable>>) -> result::Result<Vec<Variable>, Vec<Variable>> {
+    let mut order: Vec<Variable> = Vec::with_capacity(parents.len());
+    let mut placed: HashSet<Variable> = HashSet::new();
+
+    // repeatedly place every variable whose parents have all been placed (Kahn's algorithm)
+    while order.len() < parents.len() {
+        let ready: Vec<Variable> = parents.iter()
+                                          .filter(|&(v, _)| ! placed.contains(v))
+                                          .filter(|&(_, ps)| ps.iter().all(|p| placed.contains(p) || ! parents.contains_key(p)))
+                                          .map(|(&v, _)| v)
+                                          .collect();
+
+        if ready.is_empty() {
+            break;
+        }
+
+        placed.extend(ready.iter().cloned());
+        order.extend(ready);
+    }
+
+    if order.len() == parents.len() {
+        return Ok(order);
+    }
+
+    // every remaining variable has a remaining parent, so walking up from any of them must
+    // eventually revisit a variable, closing a cycle
+    let remaining = |v: &Variable| parents.contains_key(v) && ! placed.contains(v);
+    let mut path: Vec<Variable> = vec![*parents.keys().find(|v| remaining(v)).unwrap()];
+    loop {
+        let current = *path.last().unwrap();
+        let next = *parents[&current].iter().find(|p| remaining(p)).unwrap();
+
+        if let Some(start) = path.iter().position(|&v| v == next) {
+            let mut cycle = path.split_off(start);
+            cycle.reverse();
+            return Err(cycle);
+        }
+
+        path.push(next);
+    }
+}
+
+
+impl Default for DirectedModelBuilder {
+
+    fn default() -> Self {
+        DirectedModelBuilder::new()
+    }
+
+}
+
 #[cfg(test)]
+#[allow(clippy::nonminimal_bool)]
 mod tests {
 
     #[cfg(test)]
     use super::*;
+    use gaussian::ConditionalLinearGaussian;
+    use model::undirected::UndirectedModel;
+    use std::convert::TryFrom;
 
     #[test]
     fn build_empty() {
@@ -313,6 +663,35 @@ mod tests {
     }
 
     
+    #[test]
+    /// Tests that state labels are kept by the operations that produce a new model
+    fn labels() {
+        let x = Variable::binary();
+        let y = Variable::discrete(3);
+        let model = DirectedModelBuilder::new()
+                        .with_variable(&x, HashSet::new(), Initialization::Uniform)
+                        .with_variable(&y, vec![x].into_iter().collect(), Initialization::Uniform)
+                        .with_labels(&y, &["low", "medium", "high"])
+                        .build()
+                        .unwrap();
+
+        assert_eq!(model.labels().label(&y, 1).unwrap(), "medium");
+        assert_eq!(model.labels().get(&x), None);
+
+        let mut evidence = Assignment::new();
+        evidence.set(&x, 1);
+        assert_eq!(model.labels(), model.condition(&evidence).labels());
+        assert_eq!(model.labels(), UndirectedModel::try_from(&model).unwrap().labels());
+        assert_eq!(model.labels(), UndirectedModel::decomposed(&model).unwrap().labels());
+
+        let res = DirectedModelBuilder::new()
+                      .with_variable(&y, HashSet::new(), Initialization::Uniform)
+                      .with_labels(&y, &["low", "high"])
+                      .build();
+        assert!(matches!(res, Err(JeromeError::General(_))));
+    }
+
+
     #[test]
     /// Tests building a model with a single binary variable
     /// Example taken from Koller & Friedman Section 3.1.2
@@ -391,4 +770,311 @@ mod tests {
             assert_eq!(expected, new_model.probability(&a).unwrap());
         }
     }
+
+    #[test]
+    /// The probability of an assignment to a long chain underflows, but its log probability does not
+    fn log_probability() {
+        let vars: Vec<Variable> = (0..1000).map(|_| Variable::binary()).collect();
+        let cpd = |v: Variable, p: Variable| {
+            Factor::cpd(v, vec![p], array![[0.1, 0.9], [0.1, 0.9]].into_dyn()).unwrap()
+        };
+
+        let model = vars.iter()
+                        .enumerate()
+                        .fold(DirectedModelBuilder::new(), |builder, (i, v)| {
+                            if i == 0 {
+                                builder.with_variable(v, HashSet::new(), Initialization::Binomial(0.5))
+                            } else {
+                                builder.with_variable(v, vec![vars[i - 1]].into_iter().collect(), Initialization::Table(cpd(*v, vars[i - 1])))
+                            }
+                        })
+                        .build()
+                        .unwrap();
+
+        let mut a = Assignment::new();
+        for v in vars.iter() {
+            a.set(v, 0);
+        }
+
+        let expected = 0.5f64.ln() + 999.0 * 0.1f64.ln();
+        assert_eq!(0.0, model.probability(&a).unwrap());
+        assert!((expected - model.log_probability(&a).unwrap()).abs() < 1e-8);
+
+        a.unset(&vars[0]);
+        assert!(model.log_probability(&a).is_err());
+    }
+
+    #[test]
+    /// X ~ N(1, 4), Y | X ~ N(2x + 3, 1)
+    fn linear_gaussian() {
+        let x = Variable::continuous();
+        let y = Variable::continuous();
+        let d = Variable::binary();
+
+        let cpd_y = LinearGaussian::new(y, vec![x], vec![2.0], 3.0, 1.0).unwrap();
+        let model = DirectedModelBuilder::new()
+                        .with_named_variable(&x, "X", HashSet::new(), Initialization::Gaussian(1.0, 4.0))
+                        .with_named_variable(&y, "Y", vec![x].into_iter().collect(), Initialization::LinearGaussian(cpd_y.clone()))
+                        .build()
+                        .unwrap();
+
+        assert_eq!(vec![x, y], model.topological_order());
+        assert_eq!(2, model.num_variables());
+        assert!(model.cpd(&x).is_none());
+        assert_eq!(3.0, model.gaussian_cpd(&y).unwrap().bias());
+
+        let mut a = Assignment::new();
+        a.set_continuous(&x, 2.0);
+        a.set_continuous(&y, 6.0);
+
+        let normal = |mean: f64, variance: f64, v: f64| {
+            (-(v - mean).powi(2) / (2.0 * variance)).exp() / (2.0 * std::f64::consts::PI * variance).sqrt()
+        };
+        let expected = normal(1.0, 4.0, 2.0) * normal(7.0, 1.0, 6.0);
+        assert!((expected - model.probability(&a).unwrap()).abs() < 1e-12);
+        assert!((expected.ln() - model.log_probability(&a).unwrap()).abs() < 1e-12);
+
+        // conditioning on X folds its value into the mean of Y
+        let mut evidence = Assignment::new();
+        evidence.set_continuous(&x, 2.0);
+        let conditioned = model.condition(&evidence);
+        assert_eq!(vec![y], conditioned.topological_order());
+        assert!(conditioned.gaussian_cpd(&y).unwrap().parents().is_empty());
+        assert_eq!(7.0, conditioned.gaussian_cpd(&y).unwrap().bias());
+        assert_eq!("Y", conditioned.lookup_name(&y).unwrap());
+
+        // discrete variables may not have continuous parents, and initializations must match the
+        // kind of variable
+        let res = DirectedModelBuilder::new()
+                      .with_variable(&x, HashSet::new(), Initialization::Gaussian(0.0, 1.0))
+                      .with_variable(&d, vec![x].into_iter().collect(), Initialization::Uniform)
+                      .build();
+        assert!(matches!(res, Err(JeromeError::InvalidScope)));
+
+        let res = DirectedModelBuilder::new().with_variable(&x, HashSet::new(), Initialization::Binomial(0.5)).build();
+        assert!(matches!(res, Err(JeromeError::InvalidInitialization)));
+
+        let res = DirectedModelBuilder::new().with_variable(&d, HashSet::new(), Initialization::Gaussian(0.0, 1.0)).build();
+        assert!(matches!(res, Err(JeromeError::InvalidInitialization)));
+
+        let res = DirectedModelBuilder::new()
+                      .with_variable(&x, HashSet::new(), Initialization::Gaussian(0.0, 1.0))
+                      .with_variable(&y, HashSet::new(), Initialization::LinearGaussian(cpd_y))
+                      .build();
+        assert!(matches!(res, Err(JeromeError::InvalidScope)));
+    }
+
+    #[test]
+    /// M ~ Bernoulli(0.3), R | m0 ~ N(0, 1), R | m1 ~ N(u + 5, 4), U ~ N(0, 1)
+    fn conditional_linear_gaussian() {
+        let m = Variable::binary();
+        let u = Variable::continuous();
+        let r = Variable::continuous();
+
+        let cpd_r = ConditionalLinearGaussian::new(
+            r,
+            vec![m],
+            vec![
+                LinearGaussian::new(r, vec![u], vec![0.0], 0.0, 1.0).unwrap(),
+                LinearGaussian::new(r, vec![u], vec![1.0], 5.0, 4.0).unwrap()
+            ]
+        ).unwrap();
+
+        let model = DirectedModelBuilder::new()
+                        .with_named_variable(&u, "U", HashSet::new(), Initialization::Gaussian(0.0, 1.0))
+                        .with_named_variable(&m, "M", HashSet::new(), Initialization::Binomial(0.7))
+                        .with_named_variable(&r, "R", vec![m, u].into_iter().collect(), Initialization::ConditionalLinearGaussian(cpd_r.clone()))
+                        .build()
+                        .unwrap();
+
+        // discrete variables precede continuous ones in the topological order
+        assert_eq!(vec![m, u, r], model.topological_order());
+        assert!(model.gaussian_cpd(&r).is_none());
+        assert_eq!(2, model.clg_cpd(&r).unwrap().regressions().len());
+
+        let normal = |mean: f64, variance: f64, v: f64| {
+            (-(v - mean).powi(2) / (2.0 * variance)).exp() / (2.0 * std::f64::consts::PI * variance).sqrt()
+        };
+
+        let mut a = Assignment::new();
+        a.set(&m, 1);
+        a.set_continuous(&u, 0.5);
+        a.set_continuous(&r, 4.0);
+        let p1 = 0.3 * normal(0.0, 1.0, 0.5) * normal(5.5, 4.0, 4.0);
+        assert!((p1 - model.probability(&a).unwrap()).abs() < 1e-12);
+
+        let mut b = a.clone();
+        b.set(&m, 0);
+        let p0 = 0.7 * normal(0.0, 1.0, 0.5) * normal(0.0, 1.0, 4.0);
+        assert!((p0 - model.probability(&b).unwrap()).abs() < 1e-12);
+
+        let dataset = [a.clone(), b];
+        assert!((p1.ln() + p0.ln() - model.log_likelihood(dataset.iter()).unwrap()).abs() < 1e-9);
+
+        // conditioning on M and U selects and reduces a single regression
+        let mut evidence = Assignment::new();
+        evidence.set(&m, 1);
+        evidence.set_continuous(&u, 0.5);
+        let conditioned = model.condition(&evidence);
+        assert_eq!(vec![r], conditioned.topological_order());
+        assert_eq!(5.5, conditioned.gaussian_cpd(&r).unwrap().bias());
+        assert_eq!(4.0, conditioned.gaussian_cpd(&r).unwrap().variance());
+
+        // the parents of a conditional linear-Gaussian CPD must match the model
+        let res = DirectedModelBuilder::new()
+                      .with_variable(&m, HashSet::new(), Initialization::Binomial(0.7))
+                      .with_variable(&r, vec![m].into_iter().collect(), Initialization::ConditionalLinearGaussian(cpd_r))
+                      .build();
+        assert!(matches!(res, Err(JeromeError::InvalidScope)));
+    }
+
+    #[test]
+    fn noisy_max() {
+        let x1 = Variable::binary();
+        let x2 = Variable::binary();
+        let y = Variable::binary();
+
+        let cpd_y = NoisyMax::noisy_or(y, vec![x1, x2], vec![0.2, 0.5], 0.1).unwrap();
+
+        let model = DirectedModelBuilder::new()
+                        .with_variable(&x1, HashSet::new(), Initialization::Binomial(0.4))
+                        .with_variable(&x2, HashSet::new(), Initialization::Binomial(0.6))
+                        .with_variable(&y, vec![x1, x2].into_iter().collect(), Initialization::NoisyMax(cpd_y.clone()))
+                        .build()
+                        .unwrap();
+
+        assert!(model.noisy_max_cpd(&y).is_some());
+        assert!(model.noisy_max_cpd(&x1).is_none());
+
+        // the table is expanded on demand
+        let f = model.cpd(&y).unwrap();
+        assert_eq!(vec![x1, x2, y], f.scope());
+
+        let mut a = Assignment::new();
+        a.set(&x1, 1);
+        a.set(&x2, 1);
+        a.set(&y, 0);
+        let expected = 0.4 * 0.6 * 0.9 * 0.2 * 0.5;
+        assert!((expected - model.probability(&a).unwrap()).abs() < 1e-12);
+        assert!((0.9 * 0.2 * 0.5 - f.value(&a).unwrap()).abs() < 1e-12);
+
+        // conditioning on a parent keeps the CPD compact, folding the parent into the leak
+        let mut evidence = Assignment::new();
+        evidence.set(&x1, 1);
+        let conditioned = model.condition(&evidence);
+        let reduced = conditioned.noisy_max_cpd(&y).unwrap();
+        assert_eq!(&[x2], reduced.parents());
+        assert!((1.0 - 0.9 * 0.2 - reduced.leak()[1]).abs() < 1e-12);
+
+        // the CPD must match the parents in the model
+        let res = DirectedModelBuilder::new()
+                      .with_variable(&x1, HashSet::new(), Initialization::Binomial(0.4))
+                      .with_variable(&y, vec![x1].into_iter().collect(), Initialization::NoisyMax(cpd_y))
+                      .build();
+        assert!(matches!(res, Err(JeromeError::InvalidScope)));
+    }
+
+    #[test]
+    fn any_order() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+        let c = Variable::binary();
+        let x = Variable::continuous();
+
+        let cpd_x = LinearGaussian::normal(x, 0.0, 1.0).unwrap();
+
+        // C <- B <- A, added leaves first, with a continuous variable before its discrete parent
+        let model = DirectedModelBuilder::new()
+                        .in_any_order()
+                        .with_named_variable(&x, "X", vec![c].into_iter().collect(), Initialization::ConditionalLinearGaussian(
+                            ConditionalLinearGaussian::new(x, vec![c], vec![cpd_x.clone(), cpd_x.clone()]).unwrap()
+                        ))
+                        .with_named_variable(&c, "C", vec![b].into_iter().collect(), Initialization::Uniform)
+                        .with_named_variable(&b, "B", vec![a].into_iter().collect(), Initialization::Uniform)
+                        .with_named_variable(&a, "A", HashSet::new(), Initialization::Binomial(0.3))
+                        .build()
+                        .unwrap();
+
+        assert_eq!(vec![a, b, c, x], model.topological_order());
+        assert_eq!("B", model.lookup_name(&b).unwrap());
+
+        // a parent that is never added is reported at build
+        let res = DirectedModelBuilder::new()
+                      .in_any_order()
+                      .with_variable(&b, vec![a].into_iter().collect(), Initialization::Uniform)
+                      .build();
+        assert!(matches!(res, Err(JeromeError::MissingParent)));
+
+        // A -> B -> C -> A, with D hanging off of the cycle
+        let d = Variable::binary();
+        let res = DirectedModelBuilder::new()
+                      .in_any_order()
+                      .with_named_variable(&d, "D", vec![c].into_iter().collect(), Initialization::Uniform)
+                      .with_named_variable(&a, "A", vec![c].into_iter().collect(), Initialization::Uniform)
+                      .with_named_variable(&b, "B", vec![a].into_iter().collect(), Initialization::Uniform)
+                      .with_named_variable(&c, "C", vec![b].into_iter().collect(), Initialization::Uniform)
+                      .build();
+
+        match res {
+            Err(e @ JeromeError::Cycle(_)) => {
+                let message = e.to_string();
+                let cycle = message.split(": ").nth(1).unwrap();
+                assert!(["A -> B -> C -> A", "B -> C -> A -> B", "C -> A -> B -> C"].contains(&cycle));
+                assert!(! message.contains('D'));
+            },
+            _ => panic!("expected a cycle")
+        }
+    }
+
+    #[test]
+    /// The structure of the student network, Koller & Friedman Figure 3.4
+    fn structure() {
+        let d = Variable::binary();
+        let i = Variable::binary();
+        let g = Variable::discrete(3);
+        let s = Variable::binary();
+        let l = Variable::binary();
+
+        let model = DirectedModelBuilder::new()
+                        .with_variable(&d, HashSet::new(), Initialization::Uniform)
+                        .with_variable(&i, HashSet::new(), Initialization::Uniform)
+                        .with_variable(&g, vec![i, d].into_iter().collect(), Initialization::Uniform)
+                        .with_variable(&s, vec![i].into_iter().collect(), Initialization::Uniform)
+                        .with_variable(&l, vec![g].into_iter().collect(), Initialization::Uniform)
+                        .build()
+                        .unwrap();
+
+        let set = |vs: Vec<Variable>| vs.into_iter().collect::<HashSet<Variable>>();
+
+        // parents follow the scope of the CPD
+        let scope = model.cpd(&g).unwrap().scope();
+        assert_eq!(scope[..2].to_vec(), model.parents(&g));
+        assert!(model.parents(&d).is_empty());
+
+        assert_eq!(vec![g, s], model.children(&i));
+        assert_eq!(vec![l], model.children(&g));
+        assert!(model.children(&l).is_empty());
+
+        assert_eq!(set(vec![i, d, g]), model.ancestors(&l));
+        assert!(model.ancestors(&i).is_empty());
+        assert_eq!(set(vec![g, s, l]), model.descendants(&i));
+        assert_eq!(set(vec![g, l]), model.descendants(&d));
+
+        // the blanket of D includes its co-parent I
+        assert_eq!(set(vec![g, i]), model.markov_blanket(&d));
+        assert_eq!(set(vec![i, d, l]), model.markov_blanket(&g));
+        assert_eq!(set(vec![d, g, s]), model.markov_blanket(&i));
+
+        assert_eq!(vec![d, i], model.roots());
+        assert_eq!(vec![s, l], model.leaves());
+
+        let adjacency = model.adjacency();
+        assert_eq!(model.topological_order(), adjacency.keys().cloned().collect::<Vec<Variable>>());
+        assert_eq!(vec![g], adjacency[&d]);
+
+        // a variable outside of the model has no neighbors
+        let x = Variable::binary();
+        assert!(model.parents(&x).is_empty());
+        assert!(model.markov_blanket(&x).is_empty());
+    }
 }
@@ -0,0 +1,505 @@
+//! Defines a `DynamicModel`, a Dynamic Bayesian Network over a process that evolves in time
+//!
+//! A `DynamicModel` is represented as a 2-TBN (Koller & Friedman Definition 6.4 and 6.5): an
+//! initial `DirectedModel` over the state `Variable`s at time 0, and a transition network that
+//! defines the distribution of the state `Variable`s at time ```t + 1``` given those at time
+//! ```t```. Unrolling the model for a number of time slices produces an ordinary `DirectedModel`,
+//! to which every sampler and inference engine applies.
+
+use cpd::DiscreteCpd;
+use init::Initialization;
+use util::{JeromeError, Result};
+use variable::{Labels, Variable};
+use super::Model;
+use super::directed::{DirectedModel, DirectedModelBuilder};
+
+use indexmap::IndexMap;
+
+use std::collections::{HashMap, HashSet};
+
+
+/// Represents a Dynamic Bayesian Network as a 2-TBN.
+///
+/// # Representation
+/// Each state `Variable` ```X``` is paired with a `Variable` ```X'``` that stands for its value in
+/// the next time slice. The initial network is a `DirectedModel` over the ```X```. Each ```X'```
+/// has a CPD in the transition network, whose parents may be any ```X``` (the interface
+/// `Variable`s) or any other ```X'```.
+///
+/// These `Variable`s are templates: unrolling the model creates new `Variable`s for each time
+/// slice, named ```"{name}_{t}"```.
+pub struct DynamicModel {
+
+    /// Each state `Variable`, and its copy in the next time slice
+    states: IndexMap<Variable, Variable>,
+
+    /// The distribution over the state `Variable`s at time 0
+    initial: DirectedModel,
+
+    /// The transition network. Each state `Variable` is a root with a uniform CPD, which is not
+    /// part of the 2-TBN; only the CPDs of the next time slice are.
+    transition: DirectedModel
+
+}
+
+
+impl DynamicModel {
+
+    /// Get the state `Variable`s, in the order they were declared
+    pub fn states(&self) -> Vec<Variable> {
+        self.states.keys().cloned().collect()
+    }
+
+    /// Get the copy of a state `Variable` in the next time slice
+    pub fn next(&self, state: &Variable) -> Option<Variable> {
+        self.states.get(state).cloned()
+    }
+
+    /// Get the name of a state `Variable`
+    pub fn lookup_name(&self, state: &Variable) -> Option<&String> {
+        self.initial.lookup_name(state)
+    }
+
+    /// Lookup a state `Variable` by name
+    pub fn lookup_variable(&self, name: &str) -> Option<&Variable> {
+        self.initial.lookup_variable(name)
+    }
+
+    /// Get the state labels of the state `Variable`s and their copies in the next time slice
+    pub fn labels(&self) -> &Labels {
+        self.transition.labels()
+    }
+
+    /// Get the initial network, over the state `Variable`s at time 0
+    pub fn initial(&self) -> &DirectedModel {
+        &self.initial
+    }
+
+    /// Get the CPD of a state `Variable` in the next time slice, over its parents in the current
+    /// and next time slices
+    pub fn transition_cpd(&self, next: &Variable) -> Option<&DiscreteCpd> {
+        if self.states.contains_key(next) {
+            None
+        } else {
+            self.transition.discrete_cpd(next)
+        }
+    }
+
+    /// Get the parents of a state `Variable` in the next time slice
+    pub fn transition_parents(&self, next: &Variable) -> Vec<Variable> {
+        if self.states.contains_key(next) {
+            Vec::new()
+        } else {
+            self.transition.parents(next)
+        }
+    }
+
+    /// Get the state `Variable`s of the next time slice, in a topological order of the transition
+    /// network
+    pub fn transition_order(&self) -> Vec<Variable> {
+        self.transition.topological_order().into_iter().filter(|v| ! self.states.contains_key(v)).collect()
+    }
+
+    /// Get the interface `Variable`s: the state `Variable`s with a child in the next time slice
+    pub fn interface(&self) -> Vec<Variable> {
+        self.states.keys().filter(|x| ! self.transition.children(x).is_empty()).cloned().collect()
+    }
+
+    /// Get the name of a state `Variable` in time slice `t` of the unrolled model
+    pub fn slice_name(&self, state: &Variable, t: usize) -> Option<String> {
+        self.lookup_name(state).map(|name| format!("{}_{}", name, t))
+    }
+
+    /// Unroll the model into a `DirectedModel` over `slices` time slices, ```0..slices```. Each
+    /// state `Variable` is copied into every time slice, with the same cardinality and labels,
+    /// and named as by `DynamicModel::slice_name`.
+    ///
+    /// # Errors
+    /// `JeromeError::General` if `slices` is zero
+    pub fn unroll(&self, slices: usize) -> Result<DirectedModel> {
+        if slices == 0 {
+            return Err(JeromeError::General(String::from("Cannot unroll a model for zero time slices")));
+        }
+
+        let mut builder = DirectedModelBuilder::new();
+        let mut labels = Labels::new();
+        let mut previous: HashMap<Variable, Variable> = HashMap::new();
+
+        for t in 0..slices {
+            let current: HashMap<Variable, Variable> = self.states.keys().map(|&x| (x, copy(&x))).collect();
+            for (x, v) in current.iter() {
+                labels.copy_from(self.labels(), x, v);
+            }
+
+            // the first slice follows the initial network, and every other follows the transition
+            // network, with X standing for the previous slice and X' for the current one
+            let (map, cpds): (HashMap<Variable, Variable>, Vec<(Variable, &DiscreteCpd)>) = if t == 0 {
+                let cpds = self.initial.topological_order().into_iter().map(|x| (x, self.initial.discrete_cpd(&x).unwrap())).collect();
+                (current.clone(), cpds)
+            } else {
+                let map = previous.iter()
+                                  .map(|(&x, &v)| (x, v))
+                                  .chain(self.states.iter().map(|(x, next)| (*next, current[x])))
+                                  .collect();
+                let cpds = self.transition_order().into_iter().map(|x| (x, self.transition.discrete_cpd(&x).unwrap())).collect();
+                (map, cpds)
+            };
+
+            for (template, cpd) in cpds {
+                let cpd = cpd.relabel(&map)?;
+                let var = map[&template];
+                let state = if t == 0 { template } else { self.state_of(&template) };
+                let parents: HashSet<Variable> = cpd.scope().into_iter().filter(|v| *v != var).collect();
+
+                builder = builder.with_named_variable(
+                    &var, &self.slice_name(&state, t).unwrap(), parents, Initialization::from(cpd)
+                );
+            }
+
+            previous = current;
+        }
+
+        builder.with_labels_of(&labels).build()
+    }
+
+    /// Get the state `Variable` of which `next` is the copy in the next time slice
+    fn state_of(&self, next: &Variable) -> Variable {
+        *self.states.iter().find(|&(_, n)| n == next).unwrap().0
+    }
+}
+
+
+/// Create a new `Variable` with the same cardinality as `v`
+pub(super) fn copy(v: &Variable) -> Variable {
+    Variable::discrete(v.cardinality())
+}
+
+
+/// An implementation of the [builder pattern] for creating a `DynamicModel`.
+///
+/// Each state `Variable` must be declared with `with_state` before its CPDs are added. Within the
+/// initial and transition networks, `Variable`s may be added in any order.
+///
+/// [builder pattern]: https://en.wikipedia.org/wiki/Builder_pattern
+pub struct DynamicModelBuilder {
+
+    /// Each state `Variable`, and its copy in the next time slice
+    states: IndexMap<Variable, Variable>,
+
+    /// The names of the state `Variable`s
+    names: HashMap<Variable, String>,
+
+    /// The builder of the initial network
+    initial: DirectedModelBuilder,
+
+    /// The builder of the transition network
+    transition: DirectedModelBuilder,
+
+    /// The error state of the builder
+    err: Option<JeromeError>
+
+}
+
+
+impl DynamicModelBuilder {
+
+    /// Construct a new `DynamicModelBuilder` representing an empty `DynamicModel`
+    pub fn new() -> Self {
+        DynamicModelBuilder {
+            states: IndexMap::new(),
+            names: HashMap::new(),
+            initial: DirectedModelBuilder::new().in_any_order(),
+            transition: DirectedModelBuilder::new().in_any_order(),
+            err: None
+        }
+    }
+
+    /// Declare a state `Variable`.
+    ///
+    /// # Args
+    /// * `state`: the `Variable` in the current time slice
+    /// * `next`: the `Variable` that stands for `state` in the next time slice
+    /// * `name`: the name of the state `Variable`
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if either `Variable` is continuous, or they have different
+    ///   cardinalities
+    /// * `JeromeError::DuplicateVariable`, if either `Variable` was already declared
+    pub fn with_state(mut self, state: &Variable, next: &Variable, name: &str) -> Self {
+        if self.err.is_some() {
+            return self;
+        }
+
+        if state.is_continuous() || state.cardinality() != next.cardinality() || state == next {
+            self.err = Some(JeromeError::InvalidScope);
+            return self;
+        }
+
+        if self.is_declared(state) || self.is_declared(next) {
+            self.err = Some(JeromeError::DuplicateVariable);
+            return self;
+        }
+
+        self.states.insert(*state, *next);
+        self.names.insert(*state, String::from(name));
+        self.transition = self.transition.with_named_variable(state, name, HashSet::new(), Initialization::Uniform);
+
+        self
+    }
+
+    /// Label the values of a declared state `Variable`, in order. The labels apply to the state
+    /// `Variable`, its copy in the next time slice, and every copy in the unrolled model.
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if `state` was not declared
+    /// * see `Labels::set`
+    pub fn with_labels(mut self, state: &Variable, labels: &[&str]) -> Self {
+        if self.err.is_some() {
+            return self;
+        }
+
+        match self.states.get(state) {
+            Some(next) => {
+                self.initial = self.initial.with_labels(state, labels);
+                self.transition = self.transition.with_labels(state, labels).with_labels(next, labels);
+            },
+            None => {
+                self.err = Some(JeromeError::InvalidScope);
+            }
+        }
+
+        self
+    }
+
+    /// Add the CPD of a state `Variable` in the initial network.
+    ///
+    /// # Args
+    /// * `state`: a declared state `Variable`
+    /// * `parents`: the parents of `state`, which must be state `Variable`s
+    /// * `init`: the initialization mechanism for the CPD of `state`
+    pub fn with_initial(mut self, state: &Variable, parents: HashSet<Variable>, init: Initialization) -> Self {
+        if self.err.is_some() {
+            return self;
+        }
+
+        match self.names.get(state) {
+            Some(name) => {
+                self.initial = self.initial.with_named_variable(state, name, parents, init);
+            },
+            None => {
+                self.err = Some(JeromeError::InvalidScope);
+            }
+        }
+
+        self
+    }
+
+    /// Add the CPD of a state `Variable` in the next time slice, to the transition network.
+    ///
+    /// # Args
+    /// * `next`: the copy of a declared state `Variable` in the next time slice
+    /// * `parents`: the parents of `next`, which may be state `Variable`s in the current time
+    ///   slice, or their copies in the next
+    /// * `init`: the initialization mechanism for the CPD of `next`
+    pub fn with_transition(mut self, next: &Variable, parents: HashSet<Variable>, init: Initialization) -> Self {
+        if self.err.is_some() {
+            return self;
+        }
+
+        match self.states.iter().find(|&(_, n)| n == next) {
+            Some((state, _)) => {
+                let name = format!("{}'", self.names[state]);
+                self.transition = self.transition.with_named_variable(next, &name, parents, init);
+            },
+            None => {
+                self.err = Some(JeromeError::InvalidScope);
+            }
+        }
+
+        self
+    }
+
+    /// Complete building the model.
+    ///
+    /// # Returns
+    /// the `DynamicModel`, or an error if one was generated during the building process. In
+    /// addition to the errors of `DirectedModelBuilder::build`, `JeromeError::General` is
+    /// reported if a state `Variable` is missing either of its CPDs.
+    pub fn build(self) -> Result<DynamicModel> {
+        if let Some(e) = self.err {
+            return Err(e);
+        }
+
+        let initial = self.initial.build()?;
+        let transition = self.transition.build()?;
+
+        for (state, next) in self.states.iter() {
+            if initial.discrete_cpd(state).is_none() {
+                return Err(JeromeError::General(format!("State {} has no initial CPD", self.names[state])));
+            }
+
+            if transition.discrete_cpd(next).is_none() {
+                return Err(JeromeError::General(format!("State {} has no transition CPD", self.names[state])));
+            }
+        }
+
+        Ok(DynamicModel { states: self.states, initial, transition })
+    }
+
+    /// Check if a `Variable` was declared as a state, or the copy of one
+    fn is_declared(&self, v: &Variable) -> bool {
+        self.states.contains_key(v) || self.states.values().any(|n| n == v)
+    }
+}
+
+
+impl Default for DynamicModelBuilder {
+
+    fn default() -> Self {
+        Self::new()
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use inference::{ConditionalInferenceEngine, VariableEliminationEngine};
+    use model::hmm::tests::{umbrella, value};
+    use samplers::{ForwardSampler, Sampler};
+    use variable::Assignment;
+
+    #[test]
+    fn structure() {
+        let hmm = umbrella();
+        let (r, r_next, u) = (hmm.hidden(), hmm.next(), hmm.observed());
+        let model = hmm.to_dynamic();
+        let u_next = model.next(&u).unwrap();
+
+        assert_eq!(vec![r, u], model.states());
+        assert_eq!(Some(r_next), model.next(&r));
+        assert_eq!(vec![r], model.interface());
+        assert_eq!(vec![r_next, u_next], model.transition_order());
+        assert_eq!(vec![r_next], model.transition_parents(&u_next));
+        assert!(model.transition_cpd(&r).is_none());
+        assert_eq!(Some(String::from("Observed_3")), model.slice_name(&u, 3));
+
+        let unrolled = model.unroll(3).unwrap();
+        assert_eq!(6, unrolled.num_variables());
+
+        let rain: Vec<Variable> = (0..3).map(|t| *unrolled.lookup_variable(&format!("Hidden_{}", t)).unwrap()).collect();
+        let umbrella: Vec<Variable> = (0..3).map(|t| *unrolled.lookup_variable(&format!("Observed_{}", t)).unwrap()).collect();
+
+        // the template variables are not reused
+        assert!(! unrolled.variables().contains(&r));
+        assert!(unrolled.parents(&rain[0]).is_empty());
+        for t in 1..3 {
+            assert_eq!(vec![rain[t - 1]], unrolled.parents(&rain[t]));
+            assert_eq!(vec![rain[t]], unrolled.parents(&umbrella[t]));
+        }
+
+        assert!(model.unroll(0).is_err());
+    }
+
+    #[test]
+    /// Every copy of a state variable has its labels
+    fn labels() {
+        let x = Variable::binary();
+        let x_next = Variable::binary();
+        let y = Variable::binary();
+        let y_next = Variable::binary();
+
+        let model = DynamicModelBuilder::new()
+                        .with_state(&x, &x_next, "X")
+                        .with_state(&y, &y_next, "Y")
+                        .with_labels(&x, &["off", "on"])
+                        .with_initial(&x, HashSet::new(), Initialization::Uniform)
+                        .with_initial(&y, vec![x].into_iter().collect(), Initialization::Uniform)
+                        .with_transition(&x_next, vec![x].into_iter().collect(), Initialization::Uniform)
+                        .with_transition(&y_next, vec![x_next].into_iter().collect(), Initialization::Uniform)
+                        .build()
+                        .unwrap();
+        assert_eq!(model.labels().get(&x), model.labels().get(&x_next));
+
+        let unrolled = model.unroll(3).unwrap();
+        let xs: Vec<Variable> = (0..3).map(|t| *unrolled.lookup_variable(&format!("X_{}", t)).unwrap()).collect();
+        let ys: Vec<Variable> = (0..3).map(|t| *unrolled.lookup_variable(&format!("Y_{}", t)).unwrap()).collect();
+        assert!(xs.iter().all(|v| unrolled.labels().get(v) == model.labels().get(&x)));
+        assert!(ys.iter().all(|v| unrolled.labels().get(v).is_none()));
+    }
+
+    #[test]
+    fn filtering() {
+        let hmm = umbrella();
+        let unrolled = hmm.unroll(3).unwrap();
+
+        let lookup = |name: String| *unrolled.lookup_variable(&name).unwrap();
+
+        // the umbrella is seen on the first two days, but not the third
+        let mut evidence = Assignment::new();
+        evidence.set(&lookup(String::from("Observed_0")), 1);
+        evidence.set(&lookup(String::from("Observed_1")), 1);
+        evidence.set(&lookup(String::from("Observed_2")), 0);
+
+        // the forward algorithm, by hand
+        let transition = [[0.7, 0.3], [0.3, 0.7]];
+        let emission = [[0.8, 0.2], [0.1, 0.9]];
+        let mut alpha = [0.5 * emission[0][1], 0.5 * emission[1][1]];
+        for &o in [1, 0].iter() {
+            alpha = [
+                (alpha[0] * transition[0][0] + alpha[1] * transition[1][0]) * emission[0][o],
+                (alpha[0] * transition[0][1] + alpha[1] * transition[1][1]) * emission[1][o]
+            ];
+        }
+
+        let rain = lookup(String::from("Hidden_2"));
+        let mut engine = VariableEliminationEngine::for_directed(&unrolled, &evidence).unwrap();
+        let f = engine.infer(&vec![rain].into_iter().collect()).unwrap();
+        assert!((value(&f, rain, 1) - alpha[1] / (alpha[0] + alpha[1])).abs() < 1e-9);
+
+        // the unrolled model can be sampled like any other
+        let mut sampler = ForwardSampler::new(&unrolled);
+        let sample = sampler.sample();
+        assert!(unrolled.variables().iter().all(|v| sample.get(v).is_some()));
+    }
+
+    #[test]
+    fn invalid() {
+        let x = Variable::binary();
+        let x_next = Variable::binary();
+        let y = Variable::discrete(3);
+
+        // the copy must have the same cardinality
+        let res = DynamicModelBuilder::new().with_state(&x, &y, "X").build();
+        assert!(matches!(res, Err(JeromeError::InvalidScope)));
+
+        // CPDs may only be added for declared states
+        let res = DynamicModelBuilder::new().with_initial(&x, HashSet::new(), Initialization::Uniform).build();
+        assert!(matches!(res, Err(JeromeError::InvalidScope)));
+
+        let res = DynamicModelBuilder::new().with_labels(&x, &["off", "on"]).build();
+        assert!(matches!(res, Err(JeromeError::InvalidScope)));
+
+        // every state needs both of its CPDs
+        let res = DynamicModelBuilder::new()
+                      .with_state(&x, &x_next, "X")
+                      .with_initial(&x, HashSet::new(), Initialization::Uniform)
+                      .build();
+        assert!(matches!(res, Err(JeromeError::General(_))));
+
+        // the next time slice must be acyclic
+        let z = Variable::binary();
+        let z_next = Variable::binary();
+        let res = DynamicModelBuilder::new()
+                      .with_state(&x, &x_next, "X")
+                      .with_state(&z, &z_next, "Z")
+                      .with_initial(&x, HashSet::new(), Initialization::Uniform)
+                      .with_initial(&z, HashSet::new(), Initialization::Uniform)
+                      .with_transition(&x_next, vec![z_next].into_iter().collect(), Initialization::Uniform)
+                      .with_transition(&z_next, vec![x_next].into_iter().collect(), Initialization::Uniform)
+                      .build();
+        assert!(matches!(res, Err(JeromeError::Cycle(_))));
+    }
+}
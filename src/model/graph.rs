@@ -0,0 +1,465 @@
+//! Defines an `UndirectedGraph` over `Variable`s - the explicit structure of a Markovian Network
+//!
+//! Neither `DirectedModel` nor `UndirectedModel` holds a graph data structure. An `UndirectedGraph`
+//! is built on demand, by moralizing a `DirectedModel` (Koller & Friedman Definition 4.16) or by
+//! connecting the `Variable`s that share a `Factor` (Koller & Friedman Definition 4.4). It is the
+//! graph that the inference engines eliminate `Variable`s from and find cliques in.
+
+use factor::Factor;
+use util::{JeromeError, Result};
+use variable::Variable;
+use super::Model;
+use super::directed::DirectedModel;
+use super::undirected::UndirectedModel;
+
+use indexmap::IndexMap;
+
+use std::collections::HashSet;
+
+
+/// An undirected graph over `Variable`s
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UndirectedGraph {
+
+    /// The neighbors of each `Variable`, with the `Variable`s in the order they were added
+    neighbors: IndexMap<Variable, HashSet<Variable>>
+
+}
+
+
+impl UndirectedGraph {
+
+    /// Construct an empty `UndirectedGraph`
+    pub fn new() -> Self {
+        UndirectedGraph { neighbors: IndexMap::new() }
+    }
+
+    /// Construct the moral graph of a `DirectedModel`, in which each `Variable` is connected to
+    /// its parents, and the parents of each `Variable` are connected to one another.
+    pub fn moralize(model: &DirectedModel) -> Self {
+        let mut graph = UndirectedGraph::new();
+
+        for v in model.topological_order() {
+            let mut family = model.parents(&v);
+            family.push(v);
+            graph.add_clique(&family);
+        }
+
+        graph
+    }
+
+    /// Construct the interaction graph of a set of `Factor`s, in which the `Variable`s that
+    /// appear in a `Factor` together are connected.
+    pub fn from_factors(factors: &[Factor]) -> Self {
+        let mut graph = UndirectedGraph::new();
+
+        for f in factors {
+            graph.add_clique(&f.scope());
+        }
+
+        graph
+    }
+
+    /// Add a `Variable` to the graph, with no neighbors. Has no effect if it is already present.
+    pub fn add_variable(&mut self, v: Variable) {
+        self.neighbors.entry(v).or_default();
+    }
+
+    /// Add an edge between two `Variable`s, adding them to the graph if necessary. A `Variable`
+    /// is never its own neighbor.
+    pub fn add_edge(&mut self, a: Variable, b: Variable) {
+        self.add_variable(a);
+        self.add_variable(b);
+
+        if a != b {
+            self.neighbors[&a].insert(b);
+            self.neighbors[&b].insert(a);
+        }
+    }
+
+    /// Connect every pair of `Variable`s in `vars`
+    pub fn add_clique(&mut self, vars: &[Variable]) {
+        for (i, &a) in vars.iter().enumerate() {
+            self.add_variable(a);
+            for &b in vars.iter().skip(i + 1) {
+                self.add_edge(a, b);
+            }
+        }
+    }
+
+    /// Remove a `Variable` and its edges from the graph
+    ///
+    /// # Returns
+    /// the neighbors of the `Variable`, or `None` if it was not in the graph
+    pub fn remove_variable(&mut self, v: &Variable) -> Option<HashSet<Variable>> {
+        let neighbors = self.neighbors.shift_remove(v)?;
+        for n in neighbors.iter() {
+            self.neighbors[n].remove(v);
+        }
+
+        Some(neighbors)
+    }
+
+    /// Eliminate a `Variable` from the graph: remove it, and connect all of its neighbors
+    ///
+    /// # Returns
+    /// the neighbors of the `Variable`, or `None` if it was not in the graph
+    pub fn eliminate(&mut self, v: &Variable) -> Option<HashSet<Variable>> {
+        let neighbors = self.remove_variable(v)?;
+        for n in neighbors.iter() {
+            self.neighbors[n].extend(neighbors.iter().filter(|&m| m != n));
+        }
+
+        Some(neighbors)
+    }
+
+    /// Get the `Variable`s in the graph, in the order they were added
+    pub fn variables(&self) -> Vec<Variable> {
+        self.neighbors.keys().cloned().collect()
+    }
+
+    /// Get the number of `Variable`s in the graph
+    pub fn num_variables(&self) -> usize {
+        self.neighbors.len()
+    }
+
+    /// Check if a `Variable` is in the graph
+    pub fn contains(&self, v: &Variable) -> bool {
+        self.neighbors.contains_key(v)
+    }
+
+    /// Get the neighbors of a `Variable`. Empty if the `Variable` is not in the graph.
+    pub fn neighbors(&self, v: &Variable) -> HashSet<Variable> {
+        self.neighbors.get(v).cloned().unwrap_or_default()
+    }
+
+    /// Get the number of neighbors of a `Variable`
+    pub fn degree(&self, v: &Variable) -> usize {
+        self.neighbors.get(v).map_or(0, |n| n.len())
+    }
+
+    /// Check if there is an edge between two `Variable`s
+    pub fn is_adjacent(&self, a: &Variable, b: &Variable) -> bool {
+        self.neighbors.get(a).is_some_and(|n| n.contains(b))
+    }
+
+    /// Get the edges between the neighbors of `v` that are missing, and so would be added by
+    /// eliminating it
+    pub fn fill_edges(&self, v: &Variable) -> Vec<(Variable, Variable)> {
+        let mut neighbors: Vec<Variable> = self.neighbors(v).into_iter().collect();
+        neighbors.sort_by_key(|&n| usize::from(n));
+
+        let mut edges = Vec::new();
+        for (i, &a) in neighbors.iter().enumerate() {
+            for &b in neighbors.iter().skip(i + 1) {
+                if ! self.is_adjacent(&a, &b) {
+                    edges.push((a, b));
+                }
+            }
+        }
+
+        edges
+    }
+
+    /// Determine if `x` and `y` are separated by `z`, that is, whether every path between them
+    /// passes through `z` (Koller & Friedman Definition 4.8).
+    pub fn separated(&self, x: &[Variable], y: &[Variable], z: &[Variable]) -> bool {
+        let blocked: HashSet<Variable> = z.iter().cloned().collect();
+        let targets: HashSet<Variable> = y.iter().cloned().collect();
+
+        let mut visited: HashSet<Variable> = HashSet::new();
+        let mut frontier: Vec<Variable> = x.iter().filter(|v| ! blocked.contains(*v)).cloned().collect();
+
+        while let Some(v) = frontier.pop() {
+            if targets.contains(&v) {
+                return false;
+            }
+
+            if visited.insert(v) {
+                if let Some(neighbors) = self.neighbors.get(&v) {
+                    frontier.extend(neighbors.iter().filter(|n| ! blocked.contains(*n)));
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Find the maximal cliques of the graph, by the Bron-Kerbosch algorithm with pivoting.
+    ///
+    /// Each clique is in the order its `Variable`s were created, and the cliques are sorted.
+    pub fn max_cliques(&self) -> Vec<Vec<Variable>> {
+        let mut cliques = Vec::new();
+        self.bron_kerbosch(Vec::new(), self.variables().into_iter().collect(), HashSet::new(), &mut cliques);
+
+        for c in cliques.iter_mut() {
+            c.sort_by_key(|&v| usize::from(v));
+        }
+        cliques.sort_by_key(|c| c.iter().map(|&v| usize::from(v)).collect::<Vec<usize>>());
+
+        cliques
+    }
+
+    /// Triangulate the graph by eliminating its `Variable`s in `order`, which adds the fill edges
+    /// to the graph. The result is chordal.
+    ///
+    /// # Errors
+    /// `JeromeError::InvalidScope` if `order` does not contain every `Variable` exactly once
+    pub fn triangulate(&self, order: &[Variable]) -> Result<UndirectedGraph> {
+        self.check_order(order)?;
+
+        let mut triangulated = self.clone();
+        let mut remaining = self.clone();
+
+        for v in order {
+            for (a, b) in remaining.fill_edges(v) {
+                triangulated.add_edge(a, b);
+            }
+            remaining.eliminate(v);
+        }
+
+        Ok(triangulated)
+    }
+
+    /// Find the maximal cliques created by eliminating the `Variable`s of the graph in `order`,
+    /// each being a `Variable` and its neighbors at the time it is eliminated. These are the
+    /// maximal cliques of the triangulated graph, in the order they are created.
+    ///
+    /// # Errors
+    /// `JeromeError::InvalidScope` if `order` does not contain every `Variable` exactly once
+    pub fn induced_cliques(&self, order: &[Variable]) -> Result<Vec<Vec<Variable>>> {
+        self.check_order(order)?;
+
+        let mut remaining = self.clone();
+        let cliques: Vec<Vec<Variable>> = order.iter()
+            .map(|v| {
+                let mut clique: Vec<Variable> = remaining.eliminate(v).unwrap().into_iter().collect();
+                clique.insert(0, *v);
+                clique
+            })
+            .collect();
+
+        // only the maximal cliques are kept
+        let maximal: Vec<Vec<Variable>> = cliques.iter()
+            .enumerate()
+            .filter(|&(i, c)| {
+                ! cliques.iter().enumerate().any(|(j, other)| {
+                    i != j &&
+                    c.iter().all(|v| other.contains(v)) &&
+                    (other.len() > c.len() || j < i)
+                })
+            })
+            .map(|(_, c)| c.clone())
+            .collect();
+
+        Ok(maximal)
+    }
+
+    /// Determine if the graph is chordal, i.e. every cycle of length at least four has a chord.
+    ///
+    /// A graph is chordal exactly when the reverse of a maximum cardinality search is a perfect
+    /// elimination ordering, which adds no fill edges (Koller & Friedman Theorem 9.12).
+    pub fn is_chordal(&self) -> bool {
+        let mut marked: HashSet<Variable> = HashSet::new();
+        let mut order = Vec::with_capacity(self.num_variables());
+
+        for _ in 0..self.num_variables() {
+            let v = self.neighbors
+                        .iter()
+                        .filter(|&(v, _)| ! marked.contains(v))
+                        .max_by_key(|&(_, n)| n.iter().filter(|m| marked.contains(*m)).count())
+                        .map(|(&v, _)| v)
+                        .unwrap();
+
+            marked.insert(v);
+            order.push(v);
+        }
+
+        let mut remaining = self.clone();
+        order.iter().rev().all(|v| {
+            let perfect = remaining.fill_edges(v).is_empty();
+            remaining.eliminate(v);
+            perfect
+        })
+    }
+
+    /// Check that `order` contains every `Variable` of the graph exactly once
+    fn check_order(&self, order: &[Variable]) -> Result<()> {
+        let distinct: HashSet<&Variable> = order.iter().collect();
+        if distinct.len() != order.len() || distinct.len() != self.num_variables() || ! order.iter().all(|v| self.contains(v)) {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        Ok(())
+    }
+
+    /// Report every maximal clique that extends `clique` with `Variable`s from `candidates`, none
+    /// of which extends it with a `Variable` from `excluded`.
+    fn bron_kerbosch(
+        &self,
+        clique: Vec<Variable>,
+        mut candidates: HashSet<Variable>,
+        mut excluded: HashSet<Variable>,
+        cliques: &mut Vec<Vec<Variable>>
+    ) {
+        if candidates.is_empty() {
+            if excluded.is_empty() {
+                cliques.push(clique);
+            }
+            return;
+        }
+
+        // only the candidates that are not neighbors of the pivot need to be branched on
+        let pivot = *candidates.iter().chain(excluded.iter()).max_by_key(|v| self.degree(v)).unwrap();
+        let branches: Vec<Variable> = candidates.iter()
+                                                .filter(|v| ! self.is_adjacent(&pivot, v))
+                                                .cloned()
+                                                .collect();
+
+        for v in branches {
+            let neighbors = &self.neighbors[&v];
+
+            let mut extended = clique.clone();
+            extended.push(v);
+            self.bron_kerbosch(
+                extended,
+                candidates.intersection(neighbors).cloned().collect(),
+                excluded.intersection(neighbors).cloned().collect(),
+                cliques
+            );
+
+            candidates.remove(&v);
+            excluded.insert(v);
+        }
+    }
+}
+
+
+impl<'a> From<&'a DirectedModel> for UndirectedGraph {
+
+    /// The moral graph of the `DirectedModel`
+    fn from(model: &'a DirectedModel) -> Self {
+        UndirectedGraph::moralize(model)
+    }
+
+}
+
+
+impl<'a> From<&'a UndirectedModel> for UndirectedGraph {
+
+    /// The interaction graph of the `Factor`s of the `UndirectedModel`
+    fn from(model: &'a UndirectedModel) -> Self {
+        let mut graph = UndirectedGraph::from_factors(model.factors());
+
+        // a named variable need not appear in any factor
+        let mut variables: Vec<Variable> = model.variables().into_iter().collect();
+        variables.sort_by_key(|&v| usize::from(v));
+        for v in variables {
+            graph.add_variable(v);
+        }
+
+        graph
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use init::Initialization;
+    use model::directed::DirectedModelBuilder;
+    use std::convert::TryFrom;
+
+    #[test]
+    /// The student network and its moral graph, Koller & Friedman Figure 4.9
+    fn moralize() {
+        let c = Variable::binary();
+        let d = Variable::binary();
+        let i = Variable::binary();
+        let g = Variable::binary();
+        let s = Variable::binary();
+        let l = Variable::binary();
+        let j = Variable::binary();
+        let h = Variable::binary();
+
+        let model = DirectedModelBuilder::new()
+            .with_variable(&c, HashSet::new(), Initialization::Uniform)
+            .with_variable(&d, vec![c].into_iter().collect(), Initialization::Uniform)
+            .with_variable(&i, HashSet::new(), Initialization::Uniform)
+            .with_variable(&g, vec![d, i].into_iter().collect(), Initialization::Uniform)
+            .with_variable(&s, vec![i].into_iter().collect(), Initialization::Uniform)
+            .with_variable(&l, vec![g].into_iter().collect(), Initialization::Uniform)
+            .with_variable(&j, vec![l, s].into_iter().collect(), Initialization::Uniform)
+            .with_variable(&h, vec![g, j].into_iter().collect(), Initialization::Uniform)
+            .build()
+            .unwrap();
+
+        let graph = UndirectedGraph::from(&model);
+        assert_eq!(graph, UndirectedGraph::from(&UndirectedModel::try_from(&model).unwrap()));
+        assert_eq!(8, graph.num_variables());
+
+        // the parents of G, J and H are married
+        assert!(graph.is_adjacent(&d, &i));
+        assert!(graph.is_adjacent(&l, &s));
+        assert!(graph.is_adjacent(&g, &j));
+        assert!(! graph.is_adjacent(&c, &i));
+        assert_eq!(vec![d, i, l, j, h].into_iter().collect::<HashSet<Variable>>(), graph.neighbors(&g));
+
+        // the moral graph loses the marginal independence of D and I
+        assert!(! graph.separated(&[d], &[i], &[]));
+        assert!(graph.separated(&[c], &[h], &[d]));
+        assert!(graph.separated(&[d], &[s], &[g, i]));
+        assert!(! graph.separated(&[d], &[s], &[g]));
+        assert!(! graph.separated(&[c], &[s], &[]));
+
+        assert!(! graph.is_chordal());
+        assert!(graph.fill_edges(&c).is_empty());
+        assert_eq!(2, graph.fill_edges(&i).len());
+
+        let triangulated = graph.triangulate(&[c, d, i, h, g, s, l, j]).unwrap();
+        assert!(triangulated.is_chordal());
+        assert!(graph.triangulate(&[c, d]).is_err());
+
+        // the cliques of the triangulated graph are those induced by the elimination
+        let mut induced = graph.induced_cliques(&[c, d, i, h, g, s, l, j]).unwrap();
+        for clique in induced.iter_mut() {
+            clique.sort_by_key(|&v| usize::from(v));
+        }
+        induced.sort_by_key(|c| c.iter().map(|&v| usize::from(v)).collect::<Vec<usize>>());
+        assert_eq!(induced, triangulated.max_cliques());
+    }
+
+    #[test]
+    fn cliques() {
+        // a square A - B - C - D - A with a chord A - C
+        let a = Variable::binary();
+        let b = Variable::binary();
+        let c = Variable::binary();
+        let d = Variable::binary();
+        let e = Variable::binary();
+
+        let mut graph = UndirectedGraph::new();
+        graph.add_edge(a, b);
+        graph.add_edge(b, c);
+        graph.add_edge(c, d);
+        graph.add_edge(d, a);
+        assert!(! graph.is_chordal());
+        assert_eq!(4, graph.max_cliques().len());
+
+        graph.add_edge(a, c);
+        graph.add_variable(e);
+        assert!(graph.is_chordal());
+        assert_eq!(vec![vec![a, b, c], vec![a, c, d], vec![e]], graph.max_cliques());
+        assert!(graph.separated(&[b], &[d], &[a, c]));
+        assert!(graph.separated(&[a], &[e], &[]));
+
+        // eliminating B connects nothing new, and removes it from the graph
+        assert!(graph.fill_edges(&b).is_empty());
+        assert_eq!(Some(vec![a, c].into_iter().collect()), graph.eliminate(&b));
+        assert!(! graph.contains(&b));
+        assert_eq!(0, graph.degree(&b));
+        assert!(graph.eliminate(&b).is_none());
+    }
+}
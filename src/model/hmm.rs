@@ -0,0 +1,590 @@
+//! Defines a `HiddenMarkovModel`, a Dynamic Bayesian Network with a single discrete hidden state
+//! and a single discrete observation in each time slice.
+//!
+//! Inference over a `HiddenMarkovModel` uses the specialized forward, forward-backward and
+//! Viterbi algorithms (Koller & Friedman Section 15.2 and Box 17.A), which are linear in the
+//! length of the sequence of observations.
+
+use factor::Factor;
+use init::Initialization;
+use util::{JeromeError, Result};
+use variable::{all_assignments, Assignment, Variable};
+use super::Model;
+use super::directed::DirectedModel;
+use super::dynamic::{copy, DynamicModel, DynamicModelBuilder};
+
+use ndarray::prelude as nd;
+
+use std::collections::HashMap;
+
+
+/// The tolerance within which the CPDs of each time slice of an unrolled model must agree
+const TOLERANCE: f64 = 1e-6;
+
+
+/// A Hidden Markov Model, ```P(X_0) P(O_0 | X_0) prod_t P(X_t | X_(t-1)) P(O_t | X_t)```.
+///
+/// # Representation
+/// The model is defined by three CPDs over template `Variable`s: the initial distribution
+/// ```P(X)```, the transition ```P(X' | X)```, and the emission ```P(O | X)```, where ```X'```
+/// stands for the hidden state in the next time slice. A sequence of observations is given as the
+/// values of ```O``` in each time slice.
+#[derive(Clone, Debug)]
+pub struct HiddenMarkovModel {
+
+    /// The hidden state `Variable`, ```X```
+    hidden: Variable,
+
+    /// The hidden state `Variable` in the next time slice, ```X'```
+    next: Variable,
+
+    /// The observed `Variable`, ```O```
+    observed: Variable,
+
+    /// The CPD ```P(X)```
+    initial: Factor,
+
+    /// The CPD ```P(X' | X)```
+    transition: Factor,
+
+    /// The CPD ```P(O | X)```
+    emission: Factor,
+
+    /// ```P(X = i)```, indexed by ```i```
+    pi: Vec<f64>,
+
+    /// ```P(X' = j | X = i)```, indexed by ```[i][j]```
+    a: Vec<Vec<f64>>,
+
+    /// ```P(O = k | X = i)```, indexed by ```[i][k]```
+    b: Vec<Vec<f64>>
+
+}
+
+
+impl HiddenMarkovModel {
+
+    /// Construct a new `HiddenMarkovModel`.
+    ///
+    /// # Args
+    /// * `initial`: the CPD ```P(X)```
+    /// * `transition`: the CPD ```P(X' | X)```
+    /// * `emission`: the CPD ```P(O | X)```
+    ///
+    /// # Errors
+    /// * `JeromeError::NotACPD`, if any `Factor` is not a CPD
+    /// * `JeromeError::InvalidScope`, if the scopes do not share ```X```, or ```X'``` does not have
+    ///   the same cardinality as ```X```
+    pub fn new(initial: Factor, transition: Factor, emission: Factor) -> Result<Self> {
+        if ! initial.is_cpd() || ! transition.is_cpd() || ! emission.is_cpd() || initial.is_log() || transition.is_log() || emission.is_log() {
+            return Err(JeromeError::NotACPD);
+        }
+
+        let (si, st, se) = (initial.scope(), transition.scope(), emission.scope());
+        if si.len() != 1 || st.len() != 2 || se.len() != 2 {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        let (hidden, next, observed) = (si[0], st[1], se[1]);
+        if st[0] != hidden || se[0] != hidden || next.cardinality() != hidden.cardinality() || observed == next {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        let value = |f: &Factor, values: &[(Variable, usize)]| {
+            let mut a = Assignment::new();
+            for &(v, u) in values {
+                a.set(&v, u);
+            }
+            f.value(&a).unwrap()
+        };
+
+        let n = hidden.cardinality();
+        let pi = (0..n).map(|i| value(&initial, &[(hidden, i)])).collect();
+        let a = (0..n).map(|i| (0..n).map(|j| value(&transition, &[(hidden, i), (next, j)])).collect()).collect();
+        let b = (0..n).map(|i| {
+            (0..observed.cardinality()).map(|k| value(&emission, &[(hidden, i), (observed, k)])).collect()
+        }).collect();
+
+        Ok(HiddenMarkovModel { hidden, next, observed, initial, transition, emission, pi, a, b })
+    }
+
+    /// Get the hidden state `Variable`, ```X```
+    pub fn hidden(&self) -> Variable {
+        self.hidden
+    }
+
+    /// Get the hidden state `Variable` of the next time slice, ```X'```
+    pub fn next(&self) -> Variable {
+        self.next
+    }
+
+    /// Get the observed `Variable`, ```O```
+    pub fn observed(&self) -> Variable {
+        self.observed
+    }
+
+    /// Get the CPD ```P(X)```
+    pub fn initial(&self) -> &Factor {
+        &self.initial
+    }
+
+    /// Get the CPD ```P(X' | X)```
+    pub fn transition(&self) -> &Factor {
+        &self.transition
+    }
+
+    /// Get the CPD ```P(O | X)```
+    pub fn emission(&self) -> &Factor {
+        &self.emission
+    }
+
+    /// Compute the filtered distributions ```P(X_t | o_0, ..., o_t)``` by the forward algorithm.
+    ///
+    /// # Returns
+    /// a CPD over ```X``` for each time slice
+    ///
+    /// # Errors
+    /// * `JeromeError::NotEnoughData`, if there are no observations
+    /// * `JeromeError::General`, if an observation is out of the range of ```O```
+    /// * `JeromeError::DivideByZero`, if the observations have zero probability
+    pub fn filter(&self, observations: &[usize]) -> Result<Vec<Factor>> {
+        let (alpha, _) = self.forward(observations)?;
+        alpha.iter().map(|a| self.distribution(a.clone())).collect()
+    }
+
+    /// Compute the smoothed distributions ```P(X_t | o_0, ..., o_(T-1))``` by the forward-backward
+    /// algorithm.
+    ///
+    /// # Returns
+    /// a CPD over ```X``` for each time slice
+    ///
+    /// # Errors
+    /// see `HiddenMarkovModel::filter`
+    pub fn smooth(&self, observations: &[usize]) -> Result<Vec<Factor>> {
+        let (alpha, scale) = self.forward(observations)?;
+        let n = self.hidden.cardinality();
+
+        // the backward messages, scaled by the same constants as the forward messages
+        let mut beta = vec![vec![1.0; n]; observations.len()];
+        for t in (0..observations.len() - 1).rev() {
+            let o = observations[t + 1];
+            beta[t] = (0..n).map(|i| {
+                (0..n).map(|j| self.a[i][j] * self.b[j][o] * beta[t + 1][j]).sum::<f64>() / scale[t + 1]
+            }).collect();
+        }
+
+        alpha.iter()
+             .zip(beta.iter())
+             .map(|(a, b)| self.distribution(a.iter().zip(b.iter()).map(|(x, y)| x * y).collect()))
+             .collect()
+    }
+
+    /// Compute the most likely sequence of hidden states given the observations, by the Viterbi
+    /// algorithm. Ties are broken in favor of the lowest state.
+    ///
+    /// # Returns
+    /// the value of ```X``` in each time slice, and the natural logarithm of the joint probability
+    /// of the sequence and the observations
+    ///
+    /// # Errors
+    /// * `JeromeError::NotEnoughData`, if there are no observations
+    /// * `JeromeError::General`, if an observation is out of the range of ```O```
+    /// * `JeromeError::DivideByZero`, if the observations have zero probability
+    pub fn viterbi(&self, observations: &[usize]) -> Result<(Vec<usize>, f64)> {
+        self.check(observations)?;
+        let n = self.hidden.cardinality();
+
+        let mut delta: Vec<f64> = (0..n).map(|i| self.pi[i].ln() + self.b[i][observations[0]].ln()).collect();
+        let mut backpointers: Vec<Vec<usize>> = Vec::with_capacity(observations.len());
+
+        for &o in observations.iter().skip(1) {
+            let (next, pointers): (Vec<f64>, Vec<usize>) = (0..n).map(|j| {
+                let (best, score) = argmax((0..n).map(|i| delta[i] + self.a[i][j].ln()));
+                (score + self.b[j][o].ln(), best)
+            }).unzip();
+
+            delta = next;
+            backpointers.push(pointers);
+        }
+
+        let (last, score) = argmax(delta.into_iter());
+        // every sequence of hidden states is impossible, so there is no most likely one
+        if score == f64::NEG_INFINITY {
+            return Err(JeromeError::DivideByZero);
+        }
+
+        let mut path = vec![last];
+        for pointers in backpointers.iter().rev() {
+            let previous = pointers[*path.last().unwrap()];
+            path.push(previous);
+        }
+        path.reverse();
+
+        Ok((path, score))
+    }
+
+    /// Compute the probability of a sequence of observations, ```P(o_0, ..., o_(T-1))```
+    ///
+    /// # Errors
+    /// * `JeromeError::NotEnoughData`, if there are no observations
+    /// * `JeromeError::General`, if an observation is out of the range of ```O```
+    pub fn likelihood(&self, observations: &[usize]) -> Result<f64> {
+        self.log_likelihood(observations).map(f64::exp)
+    }
+
+    /// Compute the natural logarithm of the probability of a sequence of observations. Unlike
+    /// `likelihood`, this does not underflow for long sequences.
+    ///
+    /// # Errors
+    /// see `HiddenMarkovModel::likelihood`
+    pub fn log_likelihood(&self, observations: &[usize]) -> Result<f64> {
+        match self.forward(observations) {
+            Ok((_, scale)) => Ok(scale.iter().map(|c| c.ln()).sum()),
+            Err(JeromeError::DivideByZero) => Ok(f64::NEG_INFINITY),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Convert the model to a `DynamicModel`, with state `Variable`s named "Hidden" and
+    /// "Observed".
+    pub fn to_dynamic(&self) -> DynamicModel {
+        let observed_next = copy(&self.observed);
+        let map: HashMap<Variable, Variable> = vec![(self.hidden, self.next), (self.observed, observed_next)].into_iter().collect();
+        let emission_next = self.emission.relabel(&map).expect("relabel encountered unexpected error");
+
+        DynamicModelBuilder::new()
+            .with_state(&self.hidden, &self.next, "Hidden")
+            .with_state(&self.observed, &observed_next, "Observed")
+            .with_initial(&self.hidden, Default::default(), Initialization::Table(self.initial.clone()))
+            .with_initial(&self.observed, vec![self.hidden].into_iter().collect(), Initialization::Table(self.emission.clone()))
+            .with_transition(&self.next, vec![self.hidden].into_iter().collect(), Initialization::Table(self.transition.clone()))
+            .with_transition(&observed_next, vec![self.next].into_iter().collect(), Initialization::Table(emission_next))
+            .build()
+            .expect("conversion encountered unexpected error")
+    }
+
+    /// Unroll the model into a `DirectedModel` over `slices` time slices, with `Variable`s named
+    /// ```"Hidden_{t}"``` and ```"Observed_{t}"```; see `DynamicModel::unroll`.
+    ///
+    /// # Errors
+    /// `JeromeError::General` if `slices` is zero
+    pub fn unroll(&self, slices: usize) -> Result<DirectedModel> {
+        self.to_dynamic().unroll(slices)
+    }
+
+    /// Recover a `HiddenMarkovModel` from an unrolled `DirectedModel`: a chain of hidden
+    /// `Variable`s ```X_0 -> X_1 -> ...```, each with a single observed child ```O_t```. The
+    /// template `Variable`s of the result are ```X_0```, ```X_1``` and ```O_0```.
+    ///
+    /// # Errors
+    /// `JeromeError::General`, if the model does not have this structure, has fewer than two time
+    /// slices, or the CPDs differ between time slices
+    pub fn from_directed(model: &DirectedModel) -> Result<Self> {
+        let not_an_hmm = || JeromeError::General(String::from("The model is not an unrolled hidden Markov model"));
+
+        let roots = model.roots();
+        if roots.len() != 1 || model.variables().iter().any(|v| v.is_continuous()) {
+            return Err(not_an_hmm());
+        }
+
+        // walk down the chain. Each hidden variable has an observation, which is a leaf, and every
+        // hidden variable but the last has a successor, which is not.
+        let mut hidden = vec![roots[0]];
+        let mut observed = Vec::new();
+        loop {
+            let current = *hidden.last().unwrap();
+            let (leaves, successors): (Vec<Variable>, Vec<Variable>) = model.children(&current)
+                                                                            .into_iter()
+                                                                            .partition(|c| model.children(c).is_empty());
+
+            if leaves.len() != 1 || successors.len() > 1 || leaves.iter().chain(successors.iter()).any(|c| model.parents(c) != vec![current]) {
+                return Err(not_an_hmm());
+            }
+
+            observed.push(leaves[0]);
+            match successors.first() {
+                Some(&next) => hidden.push(next),
+                None => break
+            }
+        }
+
+        if hidden.len() < 2 || hidden.len() + observed.len() != model.num_variables() {
+            return Err(not_an_hmm());
+        }
+
+        let hmm = HiddenMarkovModel::new(
+            model.cpd(&hidden[0]).unwrap().clone(),
+            model.cpd(&hidden[1]).unwrap().clone(),
+            model.cpd(&observed[0]).unwrap().clone()
+        )?;
+
+        // every time slice must share the same CPDs
+        for t in 1..hidden.len() {
+            let emission: HashMap<Variable, Variable> = vec![(hidden[t], hidden[0]), (observed[t], observed[0])].into_iter().collect();
+            if ! agree(model.cpd(&observed[t]).unwrap(), &hmm.emission, &emission) {
+                return Err(not_an_hmm());
+            }
+
+            let transition: HashMap<Variable, Variable> = vec![(hidden[t - 1], hidden[0]), (hidden[t], hidden[1])].into_iter().collect();
+            if ! agree(model.cpd(&hidden[t]).unwrap(), &hmm.transition, &transition) {
+                return Err(not_an_hmm());
+            }
+        }
+
+        Ok(hmm)
+    }
+
+    /// Verify that there is at least one observation, and every observation is in range
+    fn check(&self, observations: &[usize]) -> Result<()> {
+        if observations.is_empty() {
+            return Err(JeromeError::NotEnoughData);
+        }
+
+        match observations.iter().find(|&&o| o >= self.observed.cardinality()) {
+            Some(o) => Err(
+                JeromeError::General(
+                    format!(
+                        "Error - cannot assign variable with cardinality {} a value of {}",
+                        self.observed.cardinality(),
+                        o
+                    )
+                )
+            ),
+            None => Ok(())
+        }
+    }
+
+    /// Run the forward algorithm, normalizing the message of each time slice to avoid underflow.
+    ///
+    /// # Returns
+    /// the normalized messages ```P(X_t | o_0, ..., o_t)```, and the normalizing constants
+    /// ```P(o_t | o_0, ..., o_(t-1))```
+    fn forward(&self, observations: &[usize]) -> Result<(Vec<Vec<f64>>, Vec<f64>)> {
+        self.check(observations)?;
+        let n = self.hidden.cardinality();
+
+        let mut alpha: Vec<Vec<f64>> = Vec::with_capacity(observations.len());
+        let mut scale = Vec::with_capacity(observations.len());
+
+        for (t, &o) in observations.iter().enumerate() {
+            let message: Vec<f64> = (0..n).map(|j| {
+                let prior = if t == 0 {
+                    self.pi[j]
+                } else {
+                    (0..n).map(|i| alpha[t - 1][i] * self.a[i][j]).sum()
+                };
+                prior * self.b[j][o]
+            }).collect();
+
+            let c: f64 = message.iter().sum();
+            if c <= 0.0 {
+                return Err(JeromeError::DivideByZero);
+            }
+
+            alpha.push(message.into_iter().map(|m| m / c).collect());
+            scale.push(c);
+        }
+
+        Ok((alpha, scale))
+    }
+
+    /// Normalize a message into a CPD over ```X```
+    fn distribution(&self, message: Vec<f64>) -> Result<Factor> {
+        let total: f64 = message.iter().sum();
+        if total <= 0.0 {
+            return Err(JeromeError::DivideByZero);
+        }
+
+        let table = message.into_iter().map(|m| m / total).collect::<Vec<f64>>();
+        Factor::cpd(self.hidden, Vec::new(), nd::Array1::from(table).into_dyn())
+    }
+}
+
+
+/// Find the index and value of the first maximum
+fn argmax<I: Iterator<Item = f64>>(values: I) -> (usize, f64) {
+    values.enumerate().fold((0, f64::NEG_INFINITY), |best, (i, v)| if v > best.1 { (i, v) } else { best })
+}
+
+
+/// Check if a CPD, relabeled by `map`, has the same values as `other`
+fn agree(cpd: &Factor, other: &Factor, map: &HashMap<Variable, Variable>) -> bool {
+    match cpd.relabel(map) {
+        Ok(relabeled) => {
+            let scope = other.scope();
+            relabeled.scope() == scope && all_assignments(&scope).all(|a| {
+                (relabeled.value(&a).unwrap() - other.value(&a).unwrap()).abs() < TOLERANCE
+            })
+        },
+        Err(_) => false
+    }
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+
+    use super::*;
+    use inference::{ConditionalInferenceEngine, EvidenceProbabilityEngine, VariableEliminationEngine};
+
+    /// The umbrella world: it rains with probability 0.7 if it rained the day before and 0.3
+    /// otherwise, and the director carries an umbrella with probability 0.9 if it rains and 0.2
+    /// otherwise.
+    pub(crate) fn umbrella() -> HiddenMarkovModel {
+        let r = Variable::binary();
+        let r_next = Variable::binary();
+        let u = Variable::binary();
+
+        HiddenMarkovModel::new(
+            Factor::cpd(r, vec![], array![0.5, 0.5].into_dyn()).unwrap(),
+            Factor::cpd(r_next, vec![r], array![[0.7, 0.3], [0.3, 0.7]].into_dyn()).unwrap(),
+            Factor::cpd(u, vec![r], array![[0.8, 0.2], [0.1, 0.9]].into_dyn()).unwrap()
+        ).unwrap()
+    }
+
+    /// The value of `f` at ```v = u```, for a `Factor` over the single `Variable` ```v```
+    pub(crate) fn value(f: &Factor, v: Variable, u: usize) -> f64 {
+        let mut a = Assignment::new();
+        a.set(&v, u);
+        f.value(&a).unwrap()
+    }
+
+    #[test]
+    /// The filtered, smoothed and likelihood computations agree with variable elimination over the
+    /// unrolled model
+    fn forward_backward() {
+        let hmm = umbrella();
+        let observations = [1, 1, 0, 1];
+
+        let unrolled = hmm.unroll(observations.len()).unwrap();
+        let hidden: Vec<Variable> = (0..4).map(|t| *unrolled.lookup_variable(&format!("Hidden_{}", t)).unwrap()).collect();
+        let observed: Vec<Variable> = (0..4).map(|t| *unrolled.lookup_variable(&format!("Observed_{}", t)).unwrap()).collect();
+
+        let filtered = hmm.filter(&observations).unwrap();
+        let smoothed = hmm.smooth(&observations).unwrap();
+        assert_eq!(4, filtered.len());
+        assert_eq!(4, smoothed.len());
+
+        let mut evidence = Assignment::new();
+        for t in 0..4 {
+            evidence.set(&observed[t], observations[t]);
+
+            let mut engine = VariableEliminationEngine::for_directed(&unrolled, &evidence).unwrap();
+            let f = engine.infer(&vec![hidden[t]].into_iter().collect()).unwrap();
+            assert!((value(&filtered[t], hmm.hidden(), 1) - value(&f, hidden[t], 1)).abs() < 1e-9);
+        }
+
+        for t in 0..4 {
+            let mut engine = VariableEliminationEngine::for_directed(&unrolled, &evidence).unwrap();
+            let f = engine.infer(&vec![hidden[t]].into_iter().collect()).unwrap();
+            assert!((value(&smoothed[t], hmm.hidden(), 1) - value(&f, hidden[t], 1)).abs() < 1e-9);
+        }
+
+        // the last time slice has no future evidence
+        assert!((value(&smoothed[3], hmm.hidden(), 1) - value(&filtered[3], hmm.hidden(), 1)).abs() < 1e-12);
+
+        let mut engine = VariableEliminationEngine::for_directed(&unrolled, &evidence).unwrap();
+        let p = engine.probability_of_evidence().unwrap();
+        assert!((hmm.likelihood(&observations).unwrap() - p).abs() < 1e-12);
+        assert!((hmm.log_likelihood(&observations).unwrap() - p.ln()).abs() < 1e-9);
+
+        assert!(matches!(hmm.filter(&[]), Err(JeromeError::NotEnoughData)));
+        assert!(hmm.smooth(&[0, 2]).is_err());
+    }
+
+    #[test]
+    /// The Viterbi path is the best of every possible path
+    fn viterbi() {
+        let hmm = umbrella();
+        let observations = [1, 1, 0, 1, 1];
+        let (path, score) = hmm.viterbi(&observations).unwrap();
+
+        let log_joint = |xs: &[usize]| -> f64 {
+            let mut p = hmm.pi[xs[0]].ln() + hmm.b[xs[0]][observations[0]].ln();
+            for t in 1..xs.len() {
+                p += hmm.a[xs[t - 1]][xs[t]].ln() + hmm.b[xs[t]][observations[t]].ln();
+            }
+            p
+        };
+
+        let best = (0..1 << observations.len())
+            .map(|mask| (0..observations.len()).map(|t| (mask >> t) & 1).collect::<Vec<usize>>())
+            .map(|xs| log_joint(&xs))
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        assert_eq!(vec![1, 1, 0, 1, 1], path);
+        assert!((score - best).abs() < 1e-12);
+        assert!((score - log_joint(&path)).abs() < 1e-12);
+
+        // the hidden state never changes, and is revealed by each observation
+        let x = Variable::binary();
+        let x_next = Variable::binary();
+        let o = Variable::binary();
+        let hmm = HiddenMarkovModel::new(
+            Factor::cpd(x, vec![], array![0.5, 0.5].into_dyn()).unwrap(),
+            Factor::cpd(x_next, vec![x], array![[1.0, 0.0], [0.0, 1.0]].into_dyn()).unwrap(),
+            Factor::cpd(o, vec![x], array![[1.0, 0.0], [0.0, 1.0]].into_dyn()).unwrap()
+        ).unwrap();
+
+        assert_eq!(vec![1, 1], hmm.viterbi(&[1, 1]).unwrap().0);
+        assert!(matches!(hmm.viterbi(&[0, 1]), Err(JeromeError::DivideByZero)));
+    }
+
+    #[test]
+    fn long_sequence() {
+        let hmm = umbrella();
+        let observations: Vec<usize> = (0..5000).map(|t| (t / 7) % 2).collect();
+
+        // the likelihood underflows, but the log likelihood and filtered distributions do not
+        assert_eq!(0.0, hmm.likelihood(&observations).unwrap());
+        assert!(hmm.log_likelihood(&observations).unwrap().is_finite());
+        assert!(hmm.smooth(&observations).unwrap().iter().all(|f| value(f, hmm.hidden(), 0).is_finite()));
+    }
+
+    #[test]
+    fn from_directed() {
+        let hmm = umbrella();
+        let unrolled = hmm.unroll(3).unwrap();
+
+        let recovered = HiddenMarkovModel::from_directed(&unrolled).unwrap();
+        assert_eq!(*unrolled.lookup_variable("Hidden_0").unwrap(), recovered.hidden());
+        assert_eq!(*unrolled.lookup_variable("Hidden_1").unwrap(), recovered.next());
+        assert_eq!(*unrolled.lookup_variable("Observed_0").unwrap(), recovered.observed());
+        assert_eq!(hmm.a, recovered.a);
+        assert_eq!(hmm.b, recovered.b);
+        assert_eq!(hmm.pi, recovered.pi);
+
+        // a single time slice does not define the transition
+        assert!(HiddenMarkovModel::from_directed(&hmm.unroll(1).unwrap()).is_err());
+
+        // a 2-TBN with two hidden chains is not an HMM
+        let (x, x_next, y, y_next) = (Variable::binary(), Variable::binary(), Variable::binary(), Variable::binary());
+        let model = DynamicModelBuilder::new()
+                        .with_state(&x, &x_next, "X")
+                        .with_state(&y, &y_next, "Y")
+                        .with_initial(&x, Default::default(), Initialization::Uniform)
+                        .with_initial(&y, Default::default(), Initialization::Uniform)
+                        .with_transition(&x_next, vec![x].into_iter().collect(), Initialization::Uniform)
+                        .with_transition(&y_next, vec![y].into_iter().collect(), Initialization::Uniform)
+                        .build()
+                        .unwrap();
+        assert!(HiddenMarkovModel::from_directed(&model.unroll(3).unwrap()).is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        let x = Variable::binary();
+        let x_next = Variable::discrete(3);
+        let o = Variable::binary();
+
+        let initial = Factor::cpd(x, vec![], array![0.5, 0.5].into_dyn()).unwrap();
+        let emission = Factor::cpd(o, vec![x], array![[0.8, 0.2], [0.1, 0.9]].into_dyn()).unwrap();
+
+        // the next hidden state must have the same cardinality
+        let transition = Factor::cpd(x_next, vec![x], array![[0.5, 0.25, 0.25], [0.25, 0.5, 0.25]].into_dyn()).unwrap();
+        assert!(matches!(HiddenMarkovModel::new(initial.clone(), transition, emission.clone()), Err(JeromeError::InvalidScope)));
+
+        let transition = Factor::new(vec![x, o], array![[1.0, 1.0], [1.0, 1.0]].into_dyn()).unwrap();
+        assert!(matches!(HiddenMarkovModel::new(initial, transition, emission), Err(JeromeError::NotACPD)));
+    }
+}
@@ -0,0 +1,304 @@
+//! Queries on the independencies encoded by the structure of a `DirectedModel`
+//!
+//! The graph of a `DirectedModel` asserts a set of conditional independencies, I(G), which hold
+//! in every distribution that factorizes over it. These are determined by d-separation, which is
+//! computed with the reachable (Bayes-ball) algorithm of Koller & Friedman Algorithm 3.1.
+
+use util::{JeromeError, Result};
+use variable::Variable;
+use super::directed::DirectedModel;
+
+use std::collections::HashSet;
+
+
+/// A conditional independence assertion, ```(X ⊥ Y | Z)```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Independence {
+
+    /// The `Variable`s on one side of the assertion
+    x: HashSet<Variable>,
+
+    /// The `Variable`s on the other side of the assertion
+    y: HashSet<Variable>,
+
+    /// The `Variable`s that are observed
+    z: HashSet<Variable>
+
+}
+
+
+impl Independence {
+
+    /// Construct a new `Independence` assertion, ```(X ⊥ Y | Z)```.
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if `x` or `y` is empty, or the sets are not disjoint
+    pub fn new(x: HashSet<Variable>, y: HashSet<Variable>, z: HashSet<Variable>) -> Result<Self> {
+        if x.is_empty() || y.is_empty() || ! x.is_disjoint(&y) || ! x.is_disjoint(&z) || ! y.is_disjoint(&z) {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        Ok(Independence { x, y, z })
+    }
+
+    /// Get the `Variable`s on one side of the assertion
+    pub fn x(&self) -> &HashSet<Variable> {
+        &self.x
+    }
+
+    /// Get the `Variable`s on the other side of the assertion
+    pub fn y(&self) -> &HashSet<Variable> {
+        &self.y
+    }
+
+    /// Get the observed `Variable`s
+    pub fn z(&self) -> &HashSet<Variable> {
+        &self.z
+    }
+}
+
+
+/// The direction in which the ball passes through a `Variable`
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+enum Direction {
+    /// Arriving from a child
+    Up,
+
+    /// Arriving from a parent
+    Down
+}
+
+
+impl DirectedModel {
+
+    /// Find every `Variable` reachable from `x` via an active trail given the observations `z`.
+    /// The result includes the unobserved `Variable`s of `x` themselves.
+    ///
+    /// Implements Koller & Friedman Algorithm 3.1.
+    pub fn reachable(&self, x: &[Variable], z: &[Variable]) -> HashSet<Variable> {
+        let observed: HashSet<Variable> = z.iter().cloned().collect();
+
+        // Phase 1: the observations and their ancestors, which activate v-structures
+        let mut activating = observed.clone();
+        for v in z {
+            activating.extend(self.ancestors(v));
+        }
+
+        // Phase 2: traverse the active trails
+        let mut frontier: Vec<(Variable, Direction)> = x.iter().map(|&v| (v, Direction::Up)).collect();
+        let mut visited = HashSet::new();
+        let mut reachable = HashSet::new();
+
+        while let Some((v, direction)) = frontier.pop() {
+            if ! visited.insert((v, direction)) {
+                continue;
+            }
+
+            let is_observed = observed.contains(&v);
+            if ! is_observed {
+                reachable.insert(v);
+            }
+
+            match direction {
+                Direction::Up if ! is_observed => {
+                    frontier.extend(self.parents(&v).into_iter().map(|p| (p, Direction::Up)));
+                    frontier.extend(self.children(&v).into_iter().map(|c| (c, Direction::Down)));
+                },
+                Direction::Up => { },
+                Direction::Down => {
+                    // a trail down through an unobserved variable continues to its children, and
+                    // a v-structure is active if the variable or one of its descendants is observed
+                    if ! is_observed {
+                        frontier.extend(self.children(&v).into_iter().map(|c| (c, Direction::Down)));
+                    }
+
+                    if activating.contains(&v) {
+                        frontier.extend(self.parents(&v).into_iter().map(|p| (p, Direction::Up)));
+                    }
+                }
+            }
+        }
+
+        reachable
+    }
+
+    /// Determine if `x` and `y` are d-separated given `z`, that is, whether there is no active
+    /// trail between them.
+    pub fn d_separated(&self, x: &[Variable], y: &[Variable], z: &[Variable]) -> bool {
+        let reachable = self.reachable(x, z);
+        ! y.iter().any(|v| reachable.contains(v))
+    }
+
+    /// Determine if an `Independence` assertion is in I(G)
+    pub fn asserts(&self, independence: &Independence) -> bool {
+        let x: Vec<Variable> = independence.x.iter().cloned().collect();
+        let y: Vec<Variable> = independence.y.iter().cloned().collect();
+        let z: Vec<Variable> = independence.z.iter().cloned().collect();
+
+        self.d_separated(&x, &y, &z)
+    }
+
+    /// Get the local independencies of the model: each `Variable` is independent of its
+    /// non-descendants given its parents. A `Variable` whose non-descendants are all parents
+    /// asserts nothing, and is skipped.
+    pub fn local_independencies(&self) -> Vec<Independence> {
+        let order = self.topological_order();
+
+        order.iter().filter_map(|v| {
+            let parents: HashSet<Variable> = self.parents(v).into_iter().collect();
+            let descendants = self.descendants(v);
+            let others: HashSet<Variable> = order.iter()
+                                                 .filter(|&u| u != v && ! parents.contains(u) && ! descendants.contains(u))
+                                                 .cloned()
+                                                 .collect();
+
+            Independence::new(vec![*v].into_iter().collect(), others, parents).ok()
+        }).collect()
+    }
+
+    /// Get the global independencies of the model, I(G), as assertions between pairs of
+    /// `Variable`s. Since ```(X ⊥ Y | Z)``` holds for sets exactly when it holds for every pair
+    /// drawn from `X` and `Y`, this describes I(G) completely.
+    ///
+    /// Every subset of the other `Variable`s is considered as the observations, so this is only
+    /// practical for small models.
+    pub fn global_independencies(&self) -> Vec<Independence> {
+        let order = self.topological_order();
+        let mut independencies = Vec::new();
+
+        for (i, &x) in order.iter().enumerate() {
+            for &y in &order[i + 1..] {
+                let rest: Vec<Variable> = order.iter().filter(|&&v| v != x && v != y).cloned().collect();
+
+                for mask in 0..(1usize << rest.len()) {
+                    let z: Vec<Variable> = rest.iter()
+                                               .enumerate()
+                                               .filter(|&(j, _)| mask & (1 << j) != 0)
+                                               .map(|(_, &v)| v)
+                                               .collect();
+
+                    if self.d_separated(&[x], &[y], &z) {
+                        independencies.push(Independence {
+                            x: vec![x].into_iter().collect(),
+                            y: vec![y].into_iter().collect(),
+                            z: z.into_iter().collect()
+                        });
+                    }
+                }
+            }
+        }
+
+        independencies
+    }
+
+    /// Determine if this model is an I-map for the independencies of `other`, that is, whether
+    /// ```I(self) ⊆ I(other)```. Both models must be over the same `Variable`s.
+    ///
+    /// It is sufficient to check the local independencies of this model, as they imply the rest
+    /// of I(G) (Koller & Friedman Theorem 3.1 and 3.2).
+    pub fn is_imap_of(&self, other: &DirectedModel) -> bool {
+        let variables = self.topological_order();
+        let others: HashSet<Variable> = other.topological_order().into_iter().collect();
+
+        variables.len() == others.len()
+            && variables.iter().all(|v| others.contains(v))
+            && self.local_independencies().iter().all(|i| other.asserts(i))
+    }
+
+    /// Determine if two models assert the same independencies
+    pub fn is_i_equivalent(&self, other: &DirectedModel) -> bool {
+        self.is_imap_of(other) && other.is_imap_of(self)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use init::Initialization;
+    use model::directed::DirectedModelBuilder;
+
+    /// Build a model of binary variables from a list of (variable, parents)
+    fn build(structure: &[(Variable, Vec<Variable>)]) -> DirectedModel {
+        structure.iter()
+                 .fold(DirectedModelBuilder::new(), |b, &(v, ref parents)| {
+                     b.with_variable(&v, parents.iter().cloned().collect(), Initialization::Uniform)
+                 })
+                 .build()
+                 .unwrap()
+    }
+
+    fn set(vs: &[Variable]) -> HashSet<Variable> {
+        vs.iter().cloned().collect()
+    }
+
+    #[test]
+    /// The student network, Koller & Friedman Figure 3.4
+    fn d_separation() {
+        let d = Variable::binary();
+        let i = Variable::binary();
+        let g = Variable::binary();
+        let s = Variable::binary();
+        let l = Variable::binary();
+
+        let model = build(&[(d, vec![]), (i, vec![]), (g, vec![d, i]), (s, vec![i]), (l, vec![g])]);
+
+        // the v-structure D -> G <- I is blocked until G or its descendant L is observed
+        assert!(model.d_separated(&[d], &[i], &[]));
+        assert!(! model.d_separated(&[d], &[i], &[g]));
+        assert!(! model.d_separated(&[d], &[i], &[l]));
+        assert!(! model.d_separated(&[d], &[s], &[l]));
+
+        // the chain I -> G -> L and the fork G <- I -> S are blocked by observing the middle
+        assert!(! model.d_separated(&[i], &[l], &[]));
+        assert!(model.d_separated(&[i], &[l], &[g]));
+        assert!(model.d_separated(&[g], &[s], &[i]));
+        assert!(model.d_separated(&[l], &[s, d], &[g, i]));
+
+        assert_eq!(set(&[d, g, l]), model.reachable(&[d], &[]));
+        assert_eq!(set(&[d, i, s]), model.reachable(&[d], &[g]));
+
+        let local = model.local_independencies();
+        assert_eq!(5, local.len());
+        assert!(local.contains(&Independence::new(set(&[d]), set(&[i, s]), set(&[])).unwrap()));
+        assert!(local.contains(&Independence::new(set(&[l]), set(&[d, i, s]), set(&[g])).unwrap()));
+        assert!(local.iter().all(|i| model.asserts(i)));
+
+        let global = model.global_independencies();
+        assert!(global.contains(&Independence::new(set(&[d]), set(&[s]), set(&[])).unwrap()));
+        assert!(global.contains(&Independence::new(set(&[d]), set(&[s]), set(&[g, i])).unwrap()));
+        assert!(! global.contains(&Independence::new(set(&[d]), set(&[s]), set(&[g])).unwrap()));
+        assert!(global.iter().all(|i| model.asserts(i)));
+
+        assert!(Independence::new(set(&[d]), set(&[d]), set(&[])).is_err());
+        assert!(Independence::new(set(&[]), set(&[d]), set(&[])).is_err());
+    }
+
+    #[test]
+    fn imap() {
+        let a = Variable::binary();
+        let b = Variable::binary();
+        let c = Variable::binary();
+
+        let chain = build(&[(a, vec![]), (b, vec![a]), (c, vec![b])]);
+        let fork = build(&[(b, vec![]), (a, vec![b]), (c, vec![b])]);
+        let collider = build(&[(a, vec![]), (c, vec![]), (b, vec![a, c])]);
+        let complete = build(&[(a, vec![]), (b, vec![a]), (c, vec![a, b])]);
+
+        assert!(chain.is_i_equivalent(&fork));
+        assert!(! chain.is_i_equivalent(&collider));
+
+        // a complete graph asserts nothing, so it is an I-map of every graph
+        assert!(complete.local_independencies().is_empty());
+        assert!(complete.is_imap_of(&chain));
+        assert!(complete.is_imap_of(&collider));
+        assert!(! chain.is_imap_of(&complete));
+        assert!(! collider.is_imap_of(&chain));
+
+        // models over different variables are never I-maps of one another
+        let d = Variable::binary();
+        let other = build(&[(a, vec![]), (b, vec![a]), (d, vec![b])]);
+        assert!(! chain.is_imap_of(&other));
+    }
+}
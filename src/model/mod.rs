@@ -1,7 +1,7 @@
 //! Defines a `Model`, which is a Bayesian (directed) or Markovian (undirected) graphical model
 //! representing the factorization of a probability distribution P.
 
-use variable::{Assignment, Variable};
+use variable::{Assignment, Labels, Variable};
 use util::Result;
 
 use std::collections::HashSet;
@@ -10,6 +10,7 @@ use std::collections::HashSet;
 pub trait Model{
 
     /// The concrete type of `PGM` that is returned by the `condition` operation. 
+    #[allow(non_camel_case_types)]
     type Model_Type;
 
 
@@ -29,6 +30,10 @@ pub trait Model{
     fn num_variables(&self) -> usize;
 
 
+    /// Get the state labels of the `Variable`s in the `Model`
+    fn labels(&self) -> &Labels;
+
+
     /// Condition the `Model` given the evidence.
     ///
     /// # Args
@@ -50,7 +55,23 @@ pub trait Model{
     /// # Returns
     /// the probability of the `Assignment` given the `Model`
     fn probability(&self, assignment: &Assignment) -> Result<f64>;
+
+
+    /// Determine the log probability of a full `Assignment` to the `Variable`s in the `Model`.
+    ///
+    /// Unlike `probability`, this does not underflow for large models.
+    ///
+    /// # Args
+    /// * `assignment`: a full `Assignment` to the `Model`
+    ///
+    /// # Returns
+    /// the natural logarithm of the probability of the `Assignment` given the `Model`
+    fn log_probability(&self, assignment: &Assignment) -> Result<f64>;
 }
 
 pub mod directed;
+pub mod dynamic;
+pub mod graph;
+pub mod hmm;
+pub mod independence;
 pub mod undirected;
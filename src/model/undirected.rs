@@ -2,15 +2,18 @@
 //! probability distribution P
 
 use factor::Factor;
+use inference::{log_partition, partition, EliminationOrdering, MinFill};
 use init::Initialization;
 use util::{Result, JeromeError};
-use variable::{all_assignments, Assignment, Variable};
+use variable::{Assignment, Labels, Variable};
 use super::Model;
 use super::directed::DirectedModel;
 
 use bidir_map::BidirMap;
 
 use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::sync::OnceLock;
 
 /// Represents a Markovian Network - an Undirected Probabilistic Graphical Model.
 ///
@@ -19,6 +22,8 @@ use std::collections::HashSet;
 /// explicitly define the graph structure. Instead, it uses a logical view of a Markovian Network
 /// as a collection of `Factor`s to present the semantics of operations over a Markovian Network. 
 /// Although there are connections between these `Factor`s, they are not explicitly defined. 
+/// When the structure is needed, it can be built as an `UndirectedGraph`.
+#[derive(Clone)]
 pub struct UndirectedModel {
 
     /// The `Factor`s that comprise the `UndirectedModel`
@@ -27,8 +32,13 @@ pub struct UndirectedModel {
     /// The `Variable`s that comprise the `UndirectedModel` and their names.
     variables: BidirMap<Variable, String>,
 
-    /// The partition function of the Gibbs Distribution.
-    partition: f64
+    /// The state labels of the `Variable`s
+    labels: Labels,
+
+    /// The partition function of the Gibbs Distribution, in the same space as the `Factor`s: if
+    /// they are in log-space, this is the log of the partition function. Computed by variable
+    /// elimination the first time it is needed.
+    partition: OnceLock<f64>
 
 }
 
@@ -36,8 +46,48 @@ pub struct UndirectedModel {
 impl UndirectedModel {
     
     /// Get the partition function of the `Factor`.
+    ///
+    /// The partition function is computed by variable elimination (using the min-fill heuristic)
+    /// on the first call, and cached thereafter. The computation is carried out in the same space
+    /// as the `Factor`s of the model.
     pub fn partition(&self) -> f64 {
-        self.partition
+        if self.is_log() {
+            self.log_partition().exp()
+        } else {
+            *self.partition.get_or_init(|| partition(self.factors.clone(), &MinFill.order(self)))
+        }
+    }
+
+
+    /// Get the log of the partition function of the `Factor`. If the `Factor`s of the model are in
+    /// log-space, this does not overflow or underflow for large models.
+    pub fn log_partition(&self) -> f64 {
+        if self.is_log() {
+            *self.partition.get_or_init(|| log_partition(self.factors.clone(), &MinFill.order(self)))
+        } else {
+            self.partition().ln()
+        }
+    }
+
+
+    /// Check if the `Factor`s of the `UndirectedModel` are in log-space
+    pub fn is_log(&self) -> bool {
+        self.factors.iter().any(|f| f.is_log())
+    }
+
+
+    /// Convert every `Factor` of the `UndirectedModel` to log-space, so that inference over the
+    /// model is carried out in log-space.
+    pub fn to_log(&self) -> UndirectedModel {
+        UndirectedModel {
+            factors: self.factors.iter().map(|f| f.to_log()).collect(),
+            variables: self.variables.clone(),
+            labels: self.labels.clone(),
+            partition: match self.partition.get() {
+                Some(&z) if ! self.is_log() => OnceLock::from(z.ln()),
+                _ => self.partition.clone()
+            }
+        }
     }
 
 
@@ -45,6 +95,39 @@ impl UndirectedModel {
     pub fn factors(&self) -> &Vec<Factor> {
         &self.factors
     }
+
+
+    /// Convert a `DirectedModel` to an `UndirectedModel`, decomposing each structured CPD into
+    /// small `Factor`s rather than expanding it to a table; see `DiscreteCpd::decompose`.
+    ///
+    /// The result has an additional, anonymous `Variable` for each auxiliary `Variable` of the
+    /// decompositions. Summing them out recovers the distribution of the `DirectedModel`, so the
+    /// result is suitable for sum-product inference, but not for maximizing over every `Variable`.
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if the `DirectedModel` has a continuous `Variable`
+    pub fn decomposed(directed: &DirectedModel) -> Result<Self> {
+        let mut factors = Vec::new();
+        let mut variables = BidirMap::new();
+
+        for v in directed.topological_order() {
+            let cpd = directed.discrete_cpd(&v).ok_or(JeromeError::InvalidScope)?;
+            factors.extend(cpd.decompose());
+
+            variables.insert(v, directed.lookup_name(&v).unwrap().clone());
+            for aux in cpd.auxiliary() {
+                variables.insert(*aux, aux.to_string());
+            }
+        }
+
+        // the decomposition of each CPD is normalized, so the product is too
+        Ok(UndirectedModel {
+            partition: normalized(&factors),
+            factors,
+            variables,
+            labels: directed.labels().clone()
+        })
+    }
 }
 
 
@@ -72,6 +155,11 @@ impl Model for UndirectedModel {
         self.variables.first_col().count()
     }
 
+    /// Get the state labels of the `Variable`s in the `UndirectedModel`
+    fn labels(&self) -> &Labels {
+        &self.labels
+    }
+
     /// Condition the `Model` given the evidence.
     ///
     /// # Args
@@ -81,8 +169,8 @@ impl Model for UndirectedModel {
     /// a new `Model` with scope ```self.vars() - evidence.keys()``` that represents the
     /// conditional distribution ```P(self.scope() - evidence.keys() | evidence.keys())```
     fn condition(&self, evidence: &Assignment) -> Self {
-        let factors = self.factors.iter()
-                                  .map(|ref f| f.reduce(evidence))
+        let factors: Vec<Factor> = self.factors.iter()
+                                  .map(|f| f.reduce(evidence))
                                   .filter(|f| ! f.is_identity())
                                   .collect();
 
@@ -92,10 +180,7 @@ impl Model for UndirectedModel {
                                                         .map(|(&v, n)| (v, n.clone()))
                                                         .collect();
 
-        let scope = variables.first_col().cloned().collect();
-        let partition = compute_partition(&scope, &factors);
-
-        UndirectedModel { factors, variables, partition }
+        UndirectedModel { factors, variables, labels: self.labels.clone(), partition: OnceLock::new() }
     }
 
 
@@ -112,50 +197,60 @@ impl Model for UndirectedModel {
         // for every factor in the graph
         self.factors.iter()
                     // get the value of the assignment
-                    .map(|ref f| f.value(assignment))
+                    .map(|f| f.value(assignment))
                     // and multiply those probabilities together
                     // but if there are any errors, just return the error
-                    .fold(Ok(1.0), |acc, val| acc.and_then(|p| val.map(|v| p * v)))
+                    .try_fold(1.0, |p, val| val.map(|v| p * v))
                     // and finally normalize by the partition function
-                    .map(|v| v / self.partition)
+                    .map(|v| v / self.partition())
+    }
+
+
+    /// Determine the log probability of a full `Assignment` to the `Variable`s in the `Model`.
+    fn log_probability(&self, assignment: &Assignment) -> Result<f64> {
+        self.factors.iter()
+                    .map(|f| f.log_value(assignment))
+                    .try_fold(0.0, |p, val| val.map(|v| p + v))
+                    .map(|v| v - self.log_partition())
     }
 
 }
 
 
-impl<'a> From<&'a DirectedModel> for UndirectedModel {
+impl<'a> TryFrom<&'a DirectedModel> for UndirectedModel {
+
+    type Error = JeromeError;
     
     /// Defines the conversion from `DirectedModel`s to `UndirectedModel`s
-    fn from(directed: &'a DirectedModel) -> Self {
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if the `DirectedModel` has a continuous `Variable`
+    fn try_from(directed: &'a DirectedModel) -> Result<Self> {
         let factors: Vec<Factor> = directed.topological_order()
                                            .iter()
-                                           .map(|v| directed.cpd(v).unwrap())
-                                           .cloned()
-                                           .collect();
+                                           .map(|v| directed.cpd(v).cloned().ok_or(JeromeError::InvalidScope))
+                                           .collect::<Result<_>>()?;
 
         let variables = directed.topological_order()
                                 .iter()
-                                .map(|v| (*v, directed.lookup_name(&v).unwrap().clone()))
+                                .map(|v| (*v, directed.lookup_name(v).unwrap().clone()))
                                 .collect();
 
-        let partition = compute_partition(&directed.topological_order(), &factors);
-
-        UndirectedModel {
+        // the product of the CPDs of a Bayesian Network is a normalized distribution
+        Ok(UndirectedModel {
+            partition: normalized(&factors),
             factors, 
             variables,
-            partition
-        }
+            labels: directed.labels().clone()
+        })
     }
 
 }
 
 
-/// Utility function to compute the partition function given a set of `Factor`s.
-pub fn compute_partition(scope: &Vec<Variable>, factors: &Vec<Factor>) -> f64 {
-    let assn_val = |a: Assignment| -> f64 {
-        factors.iter().map(|f| f.value(&a).unwrap()).product()
-    };
-    all_assignments(&scope).map(assn_val).sum()
+/// The cached partition function of a normalized distribution over `factors`, in their space
+fn normalized(factors: &[Factor]) -> OnceLock<f64> {
+    OnceLock::from(if factors.iter().any(|f| f.is_log()) { 0.0 } else { 1.0 })
 }
 
 
@@ -170,6 +265,9 @@ pub struct UndirectedModelBuilder {
     /// The name <-> variable mapping
     names: BidirMap<Variable, String>,
 
+    /// The state labels of the `Variable`s
+    labels: Labels,
+
     /// The error state of the builder, if any
     err: Option<JeromeError>
 
@@ -182,6 +280,7 @@ impl UndirectedModelBuilder {
         UndirectedModelBuilder {
             factors: Vec::new(),
             names: BidirMap::new(),
+            labels: Labels::new(),
             err: None
         }
     }
@@ -197,6 +296,20 @@ impl UndirectedModelBuilder {
     }
 
 
+    /// Label the values of a `Variable` in the `UndirectedModel`, in order; see `Labels::set`.
+    pub fn with_labels(mut self, var: &Variable, labels: &[&str]) -> Self {
+        if self.err.is_some() {
+            return self;
+        }
+
+        if let Err(e) = self.labels.set(var, labels) {
+            self.err = Some(e);
+        }
+
+        self
+    }
+
+
     /// Add a `Factor` to the `UndirectedModel`.
     ///
     /// # Arguments
@@ -224,10 +337,10 @@ impl UndirectedModelBuilder {
     ///
     /// # Errors
     /// * `JeromeError::InvalidScope` if there is a mismatch between the `Variable`s defined by
-    /// calls to `with_named_variable` and `with_factor`
+    ///   calls to `with_named_variable` and `with_factor`
     pub fn build(mut self) -> Result<UndirectedModel> {
-        if self.err.is_some() {
-            return Err(self.err.unwrap());
+        if let Some(e) = self.err {
+            return Err(e);
         }
 
         // make sure there are no variables defined but not used in a factor
@@ -238,7 +351,7 @@ impl UndirectedModelBuilder {
         }
 
         // for any unnamed variable in a factor, give it a name
-        for ref f in self.factors.iter() {
+        for f in self.factors.iter() {
             for v in f.scope().iter() {
                 if ! self.names.contains_first_key(v) {
                     self.names.insert(*v, v.to_string());
@@ -246,23 +359,32 @@ impl UndirectedModelBuilder {
             }
         }
 
-        // compute the partition function
-        let vars: Vec<Variable> = self.names.first_col().cloned().collect();  
-
         Ok(UndirectedModel { 
             factors: self.factors.clone(),
             variables: self.names.clone(),
-            partition: compute_partition(&vars, &self.factors)
+            labels: self.labels.clone(),
+            partition: OnceLock::new()
         })
     }
 
 }
 
+impl Default for UndirectedModelBuilder {
+
+    fn default() -> Self {
+        UndirectedModelBuilder::new()
+    }
+
+}
+
 #[cfg(test)]
+#[allow(clippy::nonminimal_bool)]
 mod tests {
 
     #[cfg(test)]
     use super::*;
+    use model::directed::DirectedModelBuilder;
+    use variable::all_assignments;
 
     #[test]
     /// Tests the implementation of `UndirectedModel` using the Misconception example from Koller &
@@ -336,6 +458,15 @@ mod tests {
         assn.set(&b, 1);
         assn.set(&c, 0);
         assert!(model.probability(&assn).is_err());
+        assert!(model.log_probability(&assn).is_err());
+
+        // log probabilities agree with probabilities, in either representation
+        let log_model = model.to_log();
+        for assn in all_assignments(&vec![a, b, c, d]) {
+            let p = model.probability(&assn).unwrap();
+            assert!((p.ln() - model.log_probability(&assn).unwrap()).abs() < 1e-10);
+            assert!((p.ln() - log_model.log_probability(&assn).unwrap()).abs() < 1e-10);
+        }
         
         ///////////////////////////////////////////////////////////////////////////////////////////
         // TEST CONDITIONING
@@ -353,5 +484,90 @@ mod tests {
         assn.set(&d, 0);
         assert!((0.057 - new_model.probability(&assn).unwrap()) < 0.0005);
     }
-}
 
+    #[test]
+    /// Building, converting and conditioning a model must not enumerate the joint distribution
+    fn large_models() {
+        // a chain of 40 binary variables has 2^40 joint assignments
+        let vars: Vec<Variable> = (0..40).map(|_| Variable::binary()).collect();
+
+        let directed = vars.iter()
+                           .enumerate()
+                           .fold(DirectedModelBuilder::new(), |builder, (i, v)| {
+                               let parents = if i == 0 { HashSet::new() } else { vec![vars[i - 1]].into_iter().collect() };
+                               builder.with_variable(v, parents, Initialization::Random)
+                           })
+                           .build()
+                           .unwrap();
+
+        let model = UndirectedModel::try_from(&directed).unwrap();
+        assert_eq!(1.0, model.partition());
+
+        // the partition functions of the models conditioned on each value of a variable are the
+        // probabilities of that evidence, which must sum to one
+        let z: f64 = (0..2).map(|val| {
+            let mut evidence = Assignment::new();
+            evidence.set(&vars[20], val);
+            model.condition(&evidence).partition()
+        }).sum();
+        assert!((1.0 - z).abs() < 1e-10);
+
+        // the same holds for an unnormalized model
+        let undirected = vars.windows(2)
+                             .fold(UndirectedModelBuilder::new(), |builder, w| {
+                                 builder.with_factor(w.iter().cloned().collect(), Initialization::Random)
+                             })
+                             .build()
+                             .unwrap();
+
+        let z: f64 = (0..2).map(|val| {
+            let mut evidence = Assignment::new();
+            evidence.set(&vars[39], val);
+            undirected.condition(&evidence).partition()
+        }).sum();
+        assert!((undirected.partition() - z).abs() < 1e-10 * z);
+    }
+
+    #[test]
+    /// Tests that the partition function is the same whether or not the model is in log-space
+    fn log_space_partition() {
+        let vars: Vec<Variable> = (0..10).map(|_| Variable::discrete(3)).collect();
+        let linear = vars.windows(2)
+                         .fold(UndirectedModelBuilder::new(), |builder, w| {
+                             builder.with_factor(w.iter().cloned().collect(), Initialization::Random)
+                         })
+                         .build()
+                         .unwrap();
+
+        // computed from scratch in log-space
+        let log = linear.to_log();
+        assert!(log.is_log());
+        assert!((log.log_partition() - linear.log_partition()).abs() < 1e-10);
+        assert!((log.partition() - linear.partition()).abs() < 1e-10 * linear.partition());
+        assert_eq!(linear.partition().ln(), linear.log_partition());
+
+        // a Bayesian Network is normalized in either space
+        let directed = vars.iter()
+                           .enumerate()
+                           .fold(DirectedModelBuilder::new(), |builder, (i, v)| {
+                               let parents = if i == 0 { HashSet::new() } else { vec![vars[i - 1]].into_iter().collect() };
+                               builder.with_variable(v, parents, Initialization::Random)
+                           })
+                           .build()
+                           .unwrap();
+
+        let linear = UndirectedModel::try_from(&directed).unwrap();
+        assert_eq!(1.0, linear.partition());
+        assert_eq!(0.0, linear.log_partition());
+
+        for model in [linear.to_log(), UndirectedModel::decomposed(&directed).unwrap().to_log()].iter() {
+            assert_eq!(1.0, model.partition());
+            assert_eq!(0.0, model.log_partition());
+        }
+
+        let mut evidence = Assignment::new();
+        evidence.set(&vars[4], 2);
+        let conditioned = UndirectedModel::try_from(&directed).unwrap().to_log().condition(&evidence);
+        assert!((conditioned.partition() - UndirectedModel::try_from(&directed).unwrap().condition(&evidence).partition()).abs() < 1e-10);
+    }
+}
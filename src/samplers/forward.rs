@@ -3,34 +3,43 @@
 //! Implementation of Koller & Friedman Algorithm 12.1 (pp 489)
 
 use model::directed::DirectedModel;
-use variable::Assignment;
-use super::{IndependentSampler, Sampler};
+use variable::{Assignment, DenseAssignment, VariableIndex};
+use super::{DenseSampler, IndependentSampler, Sampler};
+
+use std::sync::Arc;
 
 /// A simple, stateless `Sampler` for Bayesian Models that uses forward sampling to draw full
-/// assignments from the `DirectedModel`
+/// assignments from the `DirectedModel`, including any continuous `Variable`s
 pub struct ForwardSampler<'a> {
     
     /// The `DirectedModel` to sample
-    model: &'a DirectedModel
+    model: &'a DirectedModel,
+
+    /// The topological order of the `DirectedModel`, which is the layout of the samples
+    index: Arc<VariableIndex>
 }
 
 
 impl<'a> ForwardSampler<'a> {
     
     pub fn new(model: &'a DirectedModel) -> Self {
-        ForwardSampler { model }
+        let index = Arc::new(VariableIndex::new(&model.topological_order()));
+        ForwardSampler { model, index }
     }
 
-    fn get_sample(&self) -> Assignment {
-        let mut a = Assignment::new();
-
-        for ref var in self.model.topological_order().iter() {
-            let cpd = self.model.cpd(var).unwrap();
+    fn get_sample(&self) -> DenseAssignment {
+        let mut a = DenseAssignment::new(&self.index);
 
-            // this cannot fail, because we iterate in topological order so each variable will get
+        for (pos, var) in self.index.variables().iter().enumerate() {
+            // these cannot fail, because we iterate in topological order so each variable will get
             // a full assignment (minus itself) thus satistfying the contract of sample_cpd
-            let v_assignment = cpd.sample_cpd(&a).unwrap();
-            a.set(&var, v_assignment);
+            if var.is_continuous() {
+                let value = self.model.clg_cpd(var).unwrap().sample(&a).unwrap();
+                a.set_continuous_at(pos, value);
+            } else {
+                let v_assignment = self.model.discrete_cpd(var).unwrap().sample_cpd(&a).unwrap();
+                a.set_at(pos, v_assignment);
+            }
         }
 
         a
@@ -40,7 +49,7 @@ impl<'a> ForwardSampler<'a> {
 impl<'a> Sampler for ForwardSampler<'a> {
 
     fn sample(&mut self) -> Assignment {
-        self.get_sample()
+        Assignment::from(&self.get_sample())
     }
 
 }
@@ -49,6 +58,19 @@ impl<'a> Sampler for ForwardSampler<'a> {
 impl<'a> IndependentSampler for ForwardSampler<'a> {
 
     fn ind_sample(&self) -> Assignment {
+        Assignment::from(&self.get_sample())
+    }
+
+}
+
+
+impl<'a> DenseSampler for ForwardSampler<'a> {
+
+    fn index(&self) -> &Arc<VariableIndex> {
+        &self.index
+    }
+
+    fn dense_sample(&mut self) -> DenseAssignment {
         self.get_sample()
     }
 
@@ -61,6 +83,7 @@ mod tests {
     use super::*; 
     use model::directed::DirectedModelBuilder;
     use factor::Factor;
+    use gaussian::LinearGaussian;
     use init::Initialization;
     use variable::Variable;
 
@@ -105,6 +128,36 @@ mod tests {
             assert!(a.get(&sat).is_some());
             assert!(*a.get(&sat).unwrap() <= 1);
         }
+
+        for _ in 0..100 {
+            let a = sampler.dense_sample();
+
+            assert!(a.is_complete());
+            assert!(a.get(&intelligence).unwrap() <= 1);
+            assert!(a.get(&sat).unwrap() <= 1);
+        }
     }
 
+    #[test]
+    /// X ~ N(1, 4), Y | X ~ N(2x + 3, 1), so E[Y] = 5 and Var[Y] = 17
+    fn sample_linear_gaussian() {
+        let x = Variable::continuous();
+        let y = Variable::continuous();
+
+        let cpd_y = LinearGaussian::new(y, vec![x], vec![2.0], 3.0, 1.0).unwrap();
+        let model = DirectedModelBuilder::new()
+                        .with_variable(&x, HashSet::new(), Initialization::Gaussian(1.0, 4.0))
+                        .with_variable(&y, vec![x].into_iter().collect(), Initialization::LinearGaussian(cpd_y))
+                        .build()
+                        .unwrap();
+
+        let mut sampler = ForwardSampler::new(&model);
+        let n = 20000;
+        let ys: Vec<f64> = (0..n).map(|_| sampler.sample().get_continuous(&y).unwrap()).collect();
+
+        let mean = ys.iter().sum::<f64>() / n as f64;
+        let variance = ys.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n as f64;
+        assert!((mean - 5.0).abs() < 0.15);
+        assert!((variance - 17.0).abs() < 1.0);
+    }
 }
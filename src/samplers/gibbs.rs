@@ -6,21 +6,24 @@ use factor::Factor;
 use model::Model;
 use model::directed::DirectedModel;
 use model::undirected::UndirectedModel;
-use super::{IndependentWeightedSampler, LikelihoodWeightedSampler, Sampler, WeightedSample};
-use variable::{all_assignments, Assignment, Variable};
+use super::{DenseSampler, DenseWeightedSample, DenseWeightedSampler, LikelihoodWeightedSampler, Sampler};
+use util::{JeromeError, Result};
+use variable::{Assignment, DenseAssignment, Variable, VariableIndex};
 
 use rand;
 use rand::distributions::{Range, IndependentSample};
 
-use std::collections::HashSet;
+use std::sync::Arc;
 
 pub struct GibbsSampler {
 
     factors: Vec<Factor>,
 
-    variables: HashSet<Variable>,
+    /// The position of each unobserved `Variable` in `sample`, with the indices of the `factors`
+    /// whose scope contains it
+    blankets: Vec<(usize, Vec<usize>)>,
 
-    sample: Assignment
+    sample: DenseAssignment
 
 }
 
@@ -30,15 +33,20 @@ impl GibbsSampler {
     /// Construct a new `GibbsSampler` from the `DirectedModel`
     ///
     /// Initializes the assignment from a `LikelihoodWeightedSampler`
-    pub fn for_directed(model: &DirectedModel, evidence: &Assignment) -> Self {
-        let factors = model.topological_order().iter().map(|v| model.cpd(v).unwrap()).cloned().collect();
-        let variables = model.topological_order().iter().filter(|v| evidence.get(v).is_none()).cloned().collect();
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if the model has a continuous `Variable`
+    pub fn for_directed(model: &DirectedModel, evidence: &Assignment) -> Result<Self> {
+        let factors = model.topological_order()
+                           .iter()
+                           .map(|v| model.cpd(v).cloned().ok_or(JeromeError::InvalidScope))
+                           .collect::<Result<_>>()?;
 
         // use likelihood sampling to draw an initial sample from the mutilated Bayesian network
-        let t0_sampler = LikelihoodWeightedSampler::new(model, evidence);
-        let WeightedSample(sample, _) = t0_sampler.ind_weighted_sample();
+        let mut t0_sampler = LikelihoodWeightedSampler::new(model, evidence);
+        let DenseWeightedSample(sample, _) = t0_sampler.dense_weighted_sample();
 
-        GibbsSampler { factors, variables, sample }
+        Ok(GibbsSampler::new(factors, sample, evidence))
     }
 
 
@@ -51,24 +59,42 @@ impl GibbsSampler {
     /// like there is for directed?
     pub fn for_undirected(model: &UndirectedModel, evidence: &Assignment) -> Self {
         let factors = model.factors().clone();
-        let variables: HashSet<Variable> = model.variables()
-                                                .iter()
-                                                .filter(|v| evidence.get(v).is_none())
-                                                .cloned()
-                                                .collect();
 
-        let mut sample = Assignment::new();
+        let mut variables: Vec<Variable> = model.variables().into_iter().collect();
+        variables.sort_by_key(|&v| usize::from(v));
+        let index = Arc::new(VariableIndex::new(&variables));
+
+        let mut sample = DenseAssignment::from_assignment(&index, evidence);
         let mut rng = rand::thread_rng();
-        for ref v in model.variables().iter() {
-            if let Some(&val) = evidence.get(v) {
-                sample.set(v, val);
-            } else {
+        for (pos, v) in index.variables().iter().enumerate() {
+            if sample.get_at(pos).is_none() {
                 let between = Range::new(0, v.cardinality());
-                sample.set(v, between.ind_sample(&mut rng));
+                sample.set_at(pos, between.ind_sample(&mut rng));
             }
         }
 
-        GibbsSampler { factors, variables, sample }
+        GibbsSampler::new(factors, sample, evidence)
+    }
+
+
+    /// Construct a new `GibbsSampler` over the factors, starting from the full assignment `sample`
+    fn new(factors: Vec<Factor>, sample: DenseAssignment, evidence: &Assignment) -> Self {
+        let blankets = sample.index()
+                             .variables()
+                             .iter()
+                             .enumerate()
+                             .filter(|&(_, v)| evidence.get(v).is_none())
+                             .map(|(pos, v)| {
+                                 let with_v = factors.iter()
+                                                     .enumerate()
+                                                     .filter(|&(_, f)| f.scope().contains(v))
+                                                     .map(|(i, _)| i)
+                                                     .collect();
+                                 (pos, with_v)
+                             })
+                             .collect();
+
+        GibbsSampler { factors, blankets, sample }
     }
 
 }
@@ -76,54 +102,62 @@ impl GibbsSampler {
 impl Sampler for GibbsSampler {
 
     fn sample(&mut self) -> Assignment {
+        Assignment::from(&self.dense_sample())
+    }
+
+}
+
+
+impl DenseSampler for GibbsSampler {
+
+    fn index(&self) -> &Arc<VariableIndex> {
+        self.sample.index()
+    }
+
+    fn dense_sample(&mut self) -> DenseAssignment {
         let mut rng = rand::thread_rng();
         let between = Range::new(0.0, 1.0);
 
-        // for each variable in the sample set
-        for &v in self.variables.iter() {
+        let GibbsSampler { ref factors, ref blankets, ref mut sample } = *self;
 
-            //////////////////////////////////////////////////////////////
-            // 1) unset v from the current sample
-            self.sample.unset(&v);
+        // for each variable in the sample set
+        for &(pos, ref with_v) in blankets.iter() {
+            let cardinality = sample.index().variables()[pos].cardinality();
 
             //////////////////////////////////////////////////////////////
-            // 2) compute P(v | variables - {v})
-            let factors_with_v: Vec<Factor> = self.factors
-                                                  .iter()
-                                                  .filter(|f| f.scope().contains(&v))
-                                                  .map(|f| f.reduce(&self.sample))
-                                                  .collect();
-
-            let f = factors_with_v.iter()
-                                  .fold(Ok(Factor::Identity), |acc, f1| acc.and_then(|f2| f1.product(&f2)))
-                                  .unwrap();
-
-            let scope = vec![v];
-            let vals: Vec<f64> = all_assignments(&scope).map(|a| f.value(&a).unwrap()).collect();
+            // 1) compute P(v | variables - {v}) by evaluating the factors containing v at each
+            //    value of v
+            let vals: Vec<f64> = (0..cardinality).map(|val| {
+                                     sample.set_at(pos, val);
+                                     with_v.iter().map(|&i| factors[i].value(sample).unwrap()).product()
+                                 })
+                                 .collect();
             let sum: f64 = vals.iter().sum();
             let p: Vec<f64> = vals.iter().map(|val| val / sum).collect();
 
             //////////////////////////////////////////////////////////////
-            // 3) Sample v from P and set v to the value in the sample
+            // 2) Sample v from P and set v to the value in the sample
             let draw = between.ind_sample(&mut rng);
             let mut upper = 0.0;
-            for i in 0..p.len() {
-                upper = upper + p[i];
+            sample.set_at(pos, cardinality - 1);
+            for (i, p_i) in p.iter().enumerate() {
+                upper += p_i;
                 if draw < upper {
-                    self.sample.set(&v, i);     
+                    sample.set_at(pos, i);     
                     break;
                 }
             }
         }
 
         // return the sample
-        self.sample.clone()
+        sample.clone()
     }
 
 }
 
 
 #[cfg(test)]
+#[allow(clippy::assign_op_pattern)]
 mod tests {
 
     use super::*; 
@@ -178,7 +212,7 @@ mod tests {
         evidence.set(&s, 1);
 
         // need a better way to test this
-        let mut sampler = GibbsSampler::for_directed(&model, &evidence);
+        let mut sampler = GibbsSampler::for_directed(&model, &evidence).unwrap();
         for _ in 0..100 {
             let particle = sampler.sample();
 
@@ -194,6 +228,15 @@ mod tests {
             assert!(particle.get(&l).is_some());
             assert_eq!(*particle.get(&l).unwrap(), 0);
         }
+
+        for _ in 0..100 {
+            let particle = sampler.dense_sample();
+
+            assert!(particle.is_complete());
+            assert!(particle.get(&g).unwrap() < 3);
+            assert_eq!(particle.get(&s), Some(1));
+            assert_eq!(particle.get(&l), Some(0));
+        }
     }
    
 
@@ -284,7 +327,7 @@ mod tests {
         let mut evidence = Assignment::new();
         evidence.set(&c, 0);
 
-        let mut sampler = GibbsSampler::for_directed(&model, &evidence);
+        let mut sampler = GibbsSampler::for_directed(&model, &evidence).unwrap();
 
         let mut a_ct = 0;
         let mut b_ct = 0;
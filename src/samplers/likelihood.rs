@@ -2,20 +2,25 @@
 //! 
 //! Koller & Friedman Algorithm 12.2 (pp 493)
 
-use super::{WeightedSampler, IndependentWeightedSampler, WeightedSample};
-use variable::Assignment;
+use super::{DenseWeightedSample, DenseWeightedSampler, WeightedSampler, IndependentWeightedSampler, WeightedSample};
+use variable::{Assignment, DenseAssignment, VariableIndex};
 use model::directed::DirectedModel;
 
+use std::sync::Arc;
+
 
 /// A simple `Sampler` for Bayesian Models that uses likelihood weighted sampling to draw full
 /// assignments from the `DirectedModel` given evidence.
+///
+/// Evidence on continuous `Variable`s weights the sample by the density of the evidence, so the
+/// weights of samples from hybrid models are not bounded by 1.
 pub struct LikelihoodWeightedSampler<'a> {
 
     /// The model from which to sample
     model: &'a DirectedModel,
 
-    /// The evidence on which to condition
-    evidence: &'a Assignment
+    /// The evidence on which to condition, laid out against `index`
+    evidence: DenseAssignment
 
 }
 
@@ -23,30 +28,46 @@ pub struct LikelihoodWeightedSampler<'a> {
 impl<'a> LikelihoodWeightedSampler<'a> {
 
     pub fn new(model: &'a DirectedModel, evidence: &'a Assignment) -> Self {
+        let index = Arc::new(VariableIndex::new(&model.topological_order()));
+        let evidence = DenseAssignment::from_assignment(&index, evidence);
         LikelihoodWeightedSampler { model, evidence }
     }
 
 
-    fn get_sample(&self) -> WeightedSample {
-        let mut a = Assignment::new();
+    fn get_sample(&self) -> DenseWeightedSample {
+        let mut a = DenseAssignment::new(self.evidence.index());
         let mut w = 1.0;
 
-        for ref var in self.model.topological_order().iter() {
-            let cpd = self.model.cpd(var).unwrap();
+        for (pos, var) in self.evidence.index().variables().iter().enumerate() {
+            if var.is_continuous() {
+                let cpd = self.model.clg_cpd(var).unwrap();
+
+                if let Some(val) = self.evidence.get_continuous_at(pos) {
+                    a.set_continuous_at(pos, val);
+                    // update the weight by the density p(var | Pa(var))
+                    w *= cpd.density(&a).unwrap();
+                } else {
+                    a.set_continuous_at(pos, cpd.sample(&a).unwrap());
+                }
+
+                continue;
+            }
+
+            let cpd = self.model.discrete_cpd(var).unwrap();
 
-            if let Some(&val) = self.evidence.get(var) {
-                a.set(&var, val);
+            if let Some(val) = self.evidence.get_at(pos) {
+                a.set_at(pos, val);
                 // update the weight by P(var | Pa(var))
-                w = w * cpd.value(&a).unwrap();
+                w *= cpd.value(&a).unwrap();
             } else {
                 // this cannot fail, because we iterate in topological order so each variable will get
                 // a full assignment (minus itself) thus satistfying the contract of sample_cpd
                 let v_assignment = cpd.sample_cpd(&a).unwrap();
-                a.set(&var, v_assignment);
+                a.set_at(pos, v_assignment);
             }
         }
 
-        WeightedSample(a, w)
+        DenseWeightedSample(a, w)
     }
 
 }
@@ -55,7 +76,7 @@ impl<'a> LikelihoodWeightedSampler<'a> {
 impl<'a> WeightedSampler for LikelihoodWeightedSampler<'a> {
 
     fn weighted_sample(&mut self) -> WeightedSample {
-        self.get_sample()
+        self.ind_weighted_sample()
     }
 
 }
@@ -64,6 +85,20 @@ impl<'a> WeightedSampler for LikelihoodWeightedSampler<'a> {
 impl<'a> IndependentWeightedSampler for LikelihoodWeightedSampler<'a> {
     
     fn ind_weighted_sample(&self) -> WeightedSample {
+        let DenseWeightedSample(a, w) = self.get_sample();
+        WeightedSample(Assignment::from(&a), w)
+    }
+
+}
+
+
+impl<'a> DenseWeightedSampler for LikelihoodWeightedSampler<'a> {
+
+    fn index(&self) -> &Arc<VariableIndex> {
+        self.evidence.index()
+    }
+
+    fn dense_weighted_sample(&mut self) -> DenseWeightedSample {
         self.get_sample()
     }
 
@@ -75,7 +110,9 @@ mod tests {
 
     use super::*; 
     use model::directed::DirectedModelBuilder;
+    use std::collections::HashSet;
     use factor::Factor;
+    use gaussian::{ConditionalLinearGaussian, LinearGaussian};
     use init::Initialization;
     use variable::Variable;
 
@@ -162,6 +199,16 @@ mod tests {
             assert!(weight <= 1.0);
         }
 
+        for _ in 0..100 {
+            let DenseWeightedSample(particle, weight) = sampler.dense_weighted_sample();
+
+            assert!(particle.is_complete());
+            assert_eq!(particle.get(&s), Some(1));
+            assert_eq!(particle.get(&l), Some(0));
+            assert!(weight >= 0.0);
+            assert!(weight <= 1.0);
+        }
+
         // verify the weight in example 12.3
         loop {
             let WeightedSample(particle, weight) = sampler.weighted_sample();
@@ -176,6 +223,51 @@ mod tests {
             }
         }
     }
-}
 
+    /// A sensor with a discrete mode M (P(m1) = 0.3) and a reading R, where R | m0 ~ N(0, 1) and
+    /// R | m1 ~ N(5, 4)
+    #[test]
+    fn hybrid() {
+        let m = Variable::binary();
+        let r = Variable::continuous();
+
+        let cpd_r = ConditionalLinearGaussian::new(
+            r,
+            vec![m],
+            vec![LinearGaussian::normal(r, 0.0, 1.0).unwrap(), LinearGaussian::normal(r, 5.0, 4.0).unwrap()]
+        ).unwrap();
+
+        let model = DirectedModelBuilder::new()
+                        .with_variable(&m, HashSet::new(), Initialization::Binomial(0.7))
+                        .with_variable(&r, vec![m].into_iter().collect(), Initialization::ConditionalLinearGaussian(cpd_r))
+                        .build()
+                        .unwrap();
+
+        let mut evidence = Assignment::new();
+        evidence.set_continuous(&r, 2.0);
+
+        let normal = |mean: f64, variance: f64, v: f64| {
+            (-(v - mean).powi(2) / (2.0 * variance)).exp() / (2.0 * std::f64::consts::PI * variance).sqrt()
+        };
+        let p0 = 0.7 * normal(0.0, 1.0, 2.0);
+        let p1 = 0.3 * normal(5.0, 4.0, 2.0);
 
+        let mut sampler = LikelihoodWeightedSampler::new(&model, &evidence);
+        let mut total = 0.0;
+        let mut m1 = 0.0;
+        for _ in 0..20000 {
+            let WeightedSample(particle, weight) = sampler.weighted_sample();
+            assert_eq!(Some(2.0), particle.get_continuous(&r));
+
+            let expected = if *particle.get(&m).unwrap() == 0 { p0 / 0.7 } else { p1 / 0.3 };
+            assert!((expected - weight).abs() < 1e-12);
+
+            total += weight;
+            if *particle.get(&m).unwrap() == 1 {
+                m1 += weight;
+            }
+        }
+
+        assert!((m1 / total - p1 / (p0 + p1)).abs() < 0.02);
+    }
+}
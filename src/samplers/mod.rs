@@ -1,6 +1,8 @@
 //! Defines the `Sampler` trait - an object that can randomly sample from a `Model`.
 
-use variable::Assignment;
+use variable::{Assignment, DenseAssignment, VariableIndex};
+
+use std::sync::Arc;
 
 pub mod forward;
 pub mod likelihood;
@@ -27,6 +29,19 @@ pub trait IndependentSampler {
 }
 
 
+/// A `Sampler` that produces `DenseAssignment`s laid out against a fixed `VariableIndex`. This
+/// avoids building a hashed `Assignment` for every sample.
+pub trait DenseSampler {
+
+    /// The `VariableIndex` of the samples
+    fn index(&self) -> &Arc<VariableIndex>;
+
+    /// Sample from the associated `Model`.
+    fn dense_sample(&mut self) -> DenseAssignment;
+
+}
+
+
 /// A sample (a full assignment) and the associated weight.
 /// Used for likelihood weighting.
 pub struct WeightedSample(pub Assignment, pub f64);
@@ -43,3 +58,15 @@ pub trait IndependentWeightedSampler : WeightedSampler {
 
 }
 
+
+/// A `DenseAssignment` sample and the associated weight.
+pub struct DenseWeightedSample(pub DenseAssignment, pub f64);
+
+pub trait DenseWeightedSampler {
+
+    /// The `VariableIndex` of the samples
+    fn index(&self) -> &Arc<VariableIndex>;
+
+    fn dense_weighted_sample(&mut self) -> DenseWeightedSample;
+
+}
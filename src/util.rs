@@ -38,6 +38,10 @@ pub enum JeromeError {
     /// There is not enough data provided 
     NotEnoughData,
 
+    /// Represents a directed cycle in a model that must be acyclic. The value in the tuple is the
+    /// names of the variables along the cycle, in the direction of the edges.
+    Cycle(Vec<String>),
+
     /// A general error with the given description
     General(String),
 
@@ -46,25 +50,35 @@ pub enum JeromeError {
 
 }
 
+impl JeromeError {
+
+    /// A short, human readable description of the error
+    fn message(&self) -> &str {
+        match *self {
+            JeromeError::IncompleteAssignment => "Missing assignments to the required Variables",
+            JeromeError::InvalidScope => "Provided scope did not satisfy constraints",
+            JeromeError::DivideByZero => "Encountered division by zero",
+            JeromeError::MissingParent => "Missing a parent from the model",
+            JeromeError::DuplicateVariable => "A variable was encountered twice",
+            JeromeError::InvalidInitialization => "An invalid initialization was provided",
+            JeromeError::NonPositiveProbability => "Encountered a non-positive probability",
+            JeromeError::NotACPD=> "Requires a Conditional Probability Distribution",
+            JeromeError::NotEnoughData => "Not enough data has been provided",
+            JeromeError::Cycle(_) => "The model contains a directed cycle",
+            JeromeError::General(ref err) => err.as_str(),
+            JeromeError::Unknown => "An unknown error occured"
+        }
+    }
+
+}
+
 impl Error for JeromeError {
 
     fn description(&self) -> &str {
-        match self {
-            &JeromeError::IncompleteAssignment => "Missing assignments to the required Variables",
-            &JeromeError::InvalidScope => "Provided scope did not satisfy constraints",
-            &JeromeError::DivideByZero => "Encountered division by zero",
-            &JeromeError::MissingParent => "Missing a parent from the model",
-            &JeromeError::DuplicateVariable => "A variable was encountered twice",
-            &JeromeError::InvalidInitialization => "An invalid initialization was provided",
-            &JeromeError::NonPositiveProbability => "Encountered a non-positive probability",
-            &JeromeError::NotACPD=> "Requires a Conditional Probability Distribution",
-            &JeromeError::NotEnoughData => "Not enough data has been provided",
-            &JeromeError::General(ref err) => err.as_str(),
-            &JeromeError::Unknown => "An unknown error occured"
-        }
+        self.message()
     }
 
-    fn cause(&self) -> Option<&Error> {
+    fn cause(&self) -> Option<&dyn Error> {
         None
     }
 
@@ -73,7 +87,13 @@ impl Error for JeromeError {
 impl fmt::Display for JeromeError {
 
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.description())
+        match *self {
+            JeromeError::Cycle(ref names) => {
+                // close the cycle by returning to the first variable
+                write!(f, "{}: {} -> {}", self.message(), names.join(" -> "), names[0])
+            },
+            _ => write!(f, "{}", self.message())
+        }
     }
 
 }
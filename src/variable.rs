@@ -1,20 +1,27 @@
 //! Definition of the variable module
 //!
 //! A `Variable` represents a random variable in a Probabilistic Graphic Models.
+//!
+//! `Variable`s are either discrete or continuous. Discrete `Variable`s are assigned integral
+//! values; continuous `Variable`s are assigned real values through the `*_continuous` accessors
+//! of `Assignment` and `DenseAssignment`.
 
-use util::JeromeError;
+use util::{JeromeError, Result};
 
+use indexmap::IndexMap;
 use itertools::{Itertools, MultiProduct};
 
 use std::collections::HashMap;
 use std::convert::From;
+use std::fmt;
 use std::ops::Range;
-use std::string::ToString;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 
-/// A `Variable` in a Probablistic Graphical Model. A `Variable` is a discrete quantity that can take
-/// on a fixed number of potential values. These values are represented by simple integers,
-/// starting at 0.
+/// A `Variable` in a Probablistic Graphical Model. A discrete `Variable` can take on a fixed number
+/// of potential values. These values are represented by simple integers, starting at 0. A
+/// continuous `Variable` takes on real values.
 ///
 /// # Notes
 /// A `Variable` is lightweight and designed to be copyable for ease of use. 
@@ -24,44 +31,90 @@ pub struct Variable {
     /// The unique identifier of the `Variable`
     id: usize,
 
-    /// The cardinality of the `Variable` - i.e. how many possible values it can take on
-    cardinality: usize
+    /// Whether the `Variable` is discrete or continuous, and the cardinality of a discrete
+    /// `Variable`
+    kind: VariableKind
+
+}
+
+/// The kind of a `Variable`
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum VariableKind {
+
+    /// A discrete `Variable` with the given number of values, which is at least one
+    Discrete(usize),
+
+    /// A continuous `Variable`, which takes on real values
+    Continuous
 
 }
 
-static mut VARIABLE_ID: usize = 0;
+/// The next identifier to hand out. Every `Variable` constructed in the process draws its
+/// identifier from this counter, so identifiers are unique across threads.
+static VARIABLE_ID: AtomicUsize = AtomicUsize::new(0);
 
 impl Variable {
 
     /// Construct a new `Variable` with a unique identifier
-    ///
-    /// # Notes
-    /// This is *not* thread-safe
-    unsafe fn new(cardinality: usize) -> Self {
-        let id = VARIABLE_ID;
-        VARIABLE_ID += 1;
-        
-        Variable { id, cardinality }
+    fn new(kind: VariableKind) -> Self {
+        let id = VARIABLE_ID.fetch_add(1, Ordering::Relaxed);
+        Variable { id, kind }
     }
 
     /// Construct a new binary `Variable`.
     pub fn binary() -> Self {
-        unsafe {
-            Variable::new(2)
-        }
+        Variable::new(VariableKind::Discrete(2))
     }
 
     /// Construct a new discrete `Variable` with a certain number of values
+    ///
+    /// # Panics
+    /// if `cardinality` is zero; see `Variable::continuous` for a `Variable` with real values
     pub fn discrete(cardinality: usize) -> Self {
-        unsafe {
-            Variable::new(cardinality)
-        }
+        assert!(cardinality > 0, "a discrete variable must have at least one value");
+        Variable::new(VariableKind::Discrete(cardinality))
+    }
+
+    /// Construct a new continuous `Variable`, which takes on real values.
+    pub fn continuous() -> Self {
+        Variable::new(VariableKind::Continuous)
+    }
+
+    /// Recreate a `Variable` with a known identifier, e.g. when loading a model from disk.
+    ///
+    /// Identifiers handed out by `Variable::binary` and `Variable::discrete` after this call are
+    /// guaranteed to be larger than `id`, so recreated `Variable`s never collide with fresh ones.
+    ///
+    /// # Notes
+    /// It is the caller's responsibility not to recreate an identifier that was already handed out
+    /// to a different `Variable` in this process. A `VariableRegistry` checks this for the
+    /// `Variable`s it knows about.
+    ///
+    /// # Panics
+    /// if `kind` is ```VariableKind::Discrete(0)```
+    pub fn with_id(id: usize, kind: VariableKind) -> Self {
+        assert!(kind != VariableKind::Discrete(0), "a discrete variable must have at least one value");
+        VARIABLE_ID.fetch_max(id + 1, Ordering::Relaxed);
+        Variable { id, kind }
+    }
+
+    /// Get the kind of the `Variable`
+    pub fn kind(&self) -> VariableKind {
+        self.kind
     }
 
     /// Get the 'cardinality' of the variable's domain. i.e. - how many values are valid assignments the 
-    /// variable may take 
+    /// variable may take. A continuous `Variable` has a cardinality of 0.
     pub fn cardinality(&self) -> usize {
-        self.cardinality
+        match self.kind {
+            VariableKind::Discrete(cardinality) => cardinality,
+            VariableKind::Continuous => 0
+        }
+    }
+
+    /// `true`, if the `Variable` is continuous
+    pub fn is_continuous(&self) -> bool {
+        self.kind == VariableKind::Continuous
     }
 
 }
@@ -84,18 +137,296 @@ impl<'a> From<&'a Variable> for usize {
 
 }
 
-impl ToString for Variable {
+impl fmt::Display for Variable {
 
-    fn to_string(&self) -> String {
-        usize::from(self).to_string()    
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", usize::from(self))
     }
 
 }
 
+/// The state labels of a set of `Variable`s, e.g. those of a single model. A labeled `Variable` has
+/// one distinct label per value, such as ```["low", "medium", "high"]```.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Labels {
+
+    /// The labels of each labeled `Variable`, in order of value
+    labels: HashMap<Variable, Vec<String>>
+
+}
+
+
+impl Labels {
+
+    /// Construct a new, empty set of `Labels`
+    pub fn new() -> Self {
+        Labels { labels: HashMap::new() }
+    }
+
+    /// Label the values of a `Variable`, in order. Replaces any existing labels of the `Variable`.
+    ///
+    /// # Errors
+    /// * `JeromeError::General`, if the number of labels does not match the cardinality, or the
+    ///   labels are not distinct
+    pub fn set(&mut self, v: &Variable, labels: &[&str]) -> Result<()> {
+        if labels.len() != v.cardinality() {
+            return Err(
+                JeromeError::General(
+                    format!(
+                        "Error - cannot label variable with cardinality {} with {} labels", 
+                        v.cardinality(), 
+                        labels.len()
+                    )
+                )
+            );
+        }
+
+        if labels.iter().unique().count() != labels.len() {
+            return Err(JeromeError::General(String::from("Error - variable labels must be distinct")));
+        }
+
+        self.labels.insert(*v, labels.iter().map(|&l| String::from(l)).collect());
+        Ok(())
+    }
+
+    /// Give `to` the labels that `from` has in `other`, if it is labeled there. The `Variable`s
+    /// must have the same cardinality.
+    pub(crate) fn copy_from(&mut self, other: &Labels, from: &Variable, to: &Variable) {
+        debug_assert_eq!(from.cardinality(), to.cardinality());
+        if let Some(labels) = other.get(from) {
+            self.labels.insert(*to, labels.clone());
+        }
+    }
+
+    /// Add every label of `other`, replacing the labels of any `Variable` labeled in both
+    pub(crate) fn extend(&mut self, other: &Labels) {
+        self.labels.extend(other.labels.iter().map(|(&v, l)| (v, l.clone())));
+    }
+
+    /// Get the labels of a `Variable`'s values, if it is labeled
+    pub fn get(&self, v: &Variable) -> Option<&Vec<String>> {
+        self.labels.get(v)
+    }
+
+    /// Get the label of a value of a `Variable`, if it is labeled
+    pub fn label(&self, v: &Variable, value: usize) -> Option<&String> {
+        self.get(v).and_then(|labels| labels.get(value))
+    }
+
+    /// Get the value of a `Variable` with the given label
+    pub fn value_of(&self, v: &Variable, label: &str) -> Option<usize> {
+        self.get(v).and_then(|labels| labels.iter().position(|l| l == label))
+    }
+
+    /// Format a value of a `Variable`: its label if it is labeled, otherwise the value itself
+    pub fn format_value(&self, v: &Variable, value: usize) -> String {
+        self.label(v, value).cloned().unwrap_or_else(|| value.to_string())
+    }
+
+    /// Get the number of labeled `Variable`s
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// `true`, if no `Variable` is labeled
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+}
+
+
+/// A named space of `Variable`s, e.g. the `Variable`s of a single model.
+///
+/// A `VariableRegistry` hands out one `Variable` per name, and can be rebuilt from its `entries` so
+/// that a model loaded from disk sees exactly the same `Variable`s it was saved with.
+#[derive(Clone, Debug, Default)]
+pub struct VariableRegistry {
+
+    /// The registered `Variable`s, in registration order
+    variables: IndexMap<String, Variable>,
+
+    /// The state labels of the registered `Variable`s
+    labels: Labels
+
+}
+
+
+impl VariableRegistry {
+
+    /// Construct a new, empty `VariableRegistry`
+    pub fn new() -> Self {
+        VariableRegistry { variables: IndexMap::new(), labels: Labels::new() }
+    }
+
+    /// Get the `Variable` with the given name, constructing a new one if the name is not yet
+    /// registered.
+    ///
+    /// # Errors
+    /// * `JeromeError::General`, if `cardinality` is zero, or the name is registered to a
+    ///   `Variable` of a different kind or cardinality
+    pub fn variable(&mut self, name: &str, cardinality: usize) -> Result<Variable> {
+        check_kind(VariableKind::Discrete(cardinality))?;
+
+        let v = *self.variables
+                     .entry(String::from(name))
+                     .or_insert_with(|| Variable::discrete(cardinality));
+
+        if v.kind() != VariableKind::Discrete(cardinality) {
+            return Err(
+                JeromeError::General(
+                    format!(
+                        "Error - variable {} is registered as {:?}, not {:?}",
+                        name,
+                        v.kind(),
+                        VariableKind::Discrete(cardinality)
+                    )
+                )
+            );
+        }
+
+        Ok(v)
+    }
+
+    /// Register a `Variable` with a known identifier under the given name. Registering the same
+    /// name, identifier and kind twice is a no-op.
+    ///
+    /// # Errors
+    /// * `JeromeError::DuplicateVariable`, if either the name or the identifier is already
+    ///   registered to a different `Variable`
+    /// * `JeromeError::General`, if `kind` is ```VariableKind::Discrete(0)```
+    pub fn register(&mut self, name: &str, id: usize, kind: VariableKind) -> Result<Variable> {
+        check_kind(kind)?;
+
+        if let Some(&v) = self.variables.get(name) {
+            return if usize::from(v) == id && v.kind() == kind {
+                Ok(v)
+            } else {
+                Err(JeromeError::DuplicateVariable)
+            };
+        }
+
+        if self.variables.values().any(|&v| usize::from(v) == id) {
+            return Err(JeromeError::DuplicateVariable);
+        }
+
+        let v = Variable::with_id(id, kind);
+        self.variables.insert(String::from(name), v);
+        Ok(v)
+    }
+
+    /// Lookup a `Variable` by name
+    pub fn get(&self, name: &str) -> Option<&Variable> {
+        self.variables.get(name)
+    }
+
+    /// Lookup the name of a `Variable`
+    pub fn name(&self, var: &Variable) -> Option<&String> {
+        self.variables.iter().find(|&(_, v)| v == var).map(|(name, _)| name)
+    }
+
+    /// Label the values of a registered `Variable`, in order.
+    ///
+    /// # Errors
+    /// * `JeromeError::InvalidScope`, if the `Variable` is not registered
+    /// * see `Labels::set`
+    pub fn set_labels(&mut self, var: &Variable, labels: &[&str]) -> Result<()> {
+        if self.name(var).is_none() {
+            return Err(JeromeError::InvalidScope);
+        }
+
+        self.labels.set(var, labels)
+    }
+
+    /// Get the state labels of the registered `Variable`s
+    pub fn labels(&self) -> &Labels {
+        &self.labels
+    }
+
+    /// Get the registered `Variable`s, in registration order
+    pub fn variables(&self) -> Vec<Variable> {
+        self.variables.values().cloned().collect()
+    }
+
+    /// Get a record of each registered `Variable`, in registration order. Passing each entry to
+    /// `restore` on an empty `VariableRegistry` recreates this one, including state labels.
+    pub fn entries(&self) -> Vec<VariableEntry> {
+        self.variables
+            .iter()
+            .map(|(name, &v)| {
+                VariableEntry {
+                    name: name.clone(),
+                    id: usize::from(v),
+                    kind: v.kind(),
+                    labels: self.labels.get(&v).cloned()
+                }
+            })
+            .collect()
+    }
+
+    /// Recreate a `Variable` from a record produced by `entries`.
+    ///
+    /// # Errors
+    /// see `VariableRegistry::register` and `Labels::set`
+    pub fn restore(&mut self, entry: &VariableEntry) -> Result<Variable> {
+        let v = self.register(&entry.name, entry.id, entry.kind)?;
+        if let Some(ref labels) = entry.labels {
+            let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+            self.labels.set(&v, &labels)?;
+        }
+
+        Ok(v)
+    }
+
+    /// Get the number of registered `Variable`s
+    pub fn len(&self) -> usize {
+        self.variables.len()
+    }
+
+    /// `true`, if no `Variable`s are registered
+    pub fn is_empty(&self) -> bool {
+        self.variables.is_empty()
+    }
+
+}
+
+
+/// Check that a discrete `Variable` of the given kind would have at least one value
+fn check_kind(kind: VariableKind) -> Result<()> {
+    if kind == VariableKind::Discrete(0) {
+        Err(JeromeError::General(String::from("Error - a discrete variable must have at least one value")))
+    } else {
+        Ok(())
+    }
+}
+
+
+/// A record of a `Variable` registered in a `VariableRegistry`, suitable for serialization.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VariableEntry {
+
+    /// The name of the `Variable`
+    pub name: String,
+
+    /// The identifier of the `Variable`
+    pub id: usize,
+
+    /// The kind of the `Variable`
+    pub kind: VariableKind,
+
+    /// The state labels of the `Variable`, if it is labeled
+    pub labels: Option<Vec<String>>
+
+}
+
+
 /// Represents an assignment of one or more `Variable`s to values.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct Assignment {
-    assignments: HashMap<Variable, usize>
+    assignments: HashMap<Variable, usize>,
+
+    /// The values of continuous `Variable`s
+    continuous: HashMap<Variable, f64>
 }
 
 
@@ -103,7 +434,7 @@ impl Assignment {
 
     /// Construct a new, empty assignment.
     pub fn new() -> Self {
-        Assignment { assignments: HashMap::new() }
+        Assignment { assignments: HashMap::new(), continuous: HashMap::new() }
     }
 
     /// Add an assignment to a variable.
@@ -128,20 +459,350 @@ impl Assignment {
 
     pub fn unset(&mut self, v: &Variable) {
         self.assignments.remove(v);
+        self.continuous.remove(v);
     }
 
     pub fn get(&self, v: &Variable) -> Option<&usize> {
         self.assignments.get(v)
     }
 
+    /// Add an assignment to a continuous variable.
+    pub fn set_continuous(&mut self, v: &Variable, value: f64) -> Option<JeromeError> {
+        if let Some(e) = check_continuous(v, value) {
+            return Some(e);
+        }
+
+        self.continuous.insert(*v, value);
+        None
+    }
+
+    pub fn get_continuous(&self, v: &Variable) -> Option<f64> {
+        self.continuous.get(v).cloned()
+    }
+
+    /// Add an assignment to a variable by the label of the value, as given by `labels`.
+    pub fn set_label(&mut self, labels: &Labels, v: &Variable, label: &str) -> Option<JeromeError> {
+        match labels.value_of(v, label) {
+            Some(value) => self.set(v, value),
+            None => {
+                Some(
+                    JeromeError::General(
+                        format!("Error - variable {} has no value labeled {}", v, label)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Get the label of the value assigned to a variable, as given by `labels`. `None` if the
+    /// variable is unassigned or not labeled.
+    pub fn get_label<'a>(&self, labels: &'a Labels, v: &Variable) -> Option<&'a String> {
+        self.get(v).and_then(|&value| labels.label(v, value))
+    }
+
+}
+
+/// Read access to the values assigned to `Variable`s. This is implemented by both `Assignment` and
+/// `DenseAssignment`, so that operations such as `Factor::value` accept either representation.
+pub trait VariableAssignment {
+
+    /// Get the value assigned to the discrete `Variable`, if any
+    fn value_of(&self, v: &Variable) -> Option<usize>;
+
+    /// Get the value assigned to the continuous `Variable`, if any
+    fn continuous_value_of(&self, v: &Variable) -> Option<f64>;
+
+}
+
+impl VariableAssignment for Assignment {
+
+    fn value_of(&self, v: &Variable) -> Option<usize> {
+        self.get(v).cloned()
+    }
+
+    fn continuous_value_of(&self, v: &Variable) -> Option<f64> {
+        self.get_continuous(v)
+    }
+
+}
+
+
+/// Utility function to validate the assignment of a value to a continuous `Variable`
+fn check_continuous(v: &Variable, value: f64) -> Option<JeromeError> {
+    if ! v.is_continuous() {
+        Some(JeromeError::General(format!("Error - variable {} is not continuous", v)))
+    } else if ! value.is_finite() {
+        Some(JeromeError::General(format!("Error - cannot assign variable {} a value of {}", v, value)))
+    } else {
+        None
+    }
+}
+
+
+/// A fixed ordering of `Variable`s, mapping each `Variable` to a position in `[0, len)`. This is
+/// the ordering against which `DenseAssignment`s are laid out.
+#[derive(Clone, Debug, Default)]
+pub struct VariableIndex {
+
+    /// The `Variable`s, in order
+    variables: Vec<Variable>,
+
+    /// The position of each `Variable` in `variables`
+    positions: HashMap<Variable, usize>,
+
+    /// `true`, if any of the `Variable`s are continuous
+    continuous: bool
+
 }
 
+
+impl VariableIndex {
+
+    /// Construct a new `VariableIndex` over the given `Variable`s. Duplicate `Variable`s are
+    /// ignored after their first occurrence.
+    pub fn new(variables: &[Variable]) -> Self {
+        let mut index = VariableIndex::default();
+        for &v in variables {
+            if ! index.positions.contains_key(&v) {
+                index.positions.insert(v, index.variables.len());
+                index.variables.push(v);
+                index.continuous |= v.is_continuous();
+            }
+        }
+
+        index
+    }
+
+    /// Get the position of the `Variable` in the ordering
+    pub fn position(&self, v: &Variable) -> Option<usize> {
+        self.positions.get(v).cloned()
+    }
+
+    /// Get the `Variable`s in the ordering
+    pub fn variables(&self) -> &[Variable] {
+        &self.variables
+    }
+
+    /// Get the number of `Variable`s in the ordering
+    pub fn len(&self) -> usize {
+        self.variables.len()
+    }
+
+    /// `true`, if the ordering contains no `Variable`s
+    pub fn is_empty(&self) -> bool {
+        self.variables.is_empty()
+    }
+
+    /// `true`, if any of the `Variable`s in the ordering are continuous
+    pub fn has_continuous(&self) -> bool {
+        self.continuous
+    }
+
+}
+
+
+/// A compact assignment of `Variable`s to values, stored as one slot per position in a shared
+/// `VariableIndex`.
+///
+/// # Notes
+/// Unlike `Assignment`, cloning a `DenseAssignment` does not rehash its contents. This makes it the
+/// preferred representation for the inner loops of sampling and estimation. Use `From` to convert
+/// to an `Assignment` and `DenseAssignment::from_assignment` to convert back.
+#[derive(Clone, Debug)]
+pub struct DenseAssignment {
+
+    /// The ordering of the `Variable`s
+    index: Arc<VariableIndex>,
+
+    /// The value assigned to the `Variable` at each position, if any
+    values: Vec<Option<u32>>,
+
+    /// The value assigned to the continuous `Variable` at each position, if any. Empty unless the
+    /// `VariableIndex` has continuous `Variable`s.
+    reals: Vec<Option<f64>>
+
+}
+
+
+impl DenseAssignment {
+
+    /// Construct a new, empty `DenseAssignment` over the `VariableIndex`
+    pub fn new(index: &Arc<VariableIndex>) -> Self {
+        let reals = if index.has_continuous() { vec![None; index.len()] } else { Vec::new() };
+        DenseAssignment { index: Arc::clone(index), values: vec![None; index.len()], reals }
+    }
+
+    /// Construct a `DenseAssignment` from any other assignment. `Variable`s that are not in the
+    /// `VariableIndex` are dropped.
+    pub fn from_assignment<A: VariableAssignment>(index: &Arc<VariableIndex>, assignment: &A) -> Self {
+        let mut dense = DenseAssignment::new(index);
+        for (pos, v) in index.variables().iter().enumerate() {
+            if v.is_continuous() {
+                dense.reals[pos] = assignment.continuous_value_of(v);
+            } else {
+                dense.values[pos] = assignment.value_of(v).map(|val| val as u32);
+            }
+        }
+
+        dense
+    }
+
+    /// Get the `VariableIndex` this assignment is laid out against
+    pub fn index(&self) -> &Arc<VariableIndex> {
+        &self.index
+    }
+
+    /// Add an assignment to a variable.
+    pub fn set(&mut self, v: &Variable, value: usize) -> Option<JeromeError> {
+        match self.index.position(v) {
+            Some(_) if value >= v.cardinality() => {
+                Some(
+                    JeromeError::General(
+                        format!(
+                            "Error - cannot assign variable with cardinality {} a value of {}", 
+                            v.cardinality(), 
+                            value
+                        )
+                    )
+                )
+            },
+            Some(pos) => {
+                self.values[pos] = Some(value as u32);
+                None
+            },
+            None => Some(JeromeError::InvalidScope)
+        }
+    }
+
+    pub fn unset(&mut self, v: &Variable) {
+        if let Some(pos) = self.index.position(v) {
+            self.unset_at(pos);
+        }
+    }
+
+    pub fn get(&self, v: &Variable) -> Option<usize> {
+        self.index.position(v).and_then(|pos| self.get_at(pos))
+    }
+
+    /// Add an assignment to a continuous variable.
+    pub fn set_continuous(&mut self, v: &Variable, value: f64) -> Option<JeromeError> {
+        if let Some(e) = check_continuous(v, value) {
+            return Some(e);
+        }
+
+        match self.index.position(v) {
+            Some(pos) => {
+                self.reals[pos] = Some(value);
+                None
+            },
+            None => Some(JeromeError::InvalidScope)
+        }
+    }
+
+    pub fn get_continuous(&self, v: &Variable) -> Option<f64> {
+        self.index.position(v).and_then(|pos| self.get_continuous_at(pos))
+    }
+
+    /// Set the value of the `Variable` at a position in the `VariableIndex`.
+    ///
+    /// # Panics
+    /// if the position is out of range
+    pub fn set_at(&mut self, position: usize, value: usize) {
+        debug_assert!(value < self.index.variables()[position].cardinality());
+        self.values[position] = Some(value as u32);
+    }
+
+    /// Set the value of the continuous `Variable` at a position in the `VariableIndex`.
+    ///
+    /// # Panics
+    /// if the position is out of range
+    pub fn set_continuous_at(&mut self, position: usize, value: f64) {
+        debug_assert!(self.index.variables()[position].is_continuous());
+        self.reals[position] = Some(value);
+    }
+
+    /// Unset the value of the `Variable` at a position in the `VariableIndex`.
+    pub fn unset_at(&mut self, position: usize) {
+        self.values[position] = None;
+        if let Some(val) = self.reals.get_mut(position) {
+            *val = None;
+        }
+    }
+
+    /// Get the value of the `Variable` at a position in the `VariableIndex`.
+    pub fn get_at(&self, position: usize) -> Option<usize> {
+        self.values.get(position).and_then(|val| val.map(|v| v as usize))
+    }
+
+    /// Get the value of the continuous `Variable` at a position in the `VariableIndex`.
+    pub fn get_continuous_at(&self, position: usize) -> Option<f64> {
+        self.reals.get(position).and_then(|&val| val)
+    }
+
+    /// `true`, if every `Variable` in the `VariableIndex` is assigned
+    pub fn is_complete(&self) -> bool {
+        self.index.variables()
+                  .iter()
+                  .enumerate()
+                  .all(|(pos, v)| if v.is_continuous() { self.reals[pos].is_some() } else { self.values[pos].is_some() })
+    }
+
+}
+
+
+impl VariableAssignment for DenseAssignment {
+
+    fn value_of(&self, v: &Variable) -> Option<usize> {
+        self.get(v)
+    }
+
+    fn continuous_value_of(&self, v: &Variable) -> Option<f64> {
+        self.get_continuous(v)
+    }
+
+}
+
+
+impl<'a> From<&'a DenseAssignment> for Assignment {
+
+    fn from(dense: &'a DenseAssignment) -> Assignment {
+        let mut assn = Assignment::new();
+        for (pos, v) in dense.index.variables().iter().enumerate() {
+            if let Some(val) = dense.get_at(pos) {
+                assn.set(v, val);
+            } else if let Some(val) = dense.get_continuous_at(pos) {
+                assn.set_continuous(v, val);
+            }
+        }
+
+        assn
+    }
+
+}
+
+
 /// An Iterator over all possible `Assignment`s of a set of variables
 pub struct AssignmentIter<'a>(&'a Vec<Variable>, MultiProduct<Range<usize>>);
 
 
+/// Replace each `Variable` in `vars` by its image in `map`, if it has one
+///
+/// # Errors
+/// `JeromeError::InvalidScope` if an image has a different cardinality than its `Variable`, or
+/// the resulting `Variable`s are not distinct
+pub(crate) fn relabel(vars: &[Variable], map: &HashMap<Variable, Variable>) -> Result<Vec<Variable>> {
+    let relabeled: Vec<Variable> = vars.iter().map(|v| *map.get(v).unwrap_or(v)).collect();
+
+    if vars.iter().zip(relabeled.iter()).any(|(v, r)| v.cardinality() != r.cardinality())
+        || relabeled.iter().unique().count() != relabeled.len() {
+        return Err(JeromeError::InvalidScope);
+    }
+
+    Ok(relabeled)
+}
+
 /// Utility function for `AssignmentIter`.
-fn to_assn(vars: &Vec<Variable>, vals: &Vec<usize>) -> Assignment {
+fn to_assn(vars: &[Variable], vals: &[usize]) -> Assignment {
     let mut assn = Assignment::new();
     for (var, &value) in vars.iter().zip(vals.iter()) {
         // Note that an error here should be impossible if invariants are
@@ -156,13 +817,13 @@ impl<'a> Iterator for AssignmentIter<'a> {
     type Item = Assignment;
 
     fn next(&mut self) -> Option<Self::Item> {
-        return self.1.next().map(|vals| to_assn(&self.0, &vals));
+        self.1.next().map(|vals| to_assn(self.0, &vals))
     }
 }
 
 
 /// Create an `AssignmentIter`.
-pub fn all_assignments(vars: &Vec<Variable>) -> AssignmentIter { 
+pub fn all_assignments(vars: &Vec<Variable>) -> AssignmentIter<'_> { 
     let vals = vars.iter()
                    .map(|v| 0..(v.cardinality()))
                    .multi_cartesian_product();
@@ -172,6 +833,7 @@ pub fn all_assignments(vars: &Vec<Variable>) -> AssignmentIter {
 
 // Unit Tests for the Variable struct.
 #[cfg(test)]
+#[allow(clippy::redundant_pattern_matching)]
 mod tests {
 
     use super::*;
@@ -245,5 +907,178 @@ mod tests {
             assert_eq!(i % 2, *assn.get(&b).expect("Missing assignment")); 
         }
     }
-}
 
+    #[test]
+    fn dense_assignment() {
+        let a = Variable::binary();
+        let b = Variable::discrete(3);
+        let c = Variable::binary();
+        let index = Arc::new(VariableIndex::new(&[a, b, a]));
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.position(&b), Some(1));
+        assert_eq!(index.position(&c), None);
+
+        let mut dense = DenseAssignment::new(&index);
+        assert!(! dense.is_complete());
+        assert!(dense.set(&b, 3).is_some());
+        assert!(dense.set(&c, 0).is_some());
+        assert!(dense.set(&b, 2).is_none());
+        dense.set_at(0, 1);
+        assert!(dense.is_complete());
+        assert_eq!(dense.get(&a), Some(1));
+        assert_eq!(dense.value_of(&b), Some(2));
+
+        let assn = Assignment::from(&dense);
+        assert_eq!(assn.get(&a), Some(&1));
+        assert_eq!(assn.get(&b), Some(&2));
+
+        let mut partial = assn.clone();
+        partial.unset(&a);
+        partial.set(&c, 1);
+        let round_trip = DenseAssignment::from_assignment(&index, &partial);
+        assert_eq!(round_trip.get(&a), None);
+        assert_eq!(round_trip.get(&b), Some(2));
+        assert_eq!(round_trip.get(&c), None);
+
+        dense.unset(&b);
+        assert_eq!(dense.get_at(1), None);
+        assert_eq!(Assignment::from(&dense).get(&b), None);
+
+        // continuous variables live alongside discrete ones
+        let x = Variable::continuous();
+        let index = Arc::new(VariableIndex::new(&[a, x]));
+        assert!(index.has_continuous());
+
+        let mut mixed = Assignment::new();
+        mixed.set(&a, 1);
+        assert!(mixed.set_continuous(&x, f64::NAN).is_some());
+        assert!(mixed.set_continuous(&a, 0.5).is_some());
+        assert!(mixed.set(&x, 0).is_some());
+        assert!(mixed.set_continuous(&x, -2.5).is_none());
+
+        let dense = DenseAssignment::from_assignment(&index, &mixed);
+        assert!(dense.is_complete());
+        assert_eq!(dense.get_continuous(&x), Some(-2.5));
+        assert_eq!(Assignment::from(&dense).get_continuous(&x), Some(-2.5));
+
+        mixed.unset(&x);
+        assert_eq!(mixed.get_continuous(&x), None);
+    }
+
+    #[test]
+    fn unique_across_threads() {
+        let handles: Vec<_> = (0..8).map(|_| {
+                                        std::thread::spawn(|| {
+                                            (0..1000).map(|_| usize::from(Variable::binary()))
+                                                     .collect::<Vec<usize>>()
+                                        })
+                                    })
+                                    .collect();
+
+        let ids: Vec<usize> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+        let unique: std::collections::HashSet<&usize> = ids.iter().collect();
+        assert_eq!(ids.len(), unique.len());
+    }
+
+    #[test]
+    fn kinds() {
+        let v = Variable::discrete(3);
+        assert_eq!(VariableKind::Discrete(3), v.kind());
+        assert!(! v.is_continuous());
+
+        let x = Variable::continuous();
+        assert_eq!(VariableKind::Continuous, x.kind());
+        assert!(x.is_continuous());
+        assert_eq!(0, x.cardinality());
+
+        // a discrete cardinality of zero is rejected, rather than taken to mean continuous
+        let mut registry = VariableRegistry::new();
+        assert!(registry.variable("Z", 0).is_err());
+        assert!(registry.register("Z", usize::from(x) + 100, VariableKind::Discrete(0)).is_err());
+        assert!(registry.is_empty());
+
+        // continuous variables survive a round trip through the registry
+        registry.register("X", usize::from(x), VariableKind::Continuous).unwrap();
+        assert!(registry.variable("X", 2).is_err());
+        let entry = registry.entries().pop().unwrap();
+        let restored = VariableRegistry::new().restore(&entry).unwrap();
+        assert_eq!(x, restored);
+        assert!(restored.is_continuous());
+    }
+
+    #[test]
+    #[should_panic]
+    fn discrete_zero() {
+        Variable::discrete(0);
+    }
+
+    #[test]
+    fn registry() {
+        let mut registry = VariableRegistry::new();
+        let a = registry.variable("A", 2).unwrap();
+        let b = registry.variable("B", 3).unwrap();
+        assert_eq!(a, registry.variable("A", 2).unwrap());
+        assert!(registry.variable("A", 3).is_err());
+        assert_eq!(registry.get("B"), Some(&b));
+        assert_eq!(registry.name(&a).unwrap(), "A");
+        assert_eq!(registry.variables(), vec![a, b]);
+
+        // recreating from the entries yields identical variables, and fresh ones don't collide
+        let mut loaded = VariableRegistry::new();
+        for entry in registry.entries() {
+            loaded.restore(&entry).unwrap();
+        }
+
+        assert_eq!(loaded.variables(), vec![a, b]);
+        assert!(loaded.register("A", usize::from(a), VariableKind::Discrete(2)).is_ok());
+        assert!(loaded.register("A", usize::from(b), VariableKind::Discrete(3)).is_err());
+        assert!(loaded.register("C", usize::from(b), VariableKind::Discrete(3)).is_err());
+
+        let id = usize::from(b) + 100;
+        let c = loaded.register("C", id, VariableKind::Discrete(2)).unwrap();
+        assert_eq!(usize::from(c), id);
+        assert!(usize::from(Variable::binary()) > id);
+    }
+
+    #[test]
+    fn labels() {
+        let v = Variable::discrete(3);
+        let mut labels = Labels::new();
+        labels.set(&v, &["low", "medium", "high"]).unwrap();
+        assert_eq!(labels.label(&v, 2).unwrap(), "high");
+        assert_eq!(labels.value_of(&v, "medium"), Some(1));
+        assert_eq!(labels.format_value(&v, 0), "low");
+        assert!(labels.set(&v, &["a", "a", "b"]).is_err());
+
+        let unlabeled = Variable::binary();
+        assert_eq!(labels.get(&unlabeled), None);
+        assert_eq!(labels.format_value(&unlabeled, 1), "1");
+        assert!(labels.set(&unlabeled, &["yes"]).is_err());
+
+        let mut assn = Assignment::new();
+        assert!(assn.set_label(&labels, &v, "high").is_none());
+        assert!(assn.set_label(&labels, &v, "extreme").is_some());
+        assert!(assn.set_label(&labels, &unlabeled, "high").is_some());
+        assert_eq!(assn.get(&v), Some(&2));
+        assert_eq!(assn.get_label(&labels, &v).unwrap(), "high");
+
+        // labels survive a round trip through the registry
+        let mut registry = VariableRegistry::new();
+        registry.register("V", usize::from(v), VariableKind::Discrete(3)).unwrap();
+        assert!(registry.set_labels(&unlabeled, &["no", "yes"]).is_err());
+        registry.set_labels(&v, &["low", "medium", "high"]).unwrap();
+        let entry = registry.entries().pop().unwrap();
+        assert_eq!(entry.labels, Some(vec![String::from("low"), String::from("medium"), String::from("high")]));
+
+        let mut loaded = VariableRegistry::new();
+        let restored = loaded.restore(&entry).unwrap();
+        assert_eq!(restored, v);
+        assert_eq!(loaded.labels().label(&restored, 1).unwrap(), "medium");
+
+        // labels belong to their registry, not to the identifier
+        let mut other = VariableRegistry::new();
+        other.register("V", usize::from(v), VariableKind::Discrete(3)).unwrap();
+        assert_eq!(other.labels().get(&v), None);
+        assert_eq!(other.entries().pop().unwrap().labels, None);
+    }
+}